@@ -0,0 +1,145 @@
+use javelin::{Board, MoveHistory, MoveList, MoveProvider};
+
+#[derive(Clone, Copy)]
+pub struct AdjudicationSettings {
+    pub resign_score: f32,
+    pub resign_plies: u32,
+    pub draw_move: u32,
+    pub draw_margin: f32,
+    pub draw_plies: u32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum GameEnd {
+    Checkmate,
+    Stalemate,
+    InsufficientMaterial,
+    ThreeFold,
+    FiftyMoves,
+    Resign,
+    DrawAdjudication,
+}
+
+impl GameEnd {
+    pub const COUNT: usize = 7;
+    pub const ALL: [GameEnd; GameEnd::COUNT] = [
+        GameEnd::Checkmate,
+        GameEnd::Stalemate,
+        GameEnd::InsufficientMaterial,
+        GameEnd::ThreeFold,
+        GameEnd::FiftyMoves,
+        GameEnd::Resign,
+        GameEnd::DrawAdjudication,
+    ];
+
+    #[inline]
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameEnd::Checkmate => "checkmate",
+            GameEnd::Stalemate => "stalemate",
+            GameEnd::InsufficientMaterial => "insufficient_material",
+            GameEnd::ThreeFold => "threefold",
+            GameEnd::FiftyMoves => "fifty_moves",
+            GameEnd::Resign => "resign",
+            GameEnd::DrawAdjudication => "draw_adjudication",
+        }
+    }
+}
+
+//Ends games early once both sides agree on the outcome. Resign is adjudicated when the score
+//stays past the threshold in favour of the same side for enough consecutive plies, draw when
+//the score stays close to 0.5 after given move of the game. Zero plies disable the rule
+pub struct Adjudicator {
+    settings: AdjudicationSettings,
+    ply: u32,
+    resign_count: u32,
+    resign_winner: i8,
+    draw_count: u32,
+}
+
+impl Adjudicator {
+    pub fn new(settings: AdjudicationSettings) -> Self {
+        Self { settings, ply: 0, resign_count: 0, resign_winner: 0, draw_count: 0 }
+    }
+
+    pub fn reset(&mut self) {
+        self.ply = 0;
+        self.resign_count = 0;
+        self.resign_winner = 0;
+        self.draw_count = 0;
+    }
+
+    //Takes side to move expected score of the root and returns adjudicated end of the game
+    //together with the result from white perspective
+    pub fn update(&mut self, score: f32, side_to_move: u8) -> Option<(GameEnd, i8)> {
+        self.ply += 1;
+        let white_score = if side_to_move == 0 { score } else { 1.0 - score };
+
+        let winner = if white_score >= self.settings.resign_score {
+            1
+        } else if white_score <= 1.0 - self.settings.resign_score {
+            -1
+        } else {
+            0
+        };
+
+        self.resign_count = match winner {
+            0 => 0,
+            _ if winner == self.resign_winner => self.resign_count + 1,
+            _ => 1,
+        };
+        self.resign_winner = winner;
+
+        if self.settings.resign_plies > 0 && self.resign_count >= self.settings.resign_plies {
+            return Some((GameEnd::Resign, winner));
+        }
+
+        if (score - 0.5).abs() <= self.settings.draw_margin {
+            self.draw_count += 1;
+        } else {
+            self.draw_count = 0;
+        }
+
+        if self.settings.draw_plies > 0
+            && self.ply >= self.settings.draw_move * 2
+            && self.draw_count >= self.settings.draw_plies
+        {
+            return Some((GameEnd::DrawAdjudication, 0));
+        }
+
+        None
+    }
+}
+
+//Checks if the game is over in given position, returns how it ended together with the result
+//from white perspective. History holds positions of the game played before this one
+pub fn check_game_end(board: &Board, history: &MoveHistory) -> Option<(GameEnd, i8)> {
+    if board.is_insufficient_material() {
+        return Some((GameEnd::InsufficientMaterial, 0));
+    }
+
+    if board.three_fold(history) {
+        return Some((GameEnd::ThreeFold, 0));
+    }
+
+    if board.half_moves >= 100 {
+        return Some((GameEnd::FiftyMoves, 0));
+    }
+
+    let mut move_list = MoveList::new();
+    MoveProvider::generate_moves::<false>(&mut move_list, board);
+    if !move_list.is_empty() {
+        return None;
+    }
+
+    if board.is_in_check() {
+        let result = if board.side_to_move.current() == 0 { -1 } else { 1 };
+        Some((GameEnd::Checkmate, result))
+    } else {
+        Some((GameEnd::Stalemate, 0))
+    }
+}
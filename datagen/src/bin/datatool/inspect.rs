@@ -0,0 +1,86 @@
+use datagen::{ChessMoveInfo, ChessPolicyData, GameMove, PieceBoard};
+use javelin::Move;
+
+use crate::records::{board_fen, Args, DataKind, GameReader, RecordReader, ToolResult};
+
+//inspect <file> [--count n] [--skip n] [--kind k]
+pub fn run(args: &Args) -> ToolResult {
+    let path = args.path(0, "file")?;
+    let kind = args.kind(path)?;
+    let count = args.get::<usize>("count", 10)?;
+    let skip = args.get::<usize>("skip", 0)?;
+
+    match kind {
+        DataKind::Value => {
+            for (index, record) in RecordReader::<PieceBoard>::open(path)?.enumerate().skip(skip).take(count) {
+                print_value(index, &record);
+            }
+        }
+        DataKind::Policy => {
+            for (index, record) in RecordReader::<ChessPolicyData>::open(path)?.enumerate().skip(skip).take(count) {
+                print_value(index, &record.board);
+                println!("    policy: {}", policy_string(&record.moves[..record.board.num.min(104) as usize]));
+            }
+        }
+        DataKind::Games => {
+            for (index, game) in GameReader::open(path)?.enumerate().skip(skip).take(count) {
+                let game = game?;
+                println!(
+                    "game {index}: {} | result {} | {} moves",
+                    game.start_board().get_fen(),
+                    game.header.result,
+                    game.moves.len()
+                );
+
+                for (ply, (entry, policy)) in game.moves.iter().enumerate() {
+                    let filtered = match entry.flags & (GameMove::VALUE_FILTERED | GameMove::POLICY_FILTERED) {
+                        0 => "",
+                        GameMove::VALUE_FILTERED => " (value filtered)",
+                        GameMove::POLICY_FILTERED => " (policy filtered)",
+                        _ => " (filtered)",
+                    };
+
+                    println!(
+                        "  {ply:>3}. {} score {:.4} moves {}{filtered}",
+                        Move::from_raw(entry.mv).to_string(),
+                        f32::from(entry.score) / f32::from(u16::MAX),
+                        entry.num
+                    );
+
+                    if !policy.is_empty() {
+                        println!("       policy: {}", policy_string(policy));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_value(index: usize, record: &PieceBoard) {
+    println!(
+        "{index}: {} | score {:.4} | result {} | moves {}",
+        board_fen(record),
+        record.score,
+        record.result,
+        record.num
+    );
+}
+
+//Moves sorted by visits, only the most visited ones are shown
+fn policy_string(moves: &[ChessMoveInfo]) -> String {
+    let mut moves = moves.to_vec();
+    moves.sort_by(|a, b| b.visits.cmp(&a.visits));
+
+    let mut result = moves
+        .iter()
+        .take(8)
+        .map(|info| format!("{}:{}", Move::from_raw(info.mv).to_string(), info.visits))
+        .collect::<Vec<String>>();
+    if moves.len() > 8 {
+        result.push(format!("(+{} more)", moves.len() - 8));
+    }
+
+    result.join(" ")
+}
@@ -0,0 +1,52 @@
+use records::Args;
+use std::env;
+
+mod inspect;
+mod records;
+mod shuffle;
+mod stats;
+mod transform;
+
+const HELP: &str = "Usage: datatool <command> [arguments] [options]
+
+Commands:
+  inspect <file> [--count n] [--skip n]              Print records as FEN with score, result and policy
+  stats <files...>                                   WDL ratio, score and piece count histograms, duplicate rate
+  shuffle <input> <output> [--memory MB] [--seed n]  Shuffle a value or policy file using limited memory
+  dedup <input> <output>                             Remove repeated positions, keeping the first one
+  merge <output> <inputs...>                         Concatenate files of the same kind
+  split <input> <train> <validation> [--ratio r] [--seed n]
+                                                     Split records (or whole games) into train and validation
+  convert <input> <output> --to <value|policy|text>  Expand games, reduce policy to value or write text lines
+
+Every command accepts --kind <value|policy|games>. By default the kind is detected from the file name:
+files starting with `games` are game records, files containing `policy` are policy records, the rest are value
+records. Seed 0 (default) uses a random seed.";
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let Some(command) = args.first() else {
+        println!("{HELP}");
+        return;
+    };
+
+    let result = Args::parse(&args[1..]).map_err(|err| err.into()).and_then(|args| match command.as_str() {
+        "inspect" => inspect::run(&args),
+        "stats" => stats::run(&args),
+        "shuffle" => shuffle::run(&args),
+        "dedup" => transform::dedup(&args),
+        "merge" => transform::merge(&args),
+        "split" => transform::split(&args),
+        "convert" => transform::convert(&args),
+        "help" | "--help" | "-h" => {
+            println!("{HELP}");
+            Ok(())
+        }
+        _ => Err(format!("Unknown command '{command}'\n\n{HELP}").into()),
+    });
+
+    if let Err(err) = result {
+        eprintln!("{err}");
+        std::process::exit(1);
+    }
+}
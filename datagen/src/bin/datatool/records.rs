@@ -0,0 +1,264 @@
+use bytemuck::Pod;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::str::FromStr;
+
+use datagen::{ChessPolicyData, GameRecord, PieceBoard};
+use javelin::Board;
+
+pub type ToolResult = Result<(), Box<dyn Error>>;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DataKind {
+    Value,
+    Policy,
+    Games,
+}
+
+impl DataKind {
+    //Kind is taken from the file name, the same way datagen names its files
+    pub fn detect(path: &Path) -> Self {
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        if name.starts_with("games") {
+            DataKind::Games
+        } else if name.contains("policy") {
+            DataKind::Policy
+        } else {
+            DataKind::Value
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "value" => Ok(DataKind::Value),
+            "policy" => Ok(DataKind::Policy),
+            "games" => Ok(DataKind::Games),
+            _ => Err(format!("Unknown data kind '{value}'")),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataKind::Value => "value",
+            DataKind::Policy => "policy",
+            DataKind::Games => "games",
+        }
+    }
+
+    //Games have variable length, so they have no record size
+    pub fn record_size(&self) -> Option<usize> {
+        match self {
+            DataKind::Value => Some(std::mem::size_of::<PieceBoard>()),
+            DataKind::Policy => Some(std::mem::size_of::<ChessPolicyData>()),
+            DataKind::Games => None,
+        }
+    }
+}
+
+//Command line arguments after the subcommand. Every `--key` takes a value, the rest is positional
+pub struct Args {
+    pub positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+
+        let mut index = 0;
+        while index < args.len() {
+            if let Some(key) = args[index].strip_prefix("--") {
+                let value = args.get(index + 1).ok_or(format!("Missing value for --{key}"))?;
+                options.insert(key.to_string(), value.clone());
+                index += 1;
+            } else {
+                positional.push(args[index].clone());
+            }
+
+            index += 1;
+        }
+
+        Ok(Self { positional, options })
+    }
+
+    pub fn get<T: FromStr>(&self, key: &str, default: T) -> Result<T, String> {
+        match self.options.get(key) {
+            Some(value) => value.parse::<T>().map_err(|_| format!("Invalid value '{value}' for --{key}")),
+            None => Ok(default),
+        }
+    }
+
+    pub fn path(&self, index: usize, name: &str) -> Result<&Path, String> {
+        self.positional.get(index).map(Path::new).ok_or(format!("Missing <{name}> argument"))
+    }
+
+    //Data kind given with --kind, or detected from the file name
+    pub fn kind(&self, path: &Path) -> Result<DataKind, String> {
+        match self.options.get("kind") {
+            Some(kind) => DataKind::parse(kind),
+            None => Ok(DataKind::detect(path)),
+        }
+    }
+}
+
+//Streams fixed size records from a file. Incomplete record at the end of the file is ignored
+pub struct RecordReader<T: Pod> {
+    reader: BufReader<File>,
+    buffer: Vec<u8>,
+    _data: PhantomData<T>,
+}
+
+impl<T: Pod> RecordReader<T> {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(File::open(path)?),
+            buffer: vec![0; std::mem::size_of::<T>()],
+            _data: PhantomData,
+        })
+    }
+}
+
+impl<T: Pod> Iterator for RecordReader<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader.read_exact(&mut self.buffer).ok()?;
+        Some(bytemuck::pod_read_unaligned(&self.buffer))
+    }
+}
+
+//Streams raw records of given size, used by commands that don't need to look inside the records
+pub struct RawReader {
+    reader: BufReader<File>,
+    record_size: usize,
+}
+
+impl RawReader {
+    pub fn open(path: &Path, record_size: usize) -> io::Result<Self> {
+        Ok(Self { reader: BufReader::new(File::open(path)?), record_size })
+    }
+
+    pub fn next_record(&mut self, buffer: &mut Vec<u8>) -> bool {
+        buffer.resize(self.record_size, 0);
+        self.reader.read_exact(buffer).is_ok()
+    }
+}
+
+//Streams games from a file, game cut off at the end of the file is ignored
+pub struct GameReader {
+    reader: BufReader<File>,
+}
+
+impl GameReader {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self { reader: BufReader::new(File::open(path)?) })
+    }
+}
+
+impl Iterator for GameReader {
+    type Item = io::Result<GameRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match GameRecord::read_from(&mut self.reader) {
+            Ok(Some(game)) => Some(Ok(game)),
+            Ok(None) => None,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+pub fn create_writer(path: &Path) -> io::Result<BufWriter<File>> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            std::fs::create_dir_all(parent)?;
+        }
+    }
+
+    Ok(BufWriter::new(File::create(path)?))
+}
+
+#[inline]
+pub fn write_record<T: Pod, W: Write>(writer: &mut W, record: &T) -> io::Result<()> {
+    writer.write_all(bytemuck::bytes_of(record))
+}
+
+//Calls the function for value view of every record: boards of policy records and expanded
+//positions of games
+pub fn for_each_value<F: FnMut(&PieceBoard)>(path: &Path, kind: DataKind, mut function: F) -> io::Result<()> {
+    match kind {
+        DataKind::Value => RecordReader::<PieceBoard>::open(path)?.for_each(|record| function(&record)),
+        DataKind::Policy => RecordReader::<ChessPolicyData>::open(path)?.for_each(|record| function(&record.board)),
+        DataKind::Games => {
+            for game in GameReader::open(path)? {
+                game?.expand().0.iter().for_each(&mut function);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn for_each_policy<F: FnMut(&ChessPolicyData)>(path: &Path, kind: DataKind, mut function: F) -> ToolResult {
+    match kind {
+        DataKind::Value => return Err("Value data has no policy".into()),
+        DataKind::Policy => RecordReader::<ChessPolicyData>::open(path)?.for_each(|record| function(&record)),
+        DataKind::Games => {
+            for game in GameReader::open(path)? {
+                game?.expand().1.iter().for_each(&mut function);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+//Fen of the packed board. Castle rights and en passant square are not stored in the records
+pub fn board_fen(piece_board: &PieceBoard) -> String {
+    Board::from_datapack(&piece_board.to_bitboards(), piece_board.side_to_move).get_fen()
+}
+
+//Zobrist key of the packed position, the same key the engine and the duplicate filter of datagen use
+pub fn position_key(piece_board: &PieceBoard) -> u64 {
+    Board::from_datapack(&piece_board.to_bitboards(), piece_board.side_to_move).zobrist.key
+}
+
+#[inline]
+pub fn piece_count(piece_board: &PieceBoard) -> u32 {
+    piece_board.piece_boards[0].or(piece_board.piece_boards[1]).or(piece_board.piece_boards[2]).pop_count()
+}
+
+#[cfg(test)]
+mod tests {
+    use javelin::create_board;
+
+    use super::*;
+
+    const FENS: [&str; 4] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR b - - 0 1",
+        "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b - - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w - - 0 1",
+    ];
+
+    #[test]
+    fn position_key_matches_engine_key() {
+        for fen in FENS {
+            let board = create_board(fen);
+            assert_eq!(position_key(&PieceBoard::from_board(&board)), board.zobrist.key, "{fen}");
+        }
+    }
+
+    #[test]
+    fn different_positions_have_different_keys() {
+        let keys = FENS.map(|fen| position_key(&PieceBoard::from_board(&create_board(fen))));
+        for (index, key) in keys.iter().enumerate() {
+            assert!(!keys[index + 1..].contains(key), "{} shares key with a later position", FENS[index]);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::records::{create_writer, Args, RawReader, ToolResult};
+
+//shuffle <input> <output> [--memory MB] [--seed n] [--kind k]
+//External memory shuffle: records are scattered into random buckets on disk, small enough to
+//fit into the memory limit, then every bucket is shuffled in memory and appended to the output
+pub fn run(args: &Args) -> ToolResult {
+    let input = args.path(0, "input")?;
+    let output = args.path(1, "output")?;
+    let kind = args.kind(input)?;
+    let memory = args.get::<u64>("memory", 1024)?.max(1) * 1024 * 1024;
+    let mut rng = create_rng(args)?;
+
+    let record_size = kind.record_size().ok_or("Games can't be shuffled, convert them to value or policy first")?;
+    let input_size = fs::metadata(input)?.len();
+    //Buckets are filled randomly, so they are planned at half of the limit to leave room for uneven sizes
+    let bucket_count = input_size.div_ceil(memory / 2).max(1) as usize;
+
+    let temp_directory = temp_directory(output);
+    fs::create_dir_all(&temp_directory)?;
+
+    let bucket_paths =
+        (0..bucket_count).map(|index| temp_directory.join(format!("bucket_{index:04}.data"))).collect::<Vec<_>>();
+    let mut buckets = Vec::with_capacity(bucket_count);
+    for path in &bucket_paths {
+        buckets.push(BufWriter::new(File::create(path)?));
+    }
+
+    let mut reader = RawReader::open(input, record_size)?;
+    let mut record = Vec::with_capacity(record_size);
+    let mut total = 0u64;
+    while reader.next_record(&mut record) {
+        buckets[rng.gen_range(0..bucket_count)].write_all(&record)?;
+        total += 1;
+    }
+
+    for bucket in &mut buckets {
+        bucket.flush()?;
+    }
+    drop(buckets);
+
+    let mut writer = create_writer(output)?;
+    for path in &bucket_paths {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+
+        let mut records = data.chunks_exact(record_size).collect::<Vec<_>>();
+        records.shuffle(&mut rng);
+        for record in records {
+            writer.write_all(record)?;
+        }
+
+        fs::remove_file(path)?;
+    }
+
+    writer.flush()?;
+    fs::remove_dir(&temp_directory)?;
+
+    println!("Shuffled {total} {} records using {bucket_count} buckets", kind.name());
+    Ok(())
+}
+
+pub fn create_rng(args: &Args) -> Result<StdRng, String> {
+    Ok(match args.get::<u64>("seed", 0)? {
+        0 => StdRng::from_entropy(),
+        seed => StdRng::seed_from_u64(seed),
+    })
+}
+
+//Buckets are kept next to the output, so they end up on the same disk
+fn temp_directory(output: &Path) -> PathBuf {
+    let name = output.file_name().and_then(|name| name.to_str()).unwrap_or("output");
+    output.with_file_name(format!(".{name}.shuffle"))
+}
@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+
+use crate::records::{for_each_value, piece_count, position_key, Args, ToolResult};
+
+const SCORE_BINS: usize = 10;
+
+//stats <files...> [--kind k]
+pub fn run(args: &Args) -> ToolResult {
+    if args.positional.is_empty() {
+        return Err("Missing <files> argument".into());
+    }
+
+    let mut total = 0u64;
+    let mut results = [0u64; 3];
+    let mut white_to_move = 0u64;
+    let mut score_sum = 0.0f64;
+    let mut mate_scores = 0u64;
+    let mut score_histogram = [0u64; SCORE_BINS];
+    let mut piece_histogram = [0u64; 33];
+    let mut keys = HashSet::new();
+
+    for index in 0..args.positional.len() {
+        let path = args.path(index, "file")?;
+        let kind = args.kind(path)?;
+
+        for_each_value(path, kind, |record| {
+            total += 1;
+            results[(record.result.clamp(-1, 1) + 1) as usize] += 1;
+            white_to_move += u64::from(record.side_to_move == 0);
+            score_sum += f64::from(record.score);
+            mate_scores += u64::from(record.score <= 0.0 || record.score >= 1.0);
+            score_histogram[((record.score * SCORE_BINS as f32) as usize).min(SCORE_BINS - 1)] += 1;
+            piece_histogram[(piece_count(record) as usize).min(32)] += 1;
+            keys.insert(position_key(record));
+        })?;
+    }
+
+    if total == 0 {
+        println!("No records found");
+        return Ok(());
+    }
+
+    let percent = |count: u64| count as f64 * 100.0 / total as f64;
+
+    println!("Records: {total}");
+    println!(
+        "W/D/L (white perspective): {}/{}/{} ({:.1}%/{:.1}%/{:.1}%)",
+        results[2],
+        results[1],
+        results[0],
+        percent(results[2]),
+        percent(results[1]),
+        percent(results[0])
+    );
+    println!("White to move: {:.1}%", percent(white_to_move));
+    println!("Average score (side to move): {:.4}", score_sum / total as f64);
+    println!("Mate scores: {} ({:.2}%)", mate_scores, percent(mate_scores));
+    println!("Unique positions: {} (duplicate rate {:.2}%)\n", keys.len(), percent(total - keys.len() as u64));
+
+    println!("Score histogram (side to move):");
+    for (bin, &count) in score_histogram.iter().enumerate() {
+        let from = bin as f32 / SCORE_BINS as f32;
+        let to = (bin + 1) as f32 / SCORE_BINS as f32;
+        println!("  {from:.1}-{to:.1} {:>12} {:>6.2}% {}", count, percent(count), bar(percent(count)));
+    }
+
+    println!("\nPiece count histogram:");
+    for (pieces, &count) in piece_histogram.iter().enumerate().filter(|(_, &count)| count > 0) {
+        println!("  {pieces:>2} {:>12} {:>6.2}% {}", count, percent(count), bar(percent(count)));
+    }
+
+    Ok(())
+}
+
+fn bar(percent: f64) -> String {
+    "#".repeat((percent / 2.0).round() as usize)
+}
@@ -0,0 +1,173 @@
+use rand::Rng;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+
+use datagen::{complete_games_length, ChessPolicyData, PieceBoard};
+
+use crate::records::{
+    board_fen, create_writer, for_each_policy, for_each_value, position_key, write_record, Args, DataKind, GameReader,
+    RawReader, ToolResult,
+};
+use crate::shuffle::create_rng;
+
+//dedup <input> <output> [--kind k]
+//Keeps the first occurrence of every position, positions are compared by zobrist key
+pub fn dedup(args: &Args) -> ToolResult {
+    let input = args.path(0, "input")?;
+    let output = args.path(1, "output")?;
+    let kind = args.kind(input)?;
+
+    let mut seen = HashSet::new();
+    let mut writer = create_writer(output)?;
+    let mut total = 0u64;
+    let mut result = Ok(());
+
+    match kind {
+        DataKind::Value => for_each_value(input, kind, |record| {
+            total += 1;
+            if result.is_ok() && seen.insert(position_key(record)) {
+                result = write_record(&mut writer, record);
+            }
+        })?,
+        DataKind::Policy => for_each_policy(input, kind, |record| {
+            total += 1;
+            if result.is_ok() && seen.insert(position_key(&record.board)) {
+                result = write_record(&mut writer, record);
+            }
+        })?,
+        DataKind::Games => return Err("Games can't be deduplicated, convert them to value or policy first".into()),
+    }
+
+    result?;
+    writer.flush()?;
+
+    println!("Kept {} of {total} {} records", seen.len(), kind.name());
+    Ok(())
+}
+
+//merge <output> <inputs...> [--kind k]
+//Concatenates files of the same kind. Partial records at the end of the inputs are dropped
+pub fn merge(args: &Args) -> ToolResult {
+    let output = args.path(0, "output")?;
+    if args.positional.len() < 2 {
+        return Err("Missing <inputs> argument".into());
+    }
+
+    let kind = args.kind(args.path(1, "input")?)?;
+    for index in 2..args.positional.len() {
+        let input = args.path(index, "input")?;
+        if args.kind(input)? != kind {
+            return Err(format!("{} is not a {} file", input.display(), kind.name()).into());
+        }
+    }
+
+    let mut writer = create_writer(output)?;
+    for index in 1..args.positional.len() {
+        let input = args.path(index, "input")?;
+        let length = match kind.record_size() {
+            Some(record_size) => {
+                let length = std::fs::metadata(input)?.len();
+                length - length % record_size as u64
+            }
+            None => complete_games_length(input)?,
+        };
+
+        let copied = io::copy(&mut BufReader::new(File::open(input)?).take(length), &mut writer)?;
+        println!("{}: {copied} bytes", input.display());
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+//split <input> <train> <validation> [--ratio r] [--seed n] [--kind k]
+//Randomly moves given fraction of records to the validation file. Games are kept whole, so
+//positions of one game never end up in both files
+pub fn split(args: &Args) -> ToolResult {
+    let input = args.path(0, "input")?;
+    let train = args.path(1, "train")?;
+    let validation = args.path(2, "validation")?;
+    let kind = args.kind(input)?;
+    let ratio = args.get::<f64>("ratio", 0.05)?;
+    let mut rng = create_rng(args)?;
+
+    if !(0.0..=1.0).contains(&ratio) {
+        return Err("Ratio has to be between 0 and 1".into());
+    }
+
+    let mut writers = [create_writer(train)?, create_writer(validation)?];
+    let mut counts = [0u64; 2];
+
+    match kind.record_size() {
+        Some(record_size) => {
+            let mut reader = RawReader::open(input, record_size)?;
+            let mut record = Vec::with_capacity(record_size);
+            while reader.next_record(&mut record) {
+                let target = usize::from(rng.gen_bool(ratio));
+                writers[target].write_all(&record)?;
+                counts[target] += 1;
+            }
+        }
+        None => {
+            for game in GameReader::open(input)? {
+                let target = usize::from(rng.gen_bool(ratio));
+                writers[target].write_all(&game?.to_bytes())?;
+                counts[target] += 1;
+            }
+        }
+    }
+
+    for writer in &mut writers {
+        writer.flush()?;
+    }
+
+    let unit = if kind == DataKind::Games { "games" } else { "records" };
+    println!("Train: {} {unit}, validation: {} {unit}", counts[0], counts[1]);
+    Ok(())
+}
+
+//convert <input> <output> --to value|policy|text [--kind k]
+//Games expand into positions, policy records can be reduced to value records, and every kind
+//can be written as text lines of `fen | score | result`
+pub fn convert(args: &Args) -> ToolResult {
+    let input = args.path(0, "input")?;
+    let output = args.path(1, "output")?;
+    let kind = args.kind(input)?;
+    let target = args.get::<String>("to", String::new())?;
+    if !["value", "policy", "text"].contains(&target.as_str()) {
+        return Err(format!("Unknown or missing conversion target '{target}', use --to value|policy|text").into());
+    }
+
+    let mut writer = create_writer(output)?;
+    let mut total = 0u64;
+    let mut result = Ok(());
+
+    match target.as_str() {
+        "value" => for_each_value(input, kind, |record| {
+            total += 1;
+            if result.is_ok() {
+                result = write_record(&mut writer, record);
+            }
+        })?,
+        "policy" => for_each_policy(input, kind, |record: &ChessPolicyData| {
+            total += 1;
+            if result.is_ok() {
+                result = write_record(&mut writer, record);
+            }
+        })?,
+        "text" => for_each_value(input, kind, |record: &PieceBoard| {
+            total += 1;
+            if result.is_ok() {
+                result = writeln!(writer, "{} | {:.4} | {}", board_fen(record), record.score, record.result);
+            }
+        })?,
+        _ => unreachable!(),
+    }
+
+    result?;
+    writer.flush()?;
+
+    println!("Converted {total} records from {} to {target}", input.display());
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::str::FromStr;
+
+use datagen::{AdjudicationSettings, OpeningOrder, Openings};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+//Command line options, every `--key` takes a value. Later values of the same key win
+pub struct Arguments<'a> {
+    options: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Arguments<'a> {
+    pub fn parse(args: &'a [String], keys: &[&str]) -> Result<Self, String> {
+        let mut options = Vec::new();
+        let mut index = 0;
+        while index < args.len() {
+            let key = args[index].strip_prefix("--").ok_or(format!("Unexpected argument '{}'", args[index]))?;
+            if !keys.contains(&key) && !GAME_KEYS.contains(&key) {
+                return Err(format!("Unknown option '--{key}'"));
+            }
+
+            let value = args.get(index + 1).ok_or(format!("Missing value for --{key}"))?;
+            options.push((key, value.as_str()));
+            index += 2;
+        }
+
+        Ok(Self { options })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.options.iter().rev().find(|(name, _)| *name == key).map(|(_, value)| *value)
+    }
+
+    pub fn get_or<T: FromStr>(&self, key: &str, default: T) -> Result<T, String> {
+        match self.get(key) {
+            Some(value) => value.parse::<T>().map_err(|_| format!("Invalid value '{value}' for --{key}")),
+            None => Ok(default),
+        }
+    }
+}
+
+//Options shared by every command that plays games
+const GAME_KEYS: [&str; 11] = [
+    "concurrency",
+    "book",
+    "book-order",
+    "book-plies",
+    "resign-score",
+    "resign-plies",
+    "draw-move",
+    "draw-margin",
+    "draw-plies",
+    "seed",
+    "each",
+];
+
+pub const GAME_HELP: &str =
+    "  --each <settings>           Engine settings applied to both engines before their own ones
+  --concurrency <n>           Games played at the same time (default 1)
+  --book <random|file>        Opening source, random plies from startpos or EPD/FEN file (default random)
+  --book-order <order>        Book sampling order, random or sequential (default random)
+  --book-plies <n>            Random plies played after the book position (default 0)
+  --resign-score <f>          Expected score past which a side is considered lost (default 0.97)
+  --resign-plies <n>          Consecutive plies past resign score to end the game, 0 disables (default 8)
+  --draw-move <n>             Move of the game from which draws can be adjudicated (default 40)
+  --draw-margin <f>           Maximal distance of the score from 0.5 to count as drawn (default 0.03)
+  --draw-plies <n>            Consecutive plies within draw margin to end the game, 0 disables (default 20)
+  --seed <n>                  Seed for the openings (default random)";
+
+pub struct GameSettings {
+    pub concurrency: usize,
+    pub openings: Openings,
+    pub adjudication: AdjudicationSettings,
+    pub seed: Option<u64>,
+}
+
+impl GameSettings {
+    pub fn from_args(args: &Arguments) -> Result<Self, String> {
+        let book_order = match args.get("book-order").unwrap_or("random") {
+            "random" => OpeningOrder::Random,
+            "sequential" => OpeningOrder::Sequential,
+            value => return Err(format!("Invalid value '{value}' for --book-order")),
+        };
+
+        let book = args.get("book").unwrap_or("random");
+        let openings = Openings::from_source(book, book_order, args.get_or("book-plies", 0)?)
+            .map_err(|err| format!("Failed to load openings '{book}': {err}"))?;
+
+        Ok(Self {
+            concurrency: args.get_or::<usize>("concurrency", 1)?.max(1),
+            openings,
+            adjudication: AdjudicationSettings {
+                resign_score: args.get_or("resign-score", 0.97)?,
+                resign_plies: args.get_or("resign-plies", 8)?,
+                draw_move: args.get_or("draw-move", 40)?,
+                draw_margin: args.get_or("draw-margin", 0.03)?,
+                draw_plies: args.get_or("draw-plies", 20)?,
+            },
+            seed: args.get("seed").map(|_| args.get_or("seed", 0)).transpose()?,
+        })
+    }
+
+    //Every thread gets its own seed, otherwise all of them would play the same openings
+    pub fn rng(&self, thread_index: usize) -> StdRng {
+        match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed.wrapping_add(thread_index as u64)),
+            None => StdRng::from_entropy(),
+        }
+    }
+}
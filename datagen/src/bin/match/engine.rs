@@ -0,0 +1,177 @@
+use std::fs;
+use std::sync::Arc;
+use std::time::Instant;
+
+use javelin::{
+    Board, Evaluation, Move, MoveHistory, Options, PolicyNetwork, Search, SearchRules, SearchTree, ValueNetwork,
+};
+
+#[derive(Clone, Copy)]
+pub struct TimeControl {
+    pub base: u64,
+    pub increment: u64,
+}
+
+impl TimeControl {
+    //Parsed from `base+increment` in seconds, the same way cutechess and fastchess take it
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (base, increment) = value.split_once('+').unwrap_or((value, "0"));
+        let seconds = |value: &str| {
+            value
+                .parse::<f64>()
+                .map(|seconds| (seconds * 1000.0) as u64)
+                .map_err(|_| format!("Invalid time control '{value}'"))
+        };
+
+        Ok(Self { base: seconds(base)?, increment: seconds(increment)? })
+    }
+}
+
+//Everything that makes one side of the match different from the other. Options and networks
+//are applied only to the thread that searches, so both engines can share the process
+#[derive(Clone)]
+pub struct EngineConfig {
+    pub name: String,
+    pub nodes: u32,
+    pub time_control: Option<TimeControl>,
+    pub gumbel: bool,
+    pub options: Vec<(String, String)>,
+    pub value_network: Option<Arc<ValueNetwork>>,
+    pub policy_network: Option<Arc<PolicyNetwork>>,
+}
+
+impl EngineConfig {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            nodes: 0,
+            time_control: None,
+            gumbel: false,
+            options: Vec::new(),
+            value_network: None,
+            policy_network: None,
+        }
+    }
+
+    //Takes comma separated `key=value` pairs, engine options are given as `option.<Name>=<value>`
+    //with the same values as in `setoption`
+    pub fn apply(&mut self, settings: &str) -> Result<(), String> {
+        for setting in settings.split(',').map(str::trim).filter(|setting| !setting.is_empty()) {
+            let (key, value) = setting.split_once('=').ok_or(format!("Expected key=value, got '{setting}'"))?;
+            match key {
+                "name" => self.name = value.to_string(),
+                "nodes" => self.nodes = value.parse().map_err(|_| format!("Invalid value '{value}' for {key}"))?,
+                "tc" => self.time_control = Some(TimeControl::parse(value)?),
+                "gumbel" => self.gumbel = value.parse().map_err(|_| format!("Invalid value '{value}' for {key}"))?,
+                "value" => self.value_network = Some(load_network(value)?),
+                "policy" => self.policy_network = Some(load_network(value)?),
+                _ => match key.strip_prefix("option.") {
+                    Some(option) => {
+                        //Options are validated on the main thread, so games don't fail halfway through the match
+                        let result = Options::set_for_thread(option, value);
+                        Options::clear_thread_options();
+                        result?;
+                        self.options.retain(|(name, _)| name != option);
+                        self.options.push((option.to_string(), value.to_string()));
+                    }
+                    None => return Err(format!("Unknown engine setting '{key}'")),
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.nodes == 0 && self.time_control.is_none() {
+            return Err(format!("Engine '{}' needs nodes or tc", self.name));
+        }
+
+        Ok(())
+    }
+
+    //Makes the current thread search with this engine's options and networks
+    pub fn activate(&self) {
+        Options::clear_thread_options();
+        for (name, value) in &self.options {
+            Options::set_for_thread(name, value).expect("options are validated when parsed");
+        }
+
+        Evaluation::set_thread_networks(self.value_network.clone(), self.policy_network.clone());
+    }
+}
+
+//Network files are raw dumps of the network struct, the same format the trainer exports
+fn load_network<T>(path: &str) -> Result<Arc<T>, String> {
+    let bytes = fs::read(path).map_err(|err| format!("Failed to read network '{path}': {err}"))?;
+    let size = std::mem::size_of::<T>();
+    if bytes.len() != size {
+        return Err(format!("Network '{path}' has {} bytes, expected {size}", bytes.len()));
+    }
+
+    let network = unsafe {
+        let layout = std::alloc::Layout::new::<T>();
+        let ptr = std::alloc::alloc_zeroed(layout);
+        if ptr.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, size);
+        Box::from_raw(ptr.cast::<T>())
+    };
+
+    Ok(Arc::from(network))
+}
+
+pub struct SearchResult {
+    pub mv: Move,
+    pub score: f32,
+    pub time: u64,
+}
+
+//One side of a game. Search tree is kept between moves, so it can be reused the same way the
+//engine reuses it between `go` commands. Every game starts with a new player, the same as after
+//`ucinewgame`
+pub struct Player<'a> {
+    config: &'a EngineConfig,
+    search: Search<false>,
+    previous_board: Board,
+    pub time_left: u64,
+}
+
+impl<'a> Player<'a> {
+    pub fn new(config: &'a EngineConfig, board: &Board) -> Self {
+        //Tree size depends on Hash option of the engine
+        config.activate();
+        Self {
+            config,
+            search: Search::<false>::new(SearchTree::new(), None),
+            previous_board: *board,
+            time_left: config.time_control.map(|time_control| time_control.base).unwrap_or_default(),
+        }
+    }
+
+    #[inline]
+    pub fn config(&self) -> &EngineConfig {
+        self.config
+    }
+
+    pub fn search(&mut self, board: &Board, history: &MoveHistory) -> SearchResult {
+        self.config.activate();
+
+        let mut rules = SearchRules::new();
+        rules.max_nodes = self.config.nodes;
+        rules.gumbel_root = self.config.gumbel;
+        if let Some(time_control) = self.config.time_control {
+            //Zero would mean no time limit, so the search always gets at least a millisecond
+            rules.time_for_move = SearchRules::calculate_time(self.time_left, time_control.increment, 0).max(1);
+        }
+
+        let timer = Instant::now();
+        self.search.reuse_tree(board, &self.previous_board);
+        let mv = self.search.run::<false>(rules, board, history);
+        let time = timer.elapsed().as_millis() as u64;
+
+        self.previous_board = *board;
+        SearchResult { mv, score: self.search.tree().get_best_phantom().avg_score(), time }
+    }
+}
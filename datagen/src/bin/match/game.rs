@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use datagen::{check_game_end, AdjudicationSettings, Adjudicator, GameEnd};
+use javelin::{Board, MoveHistory};
+
+use crate::engine::{EngineConfig, Player};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Termination {
+    Game(GameEnd),
+    TimeForfeit,
+}
+
+impl Termination {
+    pub const COUNT: usize = GameEnd::COUNT + 1;
+
+    #[inline]
+    pub fn index(&self) -> usize {
+        match self {
+            Termination::Game(end) => end.index(),
+            Termination::TimeForfeit => GameEnd::COUNT,
+        }
+    }
+
+    pub fn name(index: usize) -> &'static str {
+        GameEnd::ALL.get(index).map(GameEnd::name).unwrap_or("time_forfeit")
+    }
+}
+
+struct GameOutcome {
+    //Result from white perspective
+    result: i8,
+    termination: Termination,
+}
+
+//Plays one game from given position, returns None when the match was stopped during the game
+fn play_game(
+    board: &Board,
+    (white, black): (&EngineConfig, &EngineConfig),
+    adjudication: AdjudicationSettings,
+    stop: &AtomicBool,
+) -> Option<GameOutcome> {
+    let mut players = [Player::new(white, board), Player::new(black, board)];
+    let mut adjudicator = Adjudicator::new(adjudication);
+    let mut board = *board;
+    let mut history = MoveHistory::new();
+
+    while !stop.load(Ordering::Relaxed) {
+        let side = board.side_to_move.current();
+        let player = &mut players[side];
+        let search = player.search(&board, &history);
+
+        if let Some(time_control) = player.config().time_control {
+            if search.time > player.time_left {
+                let result = if side == 0 { -1 } else { 1 };
+                return Some(GameOutcome { result, termination: Termination::TimeForfeit });
+            }
+
+            player.time_left = player.time_left - search.time + time_control.increment;
+        }
+
+        history.push(&board);
+        board.make_move(search.mv);
+
+        let game_end = check_game_end(&board, &history).or_else(|| adjudicator.update(search.score, side as u8));
+        if let Some((end, result)) = game_end {
+            return Some(GameOutcome { result, termination: Termination::Game(end) });
+        }
+    }
+
+    None
+}
+
+//Plays the opening twice with colors swapped and returns scores of the first engine in both
+//games, 0 for a loss, 1 for a draw and 2 for a win
+pub fn play_pair(
+    board: &Board,
+    (first, second): (&EngineConfig, &EngineConfig),
+    adjudication: AdjudicationSettings,
+    stop: &AtomicBool,
+) -> Option<[(u8, Termination); 2]> {
+    let first_game = play_game(board, (first, second), adjudication, stop)?;
+    let second_game = play_game(board, (second, first), adjudication, stop)?;
+
+    Some([
+        ((first_game.result + 1) as u8, first_game.termination),
+        ((1 - second_game.result) as u8, second_game.termination),
+    ])
+}
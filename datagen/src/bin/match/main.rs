@@ -0,0 +1,204 @@
+use std::env;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use args::{Arguments, GameSettings, GAME_HELP};
+use engine::EngineConfig;
+use game::{play_pair, Termination};
+use sprt::{MatchStats, SprtResult, SprtSettings};
+
+mod args;
+mod engine;
+mod game;
+mod sprt;
+mod spsa;
+
+const HELP: &str = "Usage: match --engine1 <settings> --engine2 <settings> [options]
+       match spsa --engine <settings> [options]
+
+Plays two Javelin configurations against each other in this process. Every opening is played
+twice with colors swapped, results are reported from the perspective of the first engine.
+`match spsa --help` describes the tuner of engine options.
+
+Engine settings are comma separated key=value pairs:
+  name=<name>                 Name shown in the results (default engine1/engine2)
+  nodes=<n>                   Nodes per move
+  tc=<base+inc>               Time control in seconds, for example tc=8+0.08
+  gumbel=<true|false>         Use Gumbel root search (nodes only)
+  value=<file>                Value network file, built-in network by default
+  policy=<file>               Policy network file, built-in network by default
+  option.<Name>=<value>       Engine option, with the same value as in setoption
+
+Options:
+  --games <n>                 Number of games, rounded up to full pairs, 0 for no limit (default 0)
+  --sprt <elo0,elo1>          Stop once SPRT accepts one of the hypotheses
+  --alpha <f>                 SPRT false positive rate (default 0.05)
+  --beta <f>                  SPRT false negative rate (default 0.05)
+  --report <n>                Pairs between result reports (default 10)";
+
+const KEYS: [&str; 7] = ["engine1", "engine2", "games", "sprt", "alpha", "beta", "report"];
+
+struct MatchConfig {
+    engines: [EngineConfig; 2],
+    pairs: usize,
+    sprt: Option<SprtSettings>,
+    report: u64,
+    games: GameSettings,
+}
+
+impl MatchConfig {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let args = Arguments::parse(args, &KEYS)?;
+
+        let mut engines = [EngineConfig::new("engine1"), EngineConfig::new("engine2")];
+        for (engine, key) in engines.iter_mut().zip(["engine1", "engine2"]) {
+            engine.apply(args.get("each").unwrap_or_default())?;
+            engine.apply(args.get(key).ok_or(format!("Missing --{key}"))?)?;
+            engine.validate()?;
+        }
+
+        let sprt = match args.get("sprt") {
+            Some(value) => {
+                let (elo0, elo1) = value.split_once(',').ok_or(format!("Invalid value '{value}' for --sprt"))?;
+                let elo = |value: &str| {
+                    value.trim().parse::<f64>().map_err(|_| format!("Invalid value '{value}' for --sprt"))
+                };
+                Some(SprtSettings {
+                    elo0: elo(elo0)?,
+                    elo1: elo(elo1)?,
+                    alpha: args.get_or("alpha", 0.05)?,
+                    beta: args.get_or("beta", 0.05)?,
+                })
+            }
+            None => None,
+        };
+
+        let games = args.get_or::<usize>("games", 0)?;
+        if games == 0 && sprt.is_none() {
+            return Err("Either --games or --sprt is needed".to_string());
+        }
+
+        Ok(Self {
+            engines,
+            pairs: if games == 0 { usize::MAX } else { games.div_ceil(2) },
+            sprt,
+            report: args.get_or::<u64>("report", 10)?.max(1),
+            games: GameSettings::from_args(&args)?,
+        })
+    }
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if args.first().is_some_and(|command| command == "spsa") {
+        spsa::run(&args[1..]);
+        return;
+    }
+
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{HELP}\n{GAME_HELP}");
+        return;
+    }
+
+    let config = match MatchConfig::from_args(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}\n\n{HELP}\n{GAME_HELP}");
+            std::process::exit(1);
+        }
+    };
+
+    let stop = stop_on_interrupt();
+    let next_pair = AtomicUsize::new(0);
+    let stats = Mutex::new(MatchStats::default());
+
+    println!("{} vs {}", config.engines[0].name, config.engines[1].name);
+    thread::scope(|scope| {
+        for index in 0..config.games.concurrency {
+            let (config, stop, next_pair, stats) = (&config, &*stop, &next_pair, &stats);
+            scope.spawn(move || {
+                let mut rng = config.games.rng(index);
+                while !stop.load(Ordering::Relaxed) && next_pair.fetch_add(1, Ordering::Relaxed) < config.pairs {
+                    let opening = config.games.openings.next(&mut rng);
+                    let [first, second] = &config.engines;
+                    let Some(games) = play_pair(&opening.board, (first, second), config.games.adjudication, stop)
+                    else {
+                        break;
+                    };
+
+                    let mut stats = stats.lock().unwrap();
+                    stats.add_pair(games);
+
+                    let finished = config.sprt.and_then(|sprt| stats.sprt(&sprt)).is_some();
+                    if finished {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+
+                    if stats.pairs() % config.report == 0 && !finished {
+                        println!("{}", report_line(&stats, config.sprt));
+                    }
+                }
+            });
+        }
+    });
+
+    print_results(&config, &stats.into_inner().unwrap());
+}
+
+//Games in progress are dropped on interrupt, results gathered so far are still printed
+fn stop_on_interrupt() -> Arc<AtomicBool> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_clone = stop.clone();
+    if let Err(err) = ctrlc::set_handler(move || stop_clone.store(true, Ordering::Relaxed)) {
+        eprintln!("Failed to set signal handler: {err}");
+    }
+
+    stop
+}
+
+fn report_line(stats: &MatchStats, sprt: Option<SprtSettings>) -> String {
+    let mut line = format!(
+        "Games {}  W/D/L {}/{}/{}  Ptnml {:?}",
+        stats.games(),
+        stats.wins,
+        stats.draws,
+        stats.losses,
+        stats.pentanomial
+    );
+
+    if let Some((elo, error)) = stats.elo() {
+        line.push_str(&format!("  Elo {elo:.1} +/- {error:.1}"));
+    }
+
+    if let Some(sprt) = sprt {
+        let (lower, upper) = sprt.bounds();
+        line.push_str(&format!("  LLR {:.2} ({lower:.2}, {upper:.2})", stats.llr(&sprt)));
+    }
+
+    line
+}
+
+fn print_results(config: &MatchConfig, stats: &MatchStats) {
+    println!("\nResults of {} vs {}:", config.engines[0].name, config.engines[1].name);
+    println!("{}", report_line(stats, config.sprt));
+
+    if stats.games() > 0 {
+        let score = (stats.wins as f64 + stats.draws as f64 / 2.0) / stats.games() as f64;
+        println!("Score {:.1}%  Draw ratio {:.1}%", score * 100.0, stats.draws as f64 / stats.games() as f64 * 100.0);
+    }
+
+    if let Some(sprt) = config.sprt {
+        let verdict = match stats.sprt(&sprt) {
+            Some(SprtResult::H0) => "H0 accepted",
+            Some(SprtResult::H1) => "H1 accepted",
+            None => "inconclusive",
+        };
+        println!("SPRT ({}, {}): {verdict}", sprt.elo0, sprt.elo1);
+    }
+
+    println!("Game ends:");
+    for (index, count) in stats.terminations.iter().enumerate().filter(|(_, count)| **count > 0) {
+        println!(" - {}: {count}", Termination::name(index));
+    }
+}
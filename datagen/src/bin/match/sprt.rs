@@ -0,0 +1,129 @@
+use crate::game::Termination;
+
+#[derive(Clone, Copy)]
+pub struct SprtSettings {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl SprtSettings {
+    //Log-likelihood ratio below lower bound accepts H0 (elo0), above upper bound accepts H1 (elo1)
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SprtResult {
+    H0,
+    H1,
+}
+
+//Results from the perspective of the first engine. Games are played in pairs with the same
+//opening and colors swapped, pentanomial counts hold how many pairs ended with 0, 0.5, 1, 1.5
+//and 2 points. Pairs are correlated through the opening, so statistics are computed over pairs
+#[derive(Default)]
+pub struct MatchStats {
+    pub wins: u64,
+    pub draws: u64,
+    pub losses: u64,
+    pub pentanomial: [u64; 5],
+    pub terminations: [u64; Termination::COUNT],
+}
+
+impl MatchStats {
+    #[inline]
+    pub fn games(&self) -> u64 {
+        self.wins + self.draws + self.losses
+    }
+
+    #[inline]
+    pub fn pairs(&self) -> u64 {
+        self.pentanomial.iter().sum()
+    }
+
+    //Takes scores of the first engine in both games of the pair, 0 for a loss, 1 for a draw
+    //and 2 for a win
+    pub fn add_pair(&mut self, games: [(u8, Termination); 2]) {
+        for (score, termination) in games {
+            match score {
+                2 => self.wins += 1,
+                1 => self.draws += 1,
+                _ => self.losses += 1,
+            }
+
+            self.terminations[termination.index()] += 1;
+        }
+
+        self.pentanomial[usize::from(games[0].0 + games[1].0)] += 1;
+    }
+
+    //Mean and variance of the score per game
+    fn score_distribution(&self) -> Option<(f64, f64)> {
+        let pairs = self.pairs() as f64;
+        if pairs == 0.0 {
+            return None;
+        }
+
+        let probabilities = self.pentanomial.map(|count| count as f64 / pairs);
+        let scores = [0.0, 0.25, 0.5, 0.75, 1.0];
+        let mean = probabilities.iter().zip(scores).map(|(probability, score)| probability * score).sum::<f64>();
+        let variance = probabilities
+            .iter()
+            .zip(scores)
+            .map(|(probability, score)| probability * (score - mean).powi(2))
+            .sum::<f64>();
+
+        Some((mean, variance))
+    }
+
+    //Elo difference with 95% confidence interval
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let (mean, variance) = self.score_distribution()?;
+        let margin = 1.96 * (variance / self.pairs() as f64).sqrt();
+
+        let elo = score_to_elo(mean);
+        let error = (score_to_elo(mean + margin) - score_to_elo(mean - margin)) / 2.0;
+        Some((elo, error))
+    }
+
+    //Generalized SPRT on the pentanomial score distribution, approximated the same way fishtest
+    //does it for logistic Elo
+    pub fn llr(&self, settings: &SprtSettings) -> f64 {
+        let Some((mean, variance)) = self.score_distribution() else {
+            return 0.0;
+        };
+
+        if variance <= 0.0 {
+            return 0.0;
+        }
+
+        let score0 = elo_to_score(settings.elo0);
+        let score1 = elo_to_score(settings.elo1);
+        self.pairs() as f64 * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+
+    pub fn sprt(&self, settings: &SprtSettings) -> Option<SprtResult> {
+        let llr = self.llr(settings);
+        let (lower, upper) = settings.bounds();
+        if llr <= lower {
+            Some(SprtResult::H0)
+        } else if llr >= upper {
+            Some(SprtResult::H1)
+        } else {
+            None
+        }
+    }
+}
+
+fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+//Scores of exactly 0 or 1 would give infinite Elo
+fn score_to_elo(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
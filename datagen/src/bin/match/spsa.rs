@@ -0,0 +1,251 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use javelin::{Options, TunableOption};
+use rand::Rng;
+
+use crate::args::{Arguments, GameSettings, GAME_HELP};
+use crate::engine::EngineConfig;
+use crate::game::play_pair;
+
+const SPSA_HELP: &str = "Usage: match spsa --engine <settings> [options]
+
+Tunes engine options with SPSA. Every iteration plays pairs of games between two copies of the
+engine, one with the options moved up and one with the options moved down by a random sign, and
+moves the options towards the side that scored better. Tuned options are the ones declared with
+`.tune(min, max, step)` in `create_option_structs!`, current values are printed in the same
+format, ready to be pasted back. Engine settings are the same as for `match`, tuned options start
+from their declared defaults unless the engine settings set them.
+
+Options:
+  --engine <settings>         Settings of the tuned engine, needs nodes or tc
+  --iterations <n>            Number of iterations (default 5000)
+  --pairs <n>                 Game pairs played in every iteration (default 1)
+  --params <names>            Comma separated options to tune (default all tunable options, without
+                              time management options when the engine has no tc)
+  --r-end <f>                 Learning rate at the end of tuning (default 0.002)
+  --log <file>                Csv file with values after every iteration (default spsa.csv)
+  --report <n>                Iterations between printing current values (default 100)";
+
+const KEYS: [&str; 7] = ["engine", "iterations", "pairs", "params", "r-end", "log", "report"];
+
+//Exponents of the learning rate and perturbation schedules recommended by Spall
+const ALPHA: f64 = 0.602;
+const GAMMA: f64 = 0.101;
+
+//Time management options, they have no effect on engines limited only by nodes
+const TIME_OPTIONS: [&str; 2] = ["TcDivider", "TcIncPercentage"];
+
+struct SpsaConfig {
+    engine: EngineConfig,
+    parameters: Vec<TunableOption>,
+    iterations: usize,
+    pairs: usize,
+    r_end: f64,
+    log: String,
+    report: usize,
+    games: GameSettings,
+}
+
+impl SpsaConfig {
+    fn from_args(args: &[String]) -> Result<Self, String> {
+        let args = Arguments::parse(args, &KEYS)?;
+
+        let mut engine = EngineConfig::new("spsa");
+        engine.apply(args.get("each").unwrap_or_default())?;
+        engine.apply(args.get("engine").ok_or("Missing --engine")?)?;
+        engine.validate()?;
+
+        let is_node_limited = engine.time_control.is_none();
+        let tunable = Options::tunable();
+        let mut parameters = match args.get("params") {
+            Some(names) => names
+                .split(',')
+                .map(|name| {
+                    let name = name.trim();
+                    tunable
+                        .iter()
+                        .find(|option| option.name == name)
+                        .cloned()
+                        .ok_or(format!("Option '{name}' has no tuning range"))
+                })
+                .collect::<Result<Vec<TunableOption>, String>>()?,
+            None => {
+                tunable.into_iter().filter(|option| !is_node_limited || !TIME_OPTIONS.contains(&option.name)).collect()
+            }
+        };
+
+        if let Some(option) = parameters.iter().find(|option| is_node_limited && TIME_OPTIONS.contains(&option.name)) {
+            return Err(format!("Option '{}' has no effect on engine limited by nodes", option.name));
+        }
+
+        //Values set in engine settings are the starting point instead of the declared defaults
+        for parameter in &mut parameters {
+            if let Some((_, value)) = engine.options.iter().find(|(name, _)| name == parameter.name) {
+                let value =
+                    value.parse::<f32>().map_err(|_| format!("Invalid value '{value}' for {}", parameter.name))?;
+                parameter.value = if parameter.is_float { value / 100.0 } else { value };
+            }
+        }
+
+        Ok(Self {
+            engine,
+            parameters,
+            iterations: args.get_or::<usize>("iterations", 5000)?.max(1),
+            pairs: args.get_or::<usize>("pairs", 1)?.max(1),
+            r_end: args.get_or("r-end", 0.002)?,
+            log: args.get("log").unwrap_or("spsa.csv").to_string(),
+            report: args.get_or::<usize>("report", 100)?.max(1),
+            games: GameSettings::from_args(&args)?,
+        })
+    }
+
+    //Perturbation size and learning rate of a parameter in given iteration. Both decay, so the
+    //perturbation ends at the declared step and the learning rate at `r_end`
+    fn schedule(&self, parameter: &TunableOption, iteration: usize) -> (f64, f64) {
+        let iterations = self.iterations as f64;
+        let stability = 0.1 * iterations;
+        let c_end = f64::from(parameter.range.step);
+        let a_end = self.r_end * c_end * c_end;
+
+        let c = c_end * iterations.powf(GAMMA) / (iteration as f64).powf(GAMMA);
+        let a = a_end * (stability + iterations).powf(ALPHA) / (stability + iteration as f64).powf(ALPHA);
+        (c, a / (c * c))
+    }
+}
+
+struct SpsaState {
+    values: Vec<f64>,
+    completed: usize,
+    log: BufWriter<File>,
+}
+
+pub fn run(args: &[String]) {
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{SPSA_HELP}\n{GAME_HELP}");
+        return;
+    }
+
+    let config = match SpsaConfig::from_args(args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}\n\n{SPSA_HELP}\n{GAME_HELP}");
+            std::process::exit(1);
+        }
+    };
+
+    if config.parameters.is_empty() {
+        eprintln!("No options to tune");
+        std::process::exit(1);
+    }
+
+    let state = match create_log(&config) {
+        Ok(log) => Mutex::new(SpsaState {
+            values: config.parameters.iter().map(|parameter| f64::from(parameter.value)).collect(),
+            completed: 0,
+            log,
+        }),
+        Err(err) => {
+            eprintln!("Failed to create log '{}': {err}", config.log);
+            std::process::exit(1);
+        }
+    };
+
+    let stop = crate::stop_on_interrupt();
+    let next_iteration = AtomicUsize::new(1);
+
+    println!("Tuning {} options for {} iterations", config.parameters.len(), config.iterations);
+    thread::scope(|scope| {
+        for index in 0..config.games.concurrency {
+            let (config, stop, next_iteration, state) = (&config, &*stop, &next_iteration, &state);
+            scope.spawn(move || {
+                let mut rng = config.games.rng(index);
+                loop {
+                    let iteration = next_iteration.fetch_add(1, Ordering::Relaxed);
+                    if iteration > config.iterations || stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    //Iterations run in parallel, so every thread perturbs the values it sees when the
+                    //iteration starts, the same way distributed SPSA does it
+                    let values = state.lock().unwrap().values.clone();
+                    let signs = values.iter().map(|_| if rng.gen::<bool>() { 1.0 } else { -1.0 }).collect::<Vec<f64>>();
+                    let schedules = config
+                        .parameters
+                        .iter()
+                        .map(|parameter| config.schedule(parameter, iteration))
+                        .collect::<Vec<(f64, f64)>>();
+
+                    let perturbed = |direction: f64, name: &str| {
+                        let mut engine = config.engine.clone();
+                        engine.name = name.to_string();
+                        for (((parameter, value), sign), (c, _)) in
+                            config.parameters.iter().zip(&values).zip(&signs).zip(&schedules)
+                        {
+                            let value = parameter.option_value((value + direction * sign * c) as f32);
+                            engine.apply(&format!("option.{}={value}", parameter.name)).expect("value is in range");
+                        }
+                        engine
+                    };
+                    let (plus, minus) = (perturbed(1.0, "plus"), perturbed(-1.0, "minus"));
+
+                    //Wins minus losses of the engine with values moved up
+                    let mut result = 0.0;
+                    for _ in 0..config.pairs {
+                        let opening = config.games.openings.next(&mut rng);
+                        let Some(games) = play_pair(&opening.board, (&plus, &minus), config.games.adjudication, stop)
+                        else {
+                            return;
+                        };
+                        result += games.iter().map(|(score, _)| f64::from(*score) - 1.0).sum::<f64>();
+                    }
+
+                    let mut state = state.lock().unwrap();
+                    for (index, parameter) in config.parameters.iter().enumerate() {
+                        let (c, r) = schedules[index];
+                        let range = parameter.range;
+                        state.values[index] = (state.values[index] + r * c * result * signs[index])
+                            .clamp(f64::from(range.min), f64::from(range.max));
+                    }
+                    state.completed += 1;
+
+                    if let Err(err) = write_log_row(&mut state) {
+                        eprintln!("Failed to write log '{}': {err}", config.log);
+                    }
+
+                    if state.completed % config.report == 0 {
+                        println!("\nIteration {}/{}", state.completed, config.iterations);
+                        print_values(config, &state.values);
+                    }
+                }
+            });
+        }
+    });
+
+    let state = state.into_inner().unwrap();
+    println!("\nFinal values after {} iterations:", state.completed);
+    print_values(&config, &state.values);
+}
+
+fn create_log(config: &SpsaConfig) -> io::Result<BufWriter<File>> {
+    let mut log = BufWriter::new(File::create(&config.log)?);
+    let names = config.parameters.iter().map(|parameter| parameter.name).collect::<Vec<&str>>();
+    writeln!(log, "iteration,{}", names.join(","))?;
+    log.flush()?;
+    Ok(log)
+}
+
+fn write_log_row(state: &mut SpsaState) -> io::Result<()> {
+    let values = state.values.iter().map(|value| value.to_string()).collect::<Vec<String>>();
+    writeln!(state.log, "{},{}", state.completed, values.join(","))?;
+    state.log.flush()
+}
+
+fn print_values(config: &SpsaConfig, values: &[f64]) {
+    for (parameter, value) in config.parameters.iter().zip(values) {
+        println!("    {}", parameter.declaration(*value as f32));
+    }
+}
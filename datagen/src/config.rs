@@ -0,0 +1,226 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::adjudication::AdjudicationSettings;
+use crate::filters::FilterSettings;
+use crate::openings::OpeningOrder;
+use crate::selfplay_thread::SelfPlaySettings;
+
+pub const HELP: &str = "Usage: datagen [--config <file>] [options]
+
+Options (can also be set in config file as `key = value`, using underscores instead of dashes):
+  --output <dir>              Directory for data shards (default ../../resources/data)
+  --format <positions|games>  Write value/policy position records or compressed game records (default positions)
+  --pgn                       Write every game to PGN file next to the shards as well
+  --nodes <n>                 Nodes per full search (default 1000)
+  --fast-nodes <n>            Nodes per fast search, 0 disables playout cap randomization (default 0)
+  --full-search-ratio <f>     Fraction of moves searched with full budget (default 1.0)
+  --gumbel                    Use Gumbel root search and write improved policy targets
+  --threads <n>               Number of selfplay threads (default 1)
+  --games <n>                 Stop after n games, 0 for no limit (default 0)
+  --positions <n>             Stop after n value positions, 0 for no limit (default 0)
+  --seed <n>                  Seed for openings and playout caps (default random)
+  --openings <random|file>    Opening source, random plies from startpos or EPD/FEN file (default random)
+  --opening-order <order>     Book sampling order, random or sequential (default random)
+  --opening-plies <n>         Random plies played after the book position (default 0)
+  --both-colors               Follow every opening with the same opening with colors swapped
+  --resign-score <f>          Expected score past which a side is considered lost (default 0.97)
+  --resign-plies <n>          Consecutive plies past resign score to end the game, 0 disables (default 0)
+  --draw-move <n>             Move of the game from which draws can be adjudicated (default 40)
+  --draw-margin <f>           Maximal distance of the score from 0.5 to count as drawn (default 0.03)
+  --draw-plies <n>            Consecutive plies within draw margin to end the game, 0 disables (default 0)
+  --filter-in-check           Skip positions with side to move in check
+  --filter-tactical           Skip positions where the best move is a capture or promotion
+  --filter-score <f>          Skip positions with expected score above f or below 1 - f (default 1.0)
+  --filter-plies <n>          Skip first n plies of every game (default 0)
+  --filter-duplicates         Skip positions that already occurred in the same game
+  --filter-policy             Apply the filters to policy data as well
+  --sync-interval <s>         Seconds between flushes of shards to disk (default 60)
+  --shard-size <mb>           Size after which a new shard file is started (default 1024)
+  --log-interval <s>          Seconds between status lines in quiet mode (default 60)
+  --quiet                     Print status lines instead of full screen dashboard";
+
+//Options that don't take a value in command line
+const FLAGS: [&str; 8] = [
+    "pgn",
+    "gumbel",
+    "quiet",
+    "both-colors",
+    "filter-in-check",
+    "filter-tactical",
+    "filter-duplicates",
+    "filter-policy",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DataFormat {
+    Positions,
+    Games,
+}
+
+#[derive(Clone)]
+pub struct DatagenConfig {
+    pub output_directory: PathBuf,
+    pub format: DataFormat,
+    pub pgn: bool,
+    pub selfplay: SelfPlaySettings,
+    pub threads: usize,
+    pub max_games: u64,
+    pub max_positions: u64,
+    pub seed: Option<u64>,
+    pub openings: String,
+    pub opening_order: OpeningOrder,
+    pub opening_plies: u32,
+    pub both_colors: bool,
+    pub adjudication: AdjudicationSettings,
+    pub filters: FilterSettings,
+    pub sync_interval: u64,
+    pub shard_size: u64,
+    pub log_interval: u64,
+    pub quiet: bool,
+}
+
+impl DatagenConfig {
+    pub fn new() -> Self {
+        Self {
+            output_directory: PathBuf::from("../../resources/data"),
+            format: DataFormat::Positions,
+            pgn: false,
+            selfplay: SelfPlaySettings { full_nodes: 1000, fast_nodes: 0, full_search_ratio: 1.0, gumbel: false },
+            threads: 1,
+            max_games: 0,
+            max_positions: 0,
+            seed: None,
+            openings: "random".to_string(),
+            opening_order: OpeningOrder::Random,
+            opening_plies: 0,
+            both_colors: false,
+            adjudication: AdjudicationSettings {
+                resign_score: 0.97,
+                resign_plies: 0,
+                draw_move: 40,
+                draw_margin: 0.03,
+                draw_plies: 0,
+            },
+            filters: FilterSettings {
+                in_check: false,
+                tactical_move: false,
+                score_limit: 1.0,
+                opening_plies: 0,
+                duplicates: false,
+                policy: false,
+            },
+            sync_interval: 60,
+            shard_size: 1024,
+            log_interval: 60,
+            quiet: false,
+        }
+    }
+
+    //Config file is applied first, so flags passed in command line always take precedence
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut config = Self::new();
+
+        if let Some(index) = args.iter().position(|arg| arg == "--config") {
+            let path = args.get(index + 1).ok_or("Missing path after --config")?;
+            config.load_file(path)?;
+        }
+
+        let mut index = 0;
+        while index < args.len() {
+            let key = args[index].strip_prefix("--").ok_or(format!("Unexpected argument '{}'", args[index]))?;
+
+            match key {
+                "config" => index += 1,
+                _ if FLAGS.contains(&key) => config.set(&key.replace('-', "_"), "true")?,
+                _ => {
+                    let value = args.get(index + 1).ok_or(format!("Missing value for --{key}"))?;
+                    config.set(&key.replace('-', "_"), value)?;
+                    index += 1;
+                }
+            }
+
+            index += 1;
+        }
+
+        Ok(config)
+    }
+
+    //Reads flat `key = value` file. TOML comments, section headers and quoted strings are accepted
+    pub fn load_file(&mut self, path: &str) -> Result<(), String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("Failed to read config '{path}': {err}"))?;
+
+        for (line_index, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() || line.starts_with('[') {
+                continue;
+            }
+
+            let (key, value) =
+                line.split_once('=').ok_or(format!("{path}:{}: expected key = value", line_index + 1))?;
+            self.set(key.trim(), value.trim().trim_matches('"'))
+                .map_err(|err| format!("{path}:{}: {err}", line_index + 1))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "output" => self.output_directory = PathBuf::from(value),
+            "format" => {
+                self.format = match value {
+                    "positions" => DataFormat::Positions,
+                    "games" => DataFormat::Games,
+                    _ => return Err(format!("Invalid value '{value}' for {key}")),
+                }
+            }
+            "pgn" => self.pgn = parse(key, value)?,
+            "nodes" => self.selfplay.full_nodes = parse(key, value)?,
+            "fast_nodes" => self.selfplay.fast_nodes = parse(key, value)?,
+            "full_search_ratio" => self.selfplay.full_search_ratio = parse(key, value)?,
+            "gumbel" => self.selfplay.gumbel = parse(key, value)?,
+            "threads" => self.threads = parse(key, value)?,
+            "games" => self.max_games = parse(key, value)?,
+            "positions" => self.max_positions = parse(key, value)?,
+            "seed" => self.seed = Some(parse(key, value)?),
+            "openings" => self.openings = value.to_string(),
+            "opening_order" => {
+                self.opening_order = match value {
+                    "random" => OpeningOrder::Random,
+                    "sequential" => OpeningOrder::Sequential,
+                    _ => return Err(format!("Invalid value '{value}' for {key}")),
+                }
+            }
+            "opening_plies" => self.opening_plies = parse(key, value)?,
+            "both_colors" => self.both_colors = parse(key, value)?,
+            "resign_score" => self.adjudication.resign_score = parse(key, value)?,
+            "resign_plies" => self.adjudication.resign_plies = parse(key, value)?,
+            "draw_move" => self.adjudication.draw_move = parse(key, value)?,
+            "draw_margin" => self.adjudication.draw_margin = parse(key, value)?,
+            "draw_plies" => self.adjudication.draw_plies = parse(key, value)?,
+            "filter_in_check" => self.filters.in_check = parse(key, value)?,
+            "filter_tactical" => self.filters.tactical_move = parse(key, value)?,
+            "filter_score" => self.filters.score_limit = parse(key, value)?,
+            "filter_plies" => self.filters.opening_plies = parse(key, value)?,
+            "filter_duplicates" => self.filters.duplicates = parse(key, value)?,
+            "filter_policy" => self.filters.policy = parse(key, value)?,
+            "sync_interval" => self.sync_interval = parse::<u64>(key, value)?.max(1),
+            "shard_size" => self.shard_size = parse::<u64>(key, value)?.max(1),
+            "log_interval" => self.log_interval = parse::<u64>(key, value)?.max(1),
+            "quiet" => self.quiet = parse(key, value)?,
+            _ => return Err(format!("Unknown option '{key}'")),
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn shard_size_bytes(&self) -> u64 {
+        self.shard_size * 1024 * 1024
+    }
+}
+
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String> {
+    value.parse::<T>().map_err(|_| format!("Invalid value '{value}' for {key}"))
+}
@@ -3,6 +3,8 @@ use std::fs::{self, File};
 use std::io::{self, Read};
 use std::path::Path;
 
+use crate::game_format::read_games;
+use crate::shard_writer::shard_paths;
 use crate::structs::{ChessPolicyData, PieceBoard};
 
 #[derive(Clone)]
@@ -13,8 +15,11 @@ pub struct Files {
 
 #[allow(unused)]
 impl Files {
+    pub const DATA_DIRECTORY: &'static str = "../../resources/data";
     pub const VALUE_PATH: &'static str = "../../resources/data/value.data";
     const POLICY_PATH: &'static str = "../../resources/data/policy.data";
+    pub const VALUE_FILE: &'static str = "value.data";
+    pub const POLICY_FILE: &'static str = "policy.data";
 
     pub fn new() -> Self {
         Self { value_data: Vec::new(), policy_data: Vec::new() }
@@ -48,6 +53,59 @@ impl Files {
         Ok(())
     }
 
+    pub fn save_to(&self, directory: &Path) -> io::Result<()> {
+        fs::create_dir_all(directory)?;
+        self.save_data::<PieceBoard>(directory.join(Self::VALUE_FILE), &self.value_data)?;
+        self.save_data::<ChessPolicyData>(directory.join(Self::POLICY_FILE), &self.policy_data)?;
+        Ok(())
+    }
+
+    pub fn load_from(&mut self, directory: &Path) -> io::Result<()> {
+        self.value_data = self.load_data::<PieceBoard>(directory.join(Self::VALUE_FILE))?;
+        self.policy_data = self.load_data::<ChessPolicyData>(directory.join(Self::POLICY_FILE))?;
+        Ok(())
+    }
+
+    //Appends value records from every value and game shard written by datagen threads in the
+    //directory. Games are expanded into per position records
+    pub fn load_value_shards(&mut self, directory: &Path) -> io::Result<()> {
+        for path in shard_paths(directory, "value") {
+            self.value_data.append(&mut self.load_data::<PieceBoard>(path)?);
+        }
+
+        for path in shard_paths(directory, "games") {
+            for game in read_games(&path)? {
+                self.value_data.append(&mut game.expand().0);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load_policy_shards(&mut self, directory: &Path) -> io::Result<()> {
+        for path in shard_paths(directory, "policy") {
+            self.policy_data.append(&mut self.load_data::<ChessPolicyData>(path)?);
+        }
+
+        for path in shard_paths(directory, "games") {
+            for game in read_games(&path)? {
+                self.policy_data.append(&mut game.expand().1);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn load_games(&mut self, path: &Path) -> io::Result<()> {
+        for game in read_games(path)? {
+            let (mut value_data, mut policy_data) = game.expand();
+            self.value_data.append(&mut value_data);
+            self.policy_data.append(&mut policy_data);
+        }
+
+        Ok(())
+    }
+
     pub fn load_value(&mut self) -> io::Result<()> {
         self.value_data = self.load_data::<PieceBoard>(Self::VALUE_PATH)?;
         Ok(())
@@ -58,13 +116,13 @@ impl Files {
         Ok(())
     }
 
-    fn save_data<T: Pod>(&self, path: &str, data: &[T]) -> io::Result<()> {
+    fn save_data<T: Pod>(&self, path: impl AsRef<Path>, data: &[T]) -> io::Result<()> {
         let bytes = bytemuck::cast_slice(data);
         fs::write(path, bytes)
     }
 
-    fn load_data<T: Pod + Copy>(&self, path: &str) -> io::Result<Vec<T>> {
-        let path = Path::new(path);
+    fn load_data<T: Pod + Copy>(&self, path: impl AsRef<Path>) -> io::Result<Vec<T>> {
+        let path = path.as_ref();
         if !path.exists() {
             return Ok(Vec::new());
         }
@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+
+use javelin::{Board, Move};
+
+#[derive(Clone, Copy)]
+pub struct FilterSettings {
+    pub in_check: bool,
+    pub tactical_move: bool,
+    pub score_limit: f32,
+    pub opening_plies: u32,
+    pub duplicates: bool,
+    pub policy: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Filter {
+    InCheck,
+    TacticalMove,
+    Score,
+    OpeningPly,
+    Duplicate,
+}
+
+impl Filter {
+    pub const COUNT: usize = 5;
+    pub const ALL: [Filter; Filter::COUNT] =
+        [Filter::InCheck, Filter::TacticalMove, Filter::Score, Filter::OpeningPly, Filter::Duplicate];
+
+    #[inline]
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filter::InCheck => "in check",
+            Filter::TacticalMove => "capture/promotion best move",
+            Filter::Score => "score past limit",
+            Filter::OpeningPly => "opening plies",
+            Filter::Duplicate => "duplicate in game",
+        }
+    }
+}
+
+//Chain of filters applied to every searched position of a game. Positions are checked in the
+//order of Filter variants and the first filter that matches is reported, so every position is
+//counted only once
+pub struct PositionFilter {
+    settings: FilterSettings,
+    ply: u32,
+    seen: HashSet<u64>,
+}
+
+impl PositionFilter {
+    pub fn new(settings: FilterSettings) -> Self {
+        Self { settings, ply: 0, seen: HashSet::new() }
+    }
+
+    pub fn reset(&mut self) {
+        self.ply = 0;
+        self.seen.clear();
+    }
+
+    //Score is side to move expected score of the root after search
+    pub fn check(&mut self, board: &Board, best_move: Move, score: f32) -> Option<Filter> {
+        self.ply += 1;
+        let first_visit = self.seen.insert(board.zobrist.key);
+
+        if self.settings.in_check && board.is_in_check() {
+            return Some(Filter::InCheck);
+        }
+
+        if self.settings.tactical_move && (best_move.is_capture() || best_move.is_promotion()) {
+            return Some(Filter::TacticalMove);
+        }
+
+        if score > self.settings.score_limit || score < 1.0 - self.settings.score_limit {
+            return Some(Filter::Score);
+        }
+
+        if self.ply <= self.settings.opening_plies {
+            return Some(Filter::OpeningPly);
+        }
+
+        if self.settings.duplicates && !first_visit {
+            return Some(Filter::Duplicate);
+        }
+
+        None
+    }
+}
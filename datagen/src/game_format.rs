@@ -0,0 +1,293 @@
+use bytemuck::{Pod, Zeroable};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::Path;
+
+use javelin::{create_board, Bitboard, Board, Move, Square};
+
+use crate::structs::{ChessMoveInfo, ChessPolicyData, PieceBoard};
+
+//Game record layout: GameHeader, followed by `move_count` entries. Every entry is a GameMove
+//followed by `policy_len` ChessMoveInfo items. Positions are not stored, they are recreated
+//by replaying the moves from the starting position
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq)]
+pub struct GameHeader {
+    pub piece_boards: [Bitboard; 4],
+    pub side_to_move: u8,
+    pub castle_rights: u8,
+    pub en_passant: u8,
+    pub half_moves: u8,
+    pub result: i8,
+    pub extra: u8,
+    pub move_count: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct GameMove {
+    pub mv: u16,
+    pub score: u16,
+    pub num: u8,
+    pub policy_len: u8,
+    pub flags: u8,
+    pub reserved: u8,
+}
+
+impl GameMove {
+    //Filtered positions are kept in the record, because they are needed to replay the game,
+    //but they are skipped when the game is expanded
+    pub const VALUE_FILTERED: u8 = 0b01;
+    pub const POLICY_FILTERED: u8 = 0b10;
+}
+
+unsafe impl Zeroable for GameHeader {}
+unsafe impl Pod for GameHeader {}
+
+unsafe impl Zeroable for GameMove {}
+unsafe impl Pod for GameMove {}
+
+//Castle rights are stored as bits in the same order as in fen
+const CASTLE_RIGHTS: [char; 4] = ['K', 'Q', 'k', 'q'];
+
+#[derive(Clone)]
+pub struct GameRecord {
+    pub header: GameHeader,
+    pub moves: Vec<(GameMove, Vec<ChessMoveInfo>)>,
+}
+
+#[allow(unused)]
+impl GameRecord {
+    pub fn new(board: &Board) -> Self {
+        let piece_board = PieceBoard::from_board(board);
+        let fen = board.get_fen();
+        let fen_rights = fen.split_whitespace().nth(2).unwrap_or("-");
+        let castle_rights = CASTLE_RIGHTS
+            .iter()
+            .enumerate()
+            .fold(0u8, |rights, (index, &right)| rights | u8::from(fen_rights.contains(right)) << index);
+
+        let header = GameHeader {
+            piece_boards: piece_board.piece_boards,
+            side_to_move: piece_board.side_to_move,
+            castle_rights,
+            en_passant: board.en_passant.get_value() as u8,
+            half_moves: board.half_moves,
+            result: 0,
+            extra: 0,
+            move_count: 0,
+        };
+
+        Self { header, moves: Vec::new() }
+    }
+
+    //Score is stored as side to move expected score, quantized to u16. Empty policy means that
+    //the move was not searched well enough to be used as policy target
+    pub fn push(&mut self, mv: Move, score: f32, num: u8, policy: &[ChessMoveInfo], flags: u8) {
+        let entry = GameMove {
+            mv: mv.get_value(),
+            score: (score.clamp(0.0, 1.0) * f32::from(u16::MAX)).round() as u16,
+            num,
+            policy_len: policy.len().min(u8::MAX as usize) as u8,
+            flags,
+            reserved: 0,
+        };
+
+        self.moves.push((entry, policy[..entry.policy_len as usize].to_vec()));
+        self.header.move_count = self.moves.len() as u16;
+    }
+
+    pub fn start_board(&self) -> Board {
+        let piece_board = PieceBoard {
+            piece_boards: self.header.piece_boards,
+            score: 0.0,
+            result: 0,
+            side_to_move: self.header.side_to_move,
+            num: 0,
+            extra: 0,
+        };
+
+        let board = Board::from_datapack(&piece_board.to_bitboards(), self.header.side_to_move);
+        let fen = board.get_fen();
+        let fields = fen.split_whitespace().collect::<Vec<&str>>();
+
+        let mut castle_rights = CASTLE_RIGHTS
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.header.castle_rights & (1 << index) > 0)
+            .map(|(_, &right)| right)
+            .collect::<String>();
+        if castle_rights.is_empty() {
+            castle_rights.push('-');
+        }
+
+        let en_passant = Square::from_raw(self.header.en_passant as usize);
+        let en_passant = if en_passant == Square::NULL { "-".to_string() } else { en_passant.to_string() };
+
+        //Datapack board has no hash, pins or castle rights, so the position is rebuilt from fen
+        //to get fully initialized board
+        create_board(&format!("{} {} {castle_rights} {en_passant} {} 1", fields[0], fields[1], self.header.half_moves))
+    }
+
+    //Replays the game and recreates per position records. Filtered positions and policy entries
+    //longer than the fixed policy record are skipped, the same way datagen skips them
+    pub fn expand(&self) -> (Vec<PieceBoard>, Vec<ChessPolicyData>) {
+        let mut value_data = Vec::with_capacity(self.moves.len());
+        let mut policy_data = Vec::new();
+        let mut board = self.start_board();
+
+        for (entry, policy) in &self.moves {
+            let mut piece_board = PieceBoard::from_board(&board);
+            piece_board.score = f32::from(entry.score) / f32::from(u16::MAX);
+            piece_board.result = self.header.result;
+            piece_board.num = entry.num;
+            if entry.flags & GameMove::VALUE_FILTERED == 0 {
+                value_data.push(piece_board);
+            }
+
+            if !policy.is_empty() && policy.len() <= 104 && entry.flags & GameMove::POLICY_FILTERED == 0 {
+                let mut policy_entry = ChessPolicyData { board: piece_board, moves: [ChessMoveInfo::default(); 104] };
+                policy_entry.moves[..policy.len()].copy_from_slice(policy);
+                policy_data.push(policy_entry);
+            }
+
+            board.make_move(Move::from_raw(entry.mv));
+        }
+
+        (value_data, policy_data)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = bytemuck::bytes_of(&self.header).to_vec();
+        for (entry, policy) in &self.moves {
+            result.extend_from_slice(bytemuck::bytes_of(entry));
+            result.extend_from_slice(bytemuck::cast_slice(policy));
+        }
+        result
+    }
+
+    //Returns None on clean end of the stream, and UnexpectedEof error when the game is cut off
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<Self>> {
+        let mut header_bytes = [0u8; std::mem::size_of::<GameHeader>()];
+        if !read_or_eof(reader, &mut header_bytes)? {
+            return Ok(None);
+        }
+
+        let header: GameHeader = bytemuck::pod_read_unaligned(&header_bytes);
+        let mut moves = Vec::with_capacity(header.move_count as usize);
+        for _ in 0..header.move_count {
+            let mut entry_bytes = [0u8; std::mem::size_of::<GameMove>()];
+            reader.read_exact(&mut entry_bytes)?;
+            let entry: GameMove = bytemuck::pod_read_unaligned(&entry_bytes);
+
+            let mut policy_bytes = vec![0u8; entry.policy_len as usize * std::mem::size_of::<ChessMoveInfo>()];
+            reader.read_exact(&mut policy_bytes)?;
+            let policy = policy_bytes
+                .chunks_exact(std::mem::size_of::<ChessMoveInfo>())
+                .map(bytemuck::pod_read_unaligned::<ChessMoveInfo>)
+                .collect::<Vec<ChessMoveInfo>>();
+
+            moves.push((entry, policy));
+        }
+
+        Ok(Some(Self { header, moves }))
+    }
+}
+
+//Reads every game in the file. Game cut off at the end of the file is ignored
+pub fn read_games(path: &Path) -> io::Result<Vec<GameRecord>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut result = Vec::new();
+    loop {
+        match GameRecord::read_from(&mut reader) {
+            Ok(Some(game)) => result.push(game),
+            Ok(None) => return Ok(result),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(result),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+//Length in bytes of the complete games at the start of the file, used to cut off game
+//that was only partially written before a crash
+pub fn complete_games_length(path: &Path) -> io::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut length = 0;
+    loop {
+        match GameRecord::read_from(&mut reader) {
+            Ok(Some(_)) => length = reader.stream_position()?,
+            Ok(None) => return Ok(length),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(length),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+//Games, value entries and policy entries stored in the file, counted the same way `expand` creates
+//the entries. Only headers and move entries are read, policies are skipped and moves aren't replayed.
+//Game cut off at the end of the file is ignored
+pub fn count_game_entries(path: &Path) -> io::Result<(u64, u64, u64)> {
+    let file = File::open(path)?;
+    let file_length = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut counts = (0, 0, 0);
+
+    loop {
+        let mut header_bytes = [0u8; std::mem::size_of::<GameHeader>()];
+        match read_or_eof(&mut reader, &mut header_bytes) {
+            Ok(true) => {}
+            Ok(false) => return Ok(counts),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(counts),
+            Err(err) => return Err(err),
+        }
+
+        let header: GameHeader = bytemuck::pod_read_unaligned(&header_bytes);
+        let mut game_counts = (1, 0, 0);
+        for _ in 0..header.move_count {
+            let mut entry_bytes = [0u8; std::mem::size_of::<GameMove>()];
+            match reader.read_exact(&mut entry_bytes) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(counts),
+                Err(err) => return Err(err),
+            }
+
+            let entry: GameMove = bytemuck::pod_read_unaligned(&entry_bytes);
+            reader.seek_relative(i64::from(entry.policy_len) * std::mem::size_of::<ChessMoveInfo>() as i64)?;
+            if entry.flags & GameMove::VALUE_FILTERED == 0 {
+                game_counts.1 += 1;
+            }
+            if entry.policy_len > 0 && entry.policy_len <= 104 && entry.flags & GameMove::POLICY_FILTERED == 0 {
+                game_counts.2 += 1;
+            }
+        }
+
+        //Seeking past the end doesn't fail, so a policy cut off at the end is detected by position
+        if reader.stream_position()? > file_length {
+            return Ok(counts);
+        }
+
+        counts = (counts.0 + game_counts.0, counts.1 + game_counts.1, counts.2 + game_counts.2);
+    }
+}
+
+#[allow(unused)]
+#[inline]
+pub fn is_game_file(path: &Path) -> bool {
+    path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with("games"))
+}
+
+//Fills the buffer, returns false when the stream ended before the first byte
+fn read_or_eof<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+            Ok(count) => read += count,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(true)
+}
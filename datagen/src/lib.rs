@@ -1,8 +1,16 @@
+mod adjudication;
 mod file_manager;
+mod game_format;
+mod openings;
+mod shard_writer;
 mod structs;
 
 pub use structs::ChessMoveInfo;
 pub use structs::ChessPolicyData;
 pub use structs::PieceBoard;
 
+pub use adjudication::{check_game_end, AdjudicationSettings, Adjudicator, GameEnd};
 pub use file_manager::Files;
+pub use game_format::{complete_games_length, count_game_entries, is_game_file, read_games, GameHeader, GameMove, GameRecord};
+pub use openings::{Opening, OpeningOrder, Openings};
+pub use shard_writer::{count_records, shard_paths, ShardWriter};
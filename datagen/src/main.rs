@@ -1,18 +1,34 @@
-use crate::file_manager::Files;
+use crate::adjudication::GameEnd;
+use crate::config::{DataFormat, DatagenConfig, HELP};
+use crate::filters::Filter;
+use crate::game_format::count_game_entries;
+use crate::openings::Openings;
 use crate::selfplay_thread::SelfPlayThread;
+use crate::shard_writer::{count_records, shard_paths};
 use crate::structs::{ChessPolicyData, PieceBoard};
-use std::io::{stdin, stdout, Write};
+use std::collections::HashSet;
+use std::env;
+use std::io;
+use std::path::Path;
 use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
+mod adjudication;
+mod config;
 mod file_manager;
+mod filters;
+mod game_format;
+mod openings;
 mod selfplay_thread;
+mod shard_writer;
 mod structs;
 
 struct GenData {
-    files: Files,
+    value_entries: u64,
+    policy_entries: u64,
     value_filtered: usize,
     policy_filtered: usize,
     games_played: u64,
@@ -25,11 +41,55 @@ struct GenData {
     queen_castle: u32,
     king_castle: u32,
     en_passants: u32,
+    full_searches: u64,
+    fast_searches: u64,
+    openings_used: HashSet<usize>,
+    game_ends: [u64; GameEnd::COUNT],
+    filter_counts: [u64; Filter::COUNT],
 }
 
 fn main() {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{HELP}");
+        return;
+    }
+
+    let config = match DatagenConfig::from_args(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{err}\n\n{HELP}");
+            std::process::exit(1);
+        }
+    };
+
+    let openings = match Openings::from_source(&config.openings, config.opening_order, config.opening_plies) {
+        Ok(openings) => openings,
+        Err(err) => {
+            eprintln!("Failed to load openings '{}': {err}", config.openings);
+            std::process::exit(1);
+        }
+    };
+
+    //Existing shards are only counted, new games are appended to them
+    let (loaded_games, loaded_positions, loaded_policy) = if config.format == DataFormat::Games {
+        match count_games(&config.output_directory) {
+            Ok(counts) => counts,
+            Err(err) => {
+                eprintln!("Failed to read game shards in '{}': {err}", config.output_directory.display());
+                std::process::exit(1);
+            }
+        }
+    } else {
+        (
+            0,
+            count_records::<PieceBoard>(&config.output_directory, "value"),
+            count_records::<ChessPolicyData>(&config.output_directory, "policy"),
+        )
+    };
     let gen_data = Arc::new(Mutex::new(GenData {
-        files: Files::new(),
+        value_entries: loaded_positions,
+        policy_entries: loaded_policy,
         value_filtered: 0,
         policy_filtered: 0,
         games_played: 0,
@@ -42,56 +102,127 @@ fn main() {
         queen_castle: 0,
         king_castle: 0,
         en_passants: 0,
+        full_searches: 0,
+        fast_searches: 0,
+        openings_used: HashSet::new(),
+        game_ends: [0; GameEnd::COUNT],
+        filter_counts: [0; Filter::COUNT],
     }));
 
-    let _ = gen_data.lock().unwrap().files.load();
-    print_raport(&gen_data.lock().unwrap());
+    if config.quiet {
+        println!("Selfplay data generator v{}", env!("CARGO_PKG_VERSION"));
+        println!(
+            "Output: {}, found {} games, {} value and {} policy entries",
+            config.output_directory.display(),
+            loaded_games,
+            loaded_positions,
+            loaded_policy
+        );
+        println!(
+            "Nodes (full/fast): {}/{}, threads: {}, openings: {} ({} positions)",
+            config.selfplay.full_nodes,
+            config.selfplay.fast_nodes,
+            config.threads,
+            config.openings,
+            openings.len()
+        );
+    }
 
-    let mut input = String::new();
+    //Threads finish their writes and sync shards once the flag is set, so the data stays
+    //consistent on Ctrl+C and on termination from outside
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_clone = stop.clone();
+    if let Err(err) = ctrlc::set_handler(move || stop_clone.store(true, Ordering::Relaxed)) {
+        eprintln!("Failed to set signal handler: {err}");
+    }
 
-    print!("Nodes per move: ");
-    let _ = stdout().flush();
-    stdin().read_line(&mut input).expect("Error reading input");
-    let nodes_per_move: u16 = input.trim().parse().expect("Invalid number for nodes per move");
-    input.clear();
+    let mut handles = Vec::new();
+    for index in 0..config.threads {
+        let selfplay_thread = SelfPlayThread::new(index, gen_data.clone(), openings.clone(), stop.clone());
+        match selfplay_thread.run(&config) {
+            Ok(handle) => handles.push(handle),
+            Err(err) => {
+                eprintln!("Failed to open shards in '{}': {err}", config.output_directory.display());
+                stop.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+    }
 
-    print!("Concurrency: ");
-    let _ = stdout().flush();
-    stdin().read_line(&mut input).expect("Error reading input");
-    let concurrency: u8 = input.trim().parse().expect("Invalid number for concurrency");
-    input.clear();
+    let mut seconds = 0u64;
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_secs(1));
+        seconds += 1;
 
-    for _ in 0..concurrency {
-        let selfplay_thread = SelfPlayThread::new(gen_data.clone());
-        selfplay_thread.run(nodes_per_move as u32);
-    }
+        let data = gen_data.lock().unwrap();
+        let new_positions = data.value_entries - loaded_positions;
+        let finished = stop.load(Ordering::Relaxed)
+            || (config.max_games > 0 && data.games_played >= config.max_games)
+            || (config.max_positions > 0 && new_positions >= config.max_positions);
 
-    let mut seconds = 0u128;
-    loop {
-        {
-            let data = gen_data.lock().unwrap();
-            print_raport(&data);
-            println!("Positions per second: {:.1}", data.files.value_data.len() as f32 / seconds as f32);
-            println!("Games per second: {:.1}\n", data.games_played as f32 / seconds as f32);
-            println!("Games played: {}", data.games_played);
-            println!("Games played: {}", data.games_played);
-            println!("W/D/L: {}/{}/{}", data.wins, data.draws, data.loses);
-            println!("Nodes per move: {}", nodes_per_move);
-            println!("Concurrency: {}\n", concurrency);
-            println!("Captures: {}", data.captures);
-            println!("Promotions: {}", data.promotion);
-            println!("Under Promotions: {}", data.under_promotions);
-            println!("Castles (Q/K): {}/{}", data.queen_castle, data.king_castle);
-            println!("En Passants: {}\n", data.en_passants);
-            println!("Time until save: {}s", 1800 - (seconds % 1800));
-            seconds += 1;
-
-            if seconds % 1800 == 0 {
-                let _ = data.files.save();
+        if config.quiet {
+            if seconds % config.log_interval == 0 || finished {
+                print_log_line(&data, seconds, new_positions);
             }
+        } else {
+            print_dashboard(&data, &config, openings.len(), seconds, new_positions);
         }
-        thread::sleep(Duration::from_secs(1));
+
+        if finished {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    println!("Stopping, waiting for threads to flush shards...");
+    for handle in handles {
+        let _ = handle.join();
     }
+
+    let data = gen_data.lock().unwrap();
+    println!(
+        "Finished with {} value and {} policy entries in '{}'",
+        data.value_entries,
+        data.policy_entries,
+        config.output_directory.display()
+    );
+}
+
+fn print_dashboard(data: &GenData, config: &DatagenConfig, openings_count: usize, seconds: u64, new_positions: u64) {
+    print_raport(data);
+    println!("Positions per second: {:.1}", new_positions as f32 / seconds as f32);
+    println!("Games per second: {:.1}\n", data.games_played as f32 / seconds as f32);
+    println!("Games played: {}", data.games_played);
+    println!("W/D/L: {}/{}/{}", data.wins, data.draws, data.loses);
+    println!("Nodes per move (full/fast): {}/{}", config.selfplay.full_nodes, config.selfplay.fast_nodes);
+    println!("Full search ratio: {:.2}", config.selfplay.full_search_ratio);
+    println!("Searches (full/fast): {}/{}", data.full_searches, data.fast_searches);
+    println!("Concurrency: {}", config.threads);
+    println!("Gumbel root: {}\n", config.selfplay.gumbel);
+    println!("Openings: {}", config.openings);
+    println!("Book openings used: {}/{}", data.openings_used.len(), openings_count);
+    println!("Extra plies: {}, both colors: {}\n", config.opening_plies, config.both_colors);
+    println!("Captures: {}", data.captures);
+    println!("Promotions: {}", data.promotion);
+    println!("Under Promotions: {}", data.under_promotions);
+    println!("Castles (Q/K): {}/{}", data.queen_castle, data.king_castle);
+    println!("En Passants: {}\n", data.en_passants);
+    println!("Shards sync interval: {}s", config.sync_interval);
+}
+
+fn print_log_line(data: &GenData, seconds: u64, new_positions: u64) {
+    println!(
+        "[{seconds}s] games: {}, value: {} (+{}), policy: {}, W/D/L: {}/{}/{}, resigns/adj. draws: {}/{}, pos/s: {:.1}",
+        data.games_played,
+        data.value_entries as usize,
+        new_positions,
+        data.policy_entries as usize,
+        data.wins,
+        data.draws,
+        data.loses,
+        data.game_ends[GameEnd::Resign.index()],
+        data.game_ends[GameEnd::DrawAdjudication.index()],
+        new_positions as f32 / seconds as f32
+    );
 }
 
 fn print_raport(data: &GenData) {
@@ -99,24 +230,45 @@ fn print_raport(data: &GenData) {
     println!("Welcome to selfplay data generator v{}\n", env!("CARGO_PKG_VERSION"));
     println!(
         "Value entries: {}({}B)",
-        data.files.value_data.len(),
-        number_scaler(data.files.value_data.len() * std::mem::size_of::<PieceBoard>())
+        data.value_entries as usize,
+        number_scaler(data.value_entries as usize * std::mem::size_of::<PieceBoard>())
     );
     println!(
-        "Filtered: {}({}B)\n",
+        "Filtered: {}({}B)",
         data.value_filtered,
         number_scaler(data.value_filtered * std::mem::size_of::<PieceBoard>())
     );
+    for filter in Filter::ALL {
+        println!(" - {}: {}", filter.name(), data.filter_counts[filter.index()]);
+    }
+    println!();
     println!(
         "Policy entries: {}({}B)",
-        data.files.policy_data.len(),
-        number_scaler(data.files.policy_data.len() * std::mem::size_of::<ChessPolicyData>())
+        data.policy_entries as usize,
+        number_scaler(data.policy_entries as usize * std::mem::size_of::<ChessPolicyData>())
     );
     println!(
         "Filtered: {}({}B)\n",
         data.policy_filtered,
         number_scaler(data.policy_filtered * std::mem::size_of::<ChessPolicyData>())
     );
+
+    println!("Game endings:");
+    for game_end in GameEnd::ALL {
+        println!(" - {}: {}", game_end.name(), data.game_ends[game_end.index()]);
+    }
+    println!();
+}
+
+//Games, value and policy entries stored in existing game shards
+fn count_games(directory: &Path) -> io::Result<(u64, u64, u64)> {
+    let mut result = (0, 0, 0);
+    for path in shard_paths(directory, "games") {
+        let (games, value_entries, policy_entries) = count_game_entries(&path)?;
+        result = (result.0 + games, result.1 + value_entries, result.2 + policy_entries);
+    }
+
+    Ok(result)
 }
 
 fn number_scaler(number: usize) -> String {
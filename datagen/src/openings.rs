@@ -0,0 +1,179 @@
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use javelin::{create_board, Board, MoveList, MoveProvider, Piece};
+use rand::Rng;
+
+const START_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OpeningOrder {
+    Random,
+    Sequential,
+}
+
+//Starting position of a game. Book line is 1-based line number in the book file, or None
+//when the opening was made from random moves from start position
+#[derive(Clone, Copy)]
+pub struct Opening {
+    pub book_line: Option<usize>,
+    pub mirrored: bool,
+    pub board: Board,
+}
+
+impl Opening {
+    //Same position with colors swapped, so the side that was better in the opening is now
+    //played by the other color. Used to balance books that are biased towards one side
+    pub fn mirrored(&self) -> Self {
+        Self {
+            book_line: self.book_line,
+            mirrored: !self.mirrored,
+            board: create_board(&mirror_fen(&self.board.get_fen())),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Openings {
+    positions: Arc<Vec<(usize, String)>>,
+    order: OpeningOrder,
+    extra_plies: u32,
+    next_index: Arc<AtomicUsize>,
+}
+
+impl Openings {
+    pub fn random() -> Self {
+        Self {
+            positions: Arc::new(Vec::new()),
+            order: OpeningOrder::Random,
+            extra_plies: 0,
+            next_index: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    //Loads positions from EPD or FEN file, one position per line. EPD operations and
+    //move counters are dropped, because only the position itself is needed
+    pub fn load(path: &str, order: OpeningOrder, extra_plies: u32) -> io::Result<Self> {
+        let content = fs::read_to_string(path)?;
+        let positions = content
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.split_whitespace().take(4).collect::<Vec<&str>>()))
+            .filter(|(_, fields)| fields.len() == 4)
+            .map(|(line, fields)| (line, format!("{} 0 1", fields.join(" "))))
+            .filter(|(_, fen)| is_playable(&create_board(fen)))
+            .collect::<Vec<(usize, String)>>();
+
+        if positions.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("No positions found in '{path}'")));
+        }
+
+        Ok(Self { positions: Arc::new(positions), order, extra_plies, next_index: Arc::new(AtomicUsize::new(0)) })
+    }
+
+    pub fn from_source(source: &str, order: OpeningOrder, extra_plies: u32) -> io::Result<Self> {
+        if source == "random" {
+            Ok(Self::random())
+        } else {
+            Self::load(source, order, extra_plies)
+        }
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    #[allow(unused)]
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    //Sequential order is shared between clones, so threads walk through the book together
+    //and every position is used once before the book starts again
+    pub fn next<R: Rng>(&self, rng: &mut R) -> Opening {
+        if self.positions.is_empty() {
+            let start_board = create_board(START_POSITION);
+            let mut board = None;
+            while board.is_none() {
+                board = play_random_plies(start_board, rng.gen_range(8..=9), rng);
+            }
+
+            return Opening { book_line: None, mirrored: false, board: board.unwrap() };
+        }
+
+        let index = match self.order {
+            OpeningOrder::Random => rng.gen_range(0..self.positions.len()),
+            OpeningOrder::Sequential => self.next_index.fetch_add(1, Ordering::Relaxed) % self.positions.len(),
+        };
+
+        let (book_line, fen) = &self.positions[index];
+        let book_board = create_board(fen);
+
+        //Extra plies can run into a mate, in that case we try again and give up after few attempts
+        let board = (0..8).find_map(|_| play_random_plies(book_board, self.extra_plies, rng)).unwrap_or(book_board);
+
+        Opening { book_line: Some(*book_line), mirrored: false, board }
+    }
+}
+
+fn mirror_fen(fen: &str) -> String {
+    let fields = fen.split_whitespace().collect::<Vec<&str>>();
+    let swap_case = |c: char| if c.is_uppercase() { c.to_ascii_lowercase() } else { c.to_ascii_uppercase() };
+
+    let pieces = fields[0].split('/').rev().map(|rank| rank.chars().map(swap_case).collect::<String>());
+    let side_to_move = if fields[1] == "w" { "b" } else { "w" };
+
+    let castle_rights = fields[2].chars().map(swap_case).collect::<String>();
+    let mut castle_rights = "KQkq".chars().filter(|&right| castle_rights.contains(right)).collect::<String>();
+    if castle_rights.is_empty() {
+        castle_rights.push('-');
+    }
+
+    let en_passant = match fields[3] {
+        "-" => "-".to_string(),
+        square => square.replace('3', "x").replace('6', "3").replace('x', "6"),
+    };
+
+    format!(
+        "{} {side_to_move} {castle_rights} {en_passant} {} {}",
+        pieces.collect::<Vec<String>>().join("/"),
+        fields[4],
+        fields[5]
+    )
+}
+
+//Plays given number of random moves, returns None if the game ended on the way
+fn play_random_plies<R: Rng>(mut board: Board, plies: u32, rng: &mut R) -> Option<Board> {
+    for _ in 0..plies {
+        let mut move_list = MoveList::new();
+        MoveProvider::generate_moves::<false>(&mut move_list, &board);
+
+        if move_list.len() == 0 {
+            return None;
+        }
+
+        let mv = move_list[if move_list.len() > 1 { rng.gen_range(0..move_list.len()) } else { 0 }];
+        board.make_move(mv);
+    }
+
+    if is_playable(&board) {
+        Some(board)
+    } else {
+        None
+    }
+}
+
+//Illegal fens are returned as empty boards, so we have to make sure both kings exist before generating moves
+fn is_playable(board: &Board) -> bool {
+    if board.get_piece_mask_for_both(Piece::KING).pop_count() != 2 {
+        return false;
+    }
+
+    let mut move_list = MoveList::new();
+    MoveProvider::generate_moves::<false>(&mut move_list, board);
+    move_list.len() > 0
+}
@@ -1,36 +1,96 @@
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::thread;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
 
-use javelin::{create_board, Board, GameResult, MoveList, MoveProvider, Search, SearchRules, SearchTree};
+use javelin::{GameResult, MoveHistory, PgnGame, PgnMove, Search, SearchRules, SearchTree};
 
+use crate::adjudication::{check_game_end, Adjudicator, GameEnd};
+use crate::config::{DataFormat, DatagenConfig};
 use crate::file_manager::Files;
+use crate::filters::PositionFilter;
+use crate::game_format::{complete_games_length, GameMove, GameRecord};
+use crate::openings::{Opening, Openings};
+use crate::shard_writer::ShardWriter;
 use crate::structs::{ChessMoveInfo, ChessPolicyData, PieceBoard};
 use crate::GenData;
 
+#[derive(Clone, Copy)]
+pub struct SelfPlaySettings {
+    pub full_nodes: u32,
+    pub fast_nodes: u32,
+    pub full_search_ratio: f32,
+    pub gumbel: bool,
+}
+
+impl SelfPlaySettings {
+    //Playout cap randomization: only a fraction of moves is searched with full budget and
+    //recorded as policy targets, remaining moves use cheap search just to advance the game
+    pub fn roll_full_search<R: Rng>(&self, rng: &mut R) -> bool {
+        self.fast_nodes == 0 || self.full_search_ratio >= 1.0 || rng.gen::<f32>() < self.full_search_ratio
+    }
+}
+
 pub struct SelfPlayThread {
+    index: usize,
     gen_data: Arc<Mutex<GenData>>,
+    openings: Openings,
+    stop: Arc<AtomicBool>,
 }
 
 impl SelfPlayThread {
-    pub fn new(gen_data: Arc<Mutex<GenData>>) -> Self {
-        Self { gen_data }
+    pub fn new(index: usize, gen_data: Arc<Mutex<GenData>>, openings: Openings, stop: Arc<AtomicBool>) -> Self {
+        Self { index, gen_data, openings, stop }
     }
 
-    pub fn run(&self, nodes: u32) {
+    //Every thread appends finished games to its own shards, so no data has to be kept in memory
+    //and no locking is needed for writing. Unfinished game is discarded when the thread is stopped
+    pub fn run(&self, config: &DatagenConfig) -> io::Result<JoinHandle<()>> {
+        let settings = config.selfplay;
+        let sync_interval = config.sync_interval;
+        let both_colors = config.both_colors;
+        let adjudication = config.adjudication;
+        let filters = config.filters;
+        let mut output = ShardOutput::open(config, self.index)?;
+
+        //Every thread gets its own seed, otherwise all of them would play the same games
+        let seed = config.seed.map(|seed| seed.wrapping_add(self.index as u64));
         let gen_data_clone = self.gen_data.clone();
-        thread::spawn(move || {
-            let mut current_board = get_new_board();
+        let openings = self.openings.clone();
+        let stop = self.stop.clone();
+        let thread_index = self.index;
+        Ok(thread::spawn(move || {
+            let mut rng = match seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_entropy(),
+            };
+            let mut last_sync = Instant::now();
+            let mut opening = openings.next(&mut rng);
+            let mut opening_games = 0;
+            let mut current_board = opening.board;
             let mut previous_board = current_board;
+            let mut history = MoveHistory::new();
             let mut game_result = GameResult::None;
             let mut temp = Files::new();
+            let mut game_record = GameRecord::new(&current_board);
+            let mut pgn_game = PgnGame::new(&current_board);
+            let mut games_played = 0;
+            let mut adjudicator = Adjudicator::new(adjudication);
+            let mut position_filter = PositionFilter::new(filters);
+            let mut game_end = GameEnd::Checkmate;
             let mut search = Search::<false>::new(SearchTree::new(), None);
-            loop {
+            while !stop.load(Ordering::Relaxed) {
+                let full_search = settings.roll_full_search(&mut rng);
                 let mut rules = SearchRules::new();
-                rules.max_nodes = nodes;
+                rules.max_nodes = if full_search { settings.full_nodes } else { settings.fast_nodes };
+                rules.gumbel_root = settings.gumbel;
 
                 search.reuse_tree(&current_board, &previous_board);
-                let mv = search.run::<false>(rules, &current_board);
+                let mv = search.run::<false>(rules, &current_board, &history);
 
                 gen_data_clone.lock().unwrap().captures += u32::from(mv.is_capture());
                 gen_data_clone.lock().unwrap().promotion += u32::from(mv.is_promotion());
@@ -39,6 +99,8 @@ impl SelfPlayThread {
                 gen_data_clone.lock().unwrap().queen_castle += u32::from(mv.is_queen_castle());
                 gen_data_clone.lock().unwrap().king_castle += u32::from(mv.is_king_castle());
                 gen_data_clone.lock().unwrap().en_passants += u32::from(mv.is_en_passant());
+                gen_data_clone.lock().unwrap().full_searches += u64::from(full_search);
+                gen_data_clone.lock().unwrap().fast_searches += u64::from(!full_search);
 
                 previous_board = current_board;
                 let mut piece_board = PieceBoard::from_board(&current_board);
@@ -50,52 +112,77 @@ impl SelfPlayThread {
                     gen_data_clone.lock().unwrap().value_filtered += 1;
                 }
 
+                let filter = position_filter.check(&current_board, mv, piece_board.score);
+                if let Some(filter) = filter {
+                    gen_data_clone.lock().unwrap().filter_counts[filter.index()] += 1;
+                }
+
+                let value_filtered = filter.is_some();
+                let policy_filtered = value_filtered && filters.policy;
+
                 //save board to temp
-                if !temp.push_value(&piece_board, false) {
+                if !temp.push_value(&piece_board, value_filtered) {
                     gen_data_clone.lock().unwrap().value_filtered += 1;
                 }
 
-                if piece_board.num <= 104 {
+                //Only full searches are good enough to be used as policy targets
+                let mut policy = Vec::new();
+                if full_search {
+                    policy = if settings.gumbel {
+                        //Improved policy is stored in place of visit counts, scaled to u16 range
+                        search
+                            .improved_policy()
+                            .into_iter()
+                            .map(|(mv, policy)| ChessMoveInfo {
+                                mv: mv.get_value(),
+                                visits: (policy * f32::from(u16::MAX)) as u16,
+                            })
+                            .collect::<Vec<ChessMoveInfo>>()
+                    } else {
+                        search.tree()[root_index]
+                            .children()
+                            .iter()
+                            .map(|child_phantom| ChessMoveInfo {
+                                mv: child_phantom.mv().get_value(),
+                                visits: child_phantom.visits() as u16,
+                            })
+                            .collect::<Vec<ChessMoveInfo>>()
+                    };
+                }
+
+                let flags = u8::from(value_filtered) * GameMove::VALUE_FILTERED
+                    | u8::from(policy_filtered) * GameMove::POLICY_FILTERED;
+                game_record.push(mv, piece_board.score, piece_board.num, &policy, flags);
+                pgn_game.moves.push(PgnMove::with_eval(mv, piece_board.score));
+
+                if !policy.is_empty() && policy.len() <= 104 {
                     let mut policy_data =
                         ChessPolicyData { board: piece_board, moves: [ChessMoveInfo::default(); 104] };
-
-                    for (index, child_phantom) in search.tree()[root_index].children().into_iter().enumerate() {
-                        policy_data.moves[index] =
-                            ChessMoveInfo { mv: child_phantom.mv().get_value(), visits: child_phantom.visits() as u16 };
-                    }
+                    policy_data.moves[..policy.len()].copy_from_slice(&policy);
 
                     //save policy to temp
-                    if !temp.push_policy(&policy_data, false) {
+                    if !temp.push_policy(&policy_data, policy_filtered) {
                         gen_data_clone.lock().unwrap().policy_filtered += 1;
                     }
                 }
 
+                history.push(&current_board);
                 current_board.make_move(mv);
 
-                if current_board.is_insufficient_material()
-                    || current_board.three_fold()
-                    || current_board.half_moves >= 100
-                {
-                    game_result = GameResult::Draw;
-                } else {
-                    let mut move_list = MoveList::new();
-                    MoveProvider::generate_moves::<false>(&mut move_list, &current_board);
-
-                    if move_list.len() == 0 {
-                        game_result = if current_board.is_in_check() {
-                            if current_board.side_to_move.current() == 0 {
-                                GameResult::Lose(0)
-                            } else {
-                                GameResult::Win(0)
-                            }
-                        } else {
-                            GameResult::Draw
-                        }
-                    }
+                let game_end_result = check_game_end(&current_board, &history)
+                    .or_else(|| adjudicator.update(piece_board.score, piece_board.side_to_move));
+                if let Some((end, result)) = game_end_result {
+                    game_end = end;
+                    game_result = match result {
+                        1 => GameResult::Win(0),
+                        -1 => GameResult::Lose(0),
+                        _ => GameResult::Draw,
+                    };
                 }
 
                 if game_result != GameResult::None {
                     gen_data_clone.lock().unwrap().games_played += 1;
+                    gen_data_clone.lock().unwrap().game_ends[game_end.index()] += 1;
 
                     //process end of the game
                     match game_result {
@@ -121,49 +208,176 @@ impl SelfPlayThread {
                         item.board.result = result_value
                     }
 
-                    //push temps into data
+                    game_record.header.result = result_value;
+
+                    games_played += 1;
+                    pgn_game.set_tag("Event", "Javelin selfplay");
+                    pgn_game.set_tag("Round", &format!("{}.{games_played}", thread_index + 1));
+                    pgn_game.set_tag("White", "Javelin");
+                    pgn_game.set_tag("Black", "Javelin");
+                    pgn_game.set_tag("Result", ["0-1", "1/2-1/2", "1-0"][(result_value + 1) as usize]);
+                    pgn_game.set_tag("Termination", game_end.name());
+
+                    //append finished game to shards
+                    if let Err(err) = output.write(&temp, &game_record, &pgn_game, &opening, result_value, game_end) {
+                        eprintln!("Failed to write shards: {err}");
+                        stop.store(true, Ordering::Relaxed);
+                    }
+
                     {
                         let mut data = gen_data_clone.lock().unwrap();
-                        data.files.value_data.append(&mut temp.value_data);
-                        data.files.policy_data.append(&mut temp.policy_data);
+                        data.value_entries += temp.value_data.len() as u64;
+                        data.policy_entries += temp.policy_data.len() as u64;
+                        if let Some(book_line) = opening.book_line {
+                            data.openings_used.insert(book_line);
+                        }
                     }
 
-                    current_board = get_new_board();
+                    temp.value_data.clear();
+                    temp.policy_data.clear();
+
+                    if last_sync.elapsed().as_secs() >= sync_interval {
+                        output.sync();
+                        last_sync = Instant::now();
+                    }
+
+                    //With both colors every opening is followed by the same opening with colors swapped
+                    opening_games += 1;
+                    if both_colors && opening_games == 1 {
+                        opening = opening.mirrored();
+                    } else {
+                        opening = openings.next(&mut rng);
+                        opening_games = 0;
+                    }
+
+                    current_board = opening.board;
+                    history.clear();
+                    game_record = GameRecord::new(&current_board);
+                    pgn_game = PgnGame::new(&current_board);
+                    adjudicator.reset();
+                    position_filter.reset();
                     game_result = GameResult::None;
                 }
             }
-        });
+
+            output.sync();
+        }))
     }
 }
 
-fn get_new_board() -> Board {
-    let mut new_board = create_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-    new_board = shuffle_board(new_board);
-    new_board
+//Shards of a single thread. Depending on the data format, finished games are stored either as
+//separate value and policy position records or as compressed game records. Opening of every
+//game is logged to csv file next to the shards, in the same order as the games, and optionally
+//the whole game is appended to PGN file
+struct ShardOutput {
+    value: Option<ShardWriter<PieceBoard>>,
+    policy: Option<ShardWriter<ChessPolicyData>>,
+    games: Option<ShardWriter<u8>>,
+    openings: BufWriter<File>,
+    pgn: Option<BufWriter<File>>,
 }
 
-fn shuffle_board(mut board: Board) -> Board {
-    let mut rng = rand::thread_rng();
-    for _ in 0..rng.gen_range(8..=9) {
-        let mut move_list = MoveList::new();
-        MoveProvider::generate_moves::<false>(&mut move_list, &board);
+impl ShardOutput {
+    fn open(config: &DatagenConfig, index: usize) -> io::Result<Self> {
+        let directory = &config.output_directory;
+        let shard_size = config.shard_size_bytes();
+
+        if config.format == DataFormat::Games {
+            let mut games = ShardWriter::<u8>::open(directory, &format!("games_t{index:02}"), shard_size)?;
+            let length = complete_games_length(&games.current_path())?;
+            games.truncate(length)?;
+            let openings = open_openings_log(config, index)?;
+            let pgn = open_pgn(config, index)?;
+            return Ok(Self { value: None, policy: None, games: Some(games), openings, pgn });
+        }
+
+        Ok(Self {
+            value: Some(ShardWriter::open(directory, &format!("value_t{index:02}"), shard_size)?),
+            policy: Some(ShardWriter::open(directory, &format!("policy_t{index:02}"), shard_size)?),
+            games: None,
+            openings: open_openings_log(config, index)?,
+            pgn: open_pgn(config, index)?,
+        })
+    }
+
+    fn write(
+        &mut self,
+        temp: &Files,
+        game_record: &GameRecord,
+        pgn_game: &PgnGame,
+        opening: &Opening,
+        result: i8,
+        game_end: GameEnd,
+    ) -> io::Result<()> {
+        if let Some(value) = &mut self.value {
+            value.write(&temp.value_data)?;
+            value.flush()?;
+        }
+
+        if let Some(policy) = &mut self.policy {
+            policy.write(&temp.policy_data)?;
+            policy.flush()?;
+        }
+
+        if let Some(games) = &mut self.games {
+            games.write(&game_record.to_bytes())?;
+            games.flush()?;
+        }
+
+        let book_line = opening.book_line.map_or("-".to_string(), |line| line.to_string());
+        writeln!(
+            self.openings,
+            "{book_line},{},{result},{},{}",
+            u8::from(opening.mirrored),
+            game_end.name(),
+            opening.board.get_fen()
+        )?;
+        self.openings.flush()?;
+
+        if let Some(pgn) = &mut self.pgn {
+            pgn.write_all(pgn_game.to_pgn().as_bytes())?;
+            pgn.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn sync(&mut self) {
+        let result = self
+            .value
+            .as_mut()
+            .map_or(Ok(()), |value| value.sync())
+            .and_then(|_| self.policy.as_mut().map_or(Ok(()), |policy| policy.sync()))
+            .and_then(|_| self.games.as_mut().map_or(Ok(()), |games| games.sync()))
+            .and_then(|_| self.openings.get_ref().sync_data())
+            .and_then(|_| self.pgn.as_ref().map_or(Ok(()), |pgn| pgn.get_ref().sync_data()));
 
-        if move_list.len() == 0 {
-            board = get_new_board();
-            return shuffle_board(board);
+        if let Err(err) = result {
+            eprintln!("Failed to sync shards: {err}");
         }
+    }
+}
+
+fn open_openings_log(config: &DatagenConfig, index: usize) -> io::Result<BufWriter<File>> {
+    let path = config.output_directory.join(format!("openings_t{index:02}.csv"));
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let is_new = file.metadata()?.len() == 0;
 
-        let mv = move_list[if move_list.len() > 1 { rng.gen_range(0..move_list.len()) } else { 0 }];
-        board.make_move(mv);
+    let mut writer = BufWriter::new(file);
+    if is_new {
+        writeln!(writer, "book_line,mirrored,result,end,fen")?;
     }
 
-    let mut move_list = MoveList::new();
-    MoveProvider::generate_moves::<false>(&mut move_list, &board);
+    Ok(writer)
+}
 
-    if move_list.len() == 0 {
-        board = get_new_board();
-        return shuffle_board(board);
+fn open_pgn(config: &DatagenConfig, index: usize) -> io::Result<Option<BufWriter<File>>> {
+    if !config.pgn {
+        return Ok(None);
     }
 
-    board
+    //Game shards are recognised by the `games` prefix, so the PGN can't share it
+    let path = config.output_directory.join(format!("selfplay_t{index:02}.pgn"));
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(Some(BufWriter::new(file)))
 }
@@ -0,0 +1,157 @@
+use bytemuck::Pod;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+//Appends records of single type to numbered shard files `{name}_{index:04}.data`. Writes go
+//through a buffer and reach the disk on flush, new shard is started once current one grows
+//beyond the size limit
+pub struct ShardWriter<T: Pod> {
+    directory: PathBuf,
+    name: String,
+    shard_index: usize,
+    writer: BufWriter<File>,
+    size: u64,
+    max_size: u64,
+    _data: PhantomData<T>,
+}
+
+#[allow(unused)]
+impl<T: Pod> ShardWriter<T> {
+    //Continues the last existing shard with this name if there is space left in it. Partial record
+    //left at the end of the file by a crash is cut off, so the shard stays aligned
+    pub fn open(directory: &Path, name: &str, max_size: u64) -> io::Result<Self> {
+        fs::create_dir_all(directory)?;
+
+        let record_size = std::mem::size_of::<T>() as u64;
+        let max_size = max_size.max(record_size);
+        let mut shard_index = 0;
+
+        if let Some((index, path)) = shard_files(directory, name).pop() {
+            let size = fs::metadata(&path)?.len();
+            shard_index = if size < max_size { index } else { index + 1 };
+        }
+
+        let path = shard_path(directory, name, shard_index);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        let aligned_size = size - size % record_size;
+        if aligned_size != size {
+            file.set_len(aligned_size)?;
+        }
+
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            name: name.to_string(),
+            shard_index,
+            writer: BufWriter::new(file),
+            size: aligned_size,
+            max_size,
+            _data: PhantomData,
+        })
+    }
+
+    pub fn write(&mut self, data: &[T]) -> io::Result<()> {
+        if self.size >= self.max_size {
+            self.rotate()?;
+        }
+
+        self.writer.write_all(bytemuck::cast_slice(data))?;
+        self.size += (data.len() * std::mem::size_of::<T>()) as u64;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    //Flushes the buffer and waits until the data is physically written
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()
+    }
+
+    #[inline]
+    pub fn current_path(&self) -> PathBuf {
+        shard_path(&self.directory, &self.name, self.shard_index)
+    }
+
+    //Cuts current shard to given length in bytes, used by writers of variable length records to
+    //remove a record that was written only partially
+    pub fn truncate(&mut self, length: u64) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().set_len(length)?;
+        self.size = length;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.sync()?;
+        self.shard_index += 1;
+        let path = shard_path(&self.directory, &self.name, self.shard_index);
+        self.writer = BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+#[inline]
+fn shard_path(directory: &Path, name: &str, index: usize) -> PathBuf {
+    directory.join(format!("{name}_{index:04}.data"))
+}
+
+//Returns shards with exactly this name, sorted by index
+fn shard_files(directory: &Path, name: &str) -> Vec<(usize, PathBuf)> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+
+    let mut result = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            let index = file_name.strip_prefix(name)?.strip_prefix('_')?.strip_suffix(".data")?;
+            if index.len() != 4 {
+                return None;
+            }
+
+            Some((index.parse::<usize>().ok()?, entry.path()))
+        })
+        .collect::<Vec<(usize, PathBuf)>>();
+
+    result.sort_by_key(|(index, _)| *index);
+    result
+}
+
+//Returns all shards in the directory, that start with given prefix (e.g. "value" matches shards
+//of every thread), sorted by name
+pub fn shard_paths(directory: &Path, prefix: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+
+    let mut result = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+                return false;
+            };
+
+            file_name.starts_with(&format!("{prefix}_")) && file_name.ends_with(".data")
+        })
+        .collect::<Vec<PathBuf>>();
+
+    result.sort();
+    result
+}
+
+//Counts whole records in all shards with given prefix, without reading them
+pub fn count_records<T: Pod>(directory: &Path, prefix: &str) -> u64 {
+    shard_paths(directory, prefix)
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|metadata| metadata.len() / std::mem::size_of::<T>() as u64)
+        .sum()
+}
@@ -1,5 +1,5 @@
 use bytemuck::{Pod, Zeroable};
-use javelin::{get_bit, Board};
+use javelin::{get_bit, Bitboard, Board, Square};
 
 #[repr(C)]
 #[derive(Clone, Copy, Default, PartialEq, Eq)]
@@ -53,4 +53,22 @@ impl PieceBoard {
 
         Self { piece_boards, score: 0.0, result: 0, side_to_move: board.side_to_move.current() as u8, num: 0, extra: 0 }
     }
+
+    //Splits packed piece boards into 12 bitboards, white pieces first, ordered from pawn to king
+    pub fn to_bitboards(&self) -> [Bitboard; 12] {
+        let mut result = [Bitboard::EMPTY; 12];
+        for square_index in 0..64 {
+            let square = Square::from_raw(square_index);
+            let piece_index = usize::from(self.piece_boards[0].get_bit(square))
+                | usize::from(self.piece_boards[1].get_bit(square)) << 1
+                | usize::from(self.piece_boards[2].get_bit(square)) << 2;
+            if piece_index == 0 {
+                continue;
+            }
+
+            let color_offset = if self.piece_boards[3].get_bit(square) { 6 } else { 0 };
+            result[piece_index - 1 + color_offset].set_bit(square);
+        }
+        result
+    }
 }
@@ -0,0 +1,347 @@
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use crate::{
+    core::{Board, Move, MoveHistory, MoveList, MoveProvider, Side},
+    mcts::{GameResult, Search, SearchRules, SearchTree},
+    pgn::{format_eval, PgnGame, PgnMove},
+};
+
+//Loss of expected score of the side to move, compared to the best move, from which the played
+//move is marked as an inaccuracy, a mistake or a blunder
+const INACCURACY_LOSS: f32 = 0.05;
+const MISTAKE_LOSS: f32 = 0.10;
+const BLUNDER_LOSS: f32 = 0.15;
+const MAX_VARIATION_LENGTH: usize = 10;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Judgement {
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    fn from_loss(loss: f32) -> Self {
+        if loss >= BLUNDER_LOSS {
+            Judgement::Blunder
+        } else if loss >= MISTAKE_LOSS {
+            Judgement::Mistake
+        } else if loss >= INACCURACY_LOSS {
+            Judgement::Inaccuracy
+        } else {
+            Judgement::Good
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Judgement::Good => "good",
+            Judgement::Inaccuracy => "inaccuracy",
+            Judgement::Mistake => "mistake",
+            Judgement::Blunder => "blunder",
+        }
+    }
+
+    fn nag(&self) -> Option<u8> {
+        match self {
+            Judgement::Good => None,
+            Judgement::Inaccuracy => Some(6),
+            Judgement::Mistake => Some(2),
+            Judgement::Blunder => Some(4),
+        }
+    }
+}
+
+//Search result of a single position of the game, expected score is for the side to move
+struct PositionAnalysis {
+    score: f32,
+    pv_line: Vec<Move>,
+}
+
+//Played move compared to the best one, scores are for the side that played the move
+struct MoveAnalysis {
+    played: String,
+    best: String,
+    best_line: Vec<Move>,
+    best_score: f32,
+    played_score: f32,
+    loss: f32,
+    judgement: Judgement,
+}
+
+pub struct Analyser;
+impl Analyser {
+    //Arguments: `<pgn> [nodes <n>] [movetime <ms>] [output <pgn>]`. Annotated games are written to
+    //the output PGN, summary of every game to JSON file with the same name
+    pub fn run(args: &[String]) {
+        let Some(input) = args.first() else {
+            println!("Usage: analyse <pgn> [nodes <n>] [movetime <ms>] [output <pgn>]");
+            return;
+        };
+
+        let mut rules = SearchRules::new();
+        let mut output = None;
+        for pair in args[1..].chunks(2) {
+            match pair {
+                [key, value] if key == "nodes" => rules.max_nodes = value.parse().unwrap_or_default(),
+                [key, value] if key == "movetime" => rules.time_for_move = value.parse().unwrap_or_default(),
+                [key, value] if key == "output" => output = Some(value.clone()),
+                _ => println!("Unknown argument '{}'", pair.join(" ")),
+            }
+        }
+
+        if rules.max_nodes == 0 && rules.time_for_move == 0 {
+            rules.max_nodes = 10000;
+        }
+
+        let games = match PgnGame::read_file(input) {
+            Ok(games) => games,
+            Err(err) => {
+                println!("{err}");
+                return;
+            }
+        };
+
+        let pgn_path = output.unwrap_or_else(|| {
+            let input_path = Path::new(input);
+            let stem = input_path.file_stem().unwrap_or_default().to_string_lossy();
+            input_path.with_file_name(format!("{stem}_analysed.pgn")).to_string_lossy().to_string()
+        });
+        let json_path = Path::new(&pgn_path).with_extension("json");
+
+        let timer = Instant::now();
+        let mut search = Search::<false>::new(SearchTree::new(), None);
+        let mut previous_board = games.first().map_or(Board::new(), |game| game.start_position);
+        let mut annotated_games = String::new();
+        let mut summaries = Vec::new();
+
+        for (index, game) in games.iter().enumerate() {
+            let game_timer = Instant::now();
+            let analysis = Analyser::analyse_game(&mut search, &mut previous_board, game, rules);
+            let (annotated, summary) = Analyser::annotate(game, &analysis, index + 1);
+            annotated_games.push_str(&annotated.to_pgn());
+            summaries.push(summary);
+
+            let count = |side: usize, judgement: Judgement| {
+                analysis.iter().skip(side).step_by(2).filter(|analysis| analysis.judgement == judgement).count()
+            };
+            let first_side = game.start_position.side_to_move.current();
+            println!(
+                "Game {}/{}: {} - {}, {} plies, {:.1}s, inaccuracies/mistakes/blunders white {}/{}/{}, black {}/{}/{}",
+                index + 1,
+                games.len(),
+                game.tag("White").unwrap_or("?"),
+                game.tag("Black").unwrap_or("?"),
+                analysis.len(),
+                game_timer.elapsed().as_secs_f32(),
+                count(first_side, Judgement::Inaccuracy),
+                count(first_side, Judgement::Mistake),
+                count(first_side, Judgement::Blunder),
+                count(1 - first_side, Judgement::Inaccuracy),
+                count(1 - first_side, Judgement::Mistake),
+                count(1 - first_side, Judgement::Blunder),
+            );
+        }
+
+        let json = format!("[\n{}\n]\n", summaries.join(",\n"));
+        let result = fs::write(&pgn_path, annotated_games).and_then(|_| fs::write(&json_path, json));
+        match result {
+            Ok(()) => println!(
+                "Analysed {} games in {:.1}s, written to '{pgn_path}' and '{}'",
+                games.len(),
+                timer.elapsed().as_secs_f32(),
+                json_path.display()
+            ),
+            Err(err) => println!("Failed to write analysis: {err}"),
+        }
+    }
+
+    //Searches every position of the main line, including the final one. Score of the played move is
+    //taken from the search of the position after it, so the tree is reused between positions
+    fn analyse_game(
+        search: &mut Search<false>,
+        previous_board: &mut Board,
+        game: &PgnGame,
+        rules: SearchRules,
+    ) -> Vec<MoveAnalysis> {
+        let mut board = game.start_position;
+        let mut history = MoveHistory::new();
+        let mut positions = Vec::with_capacity(game.moves.len() + 1);
+
+        for ply in 0..=game.moves.len() {
+            positions.push(Analyser::analyse_position(search, previous_board, &board, &history, rules));
+
+            if let Some(pgn_move) = game.moves.get(ply) {
+                history.push(&board);
+                board.make_move(pgn_move.mv);
+            }
+        }
+
+        let mut board = game.start_position;
+        let mut result = Vec::with_capacity(game.moves.len());
+        for (ply, pgn_move) in game.moves.iter().enumerate() {
+            let position = &positions[ply];
+            let best_move = position.pv_line.first().copied().unwrap_or(pgn_move.mv);
+            let played_score = 1.0 - positions[ply + 1].score;
+
+            //Search of the next position is shorter, so the played move can score a bit better
+            //than the best one
+            let best_score = if best_move == pgn_move.mv { played_score } else { position.score.max(played_score) };
+            let loss = best_score - played_score;
+
+            result.push(MoveAnalysis {
+                played: board.move_to_san(pgn_move.mv),
+                best: board.move_to_san(best_move),
+                best_line: position.pv_line.clone(),
+                best_score,
+                played_score,
+                loss,
+                judgement: Judgement::from_loss(loss),
+            });
+            board.make_move(pgn_move.mv);
+        }
+
+        result
+    }
+
+    fn analyse_position(
+        search: &mut Search<false>,
+        previous_board: &mut Board,
+        board: &Board,
+        history: &MoveHistory,
+        rules: SearchRules,
+    ) -> PositionAnalysis {
+        let mut move_list = MoveList::new();
+        MoveProvider::generate_moves::<false>(&mut move_list, board);
+        if move_list.is_empty() {
+            let score = if board.is_in_check() { 0.0 } else { 0.5 };
+            return PositionAnalysis { score, pv_line: Vec::new() };
+        }
+
+        search.reuse_tree(board, previous_board);
+        search.run::<false>(rules, board, history);
+        *previous_board = *board;
+
+        let tree = search.tree();
+        let best_phantom = tree.get_best_phantom();
+        let game_result =
+            if best_phantom.index() != -1 { tree[best_phantom.index()].result() } else { GameResult::None };
+
+        //Result of the node is given for the side to move after the best move
+        let score = match game_result {
+            GameResult::None => best_phantom.avg_score(),
+            GameResult::Draw => 0.5,
+            GameResult::Lose(_) => 1.0,
+            GameResult::Win(_) => 0.0,
+        };
+
+        let mut pv_line = tree.get_pv_line();
+        pv_line.truncate(MAX_VARIATION_LENGTH);
+        PositionAnalysis { score, pv_line }
+    }
+
+    //Game with scores of played moves as comments, and the best line as a variation of every move
+    //that isn't good enough. Returns the game together with its JSON summary
+    fn annotate(game: &PgnGame, analysis: &[MoveAnalysis], game_number: usize) -> (PgnGame, String) {
+        let mut annotated = game.clone();
+        annotated.set_tag("Annotator", &format!("Javelin v{}", env!("CARGO_PKG_VERSION")));
+
+        let mut board = game.start_position;
+        let mut moves_json = Vec::new();
+        let mut side_losses = [Vec::new(), Vec::new()];
+        let mut side_counts = [[0usize; 4]; 2];
+
+        for (ply, (pgn_move, move_analysis)) in annotated.moves.iter_mut().zip(analysis).enumerate() {
+            let side = board.side_to_move.current();
+            let judgement = move_analysis.judgement;
+            side_losses[side].push(move_analysis.loss);
+            side_counts[side][judgement as usize] += 1;
+
+            let mut comment = format_eval(move_analysis.played_score);
+            if judgement != Judgement::Good {
+                let name = judgement.name();
+                comment.push_str(&format!(
+                    " {}{}. {} was best.",
+                    name[..1].to_uppercase(),
+                    &name[1..],
+                    move_analysis.best
+                ));
+            }
+            if let Some(original) = pgn_move.comment.take() {
+                comment = format!("{original} {comment}");
+            }
+            pgn_move.comment = Some(comment);
+
+            //Move assessment of the engine replaces the one already in the game
+            if let Some(nag) = judgement.nag() {
+                pgn_move.nags.retain(|&nag| nag > 6);
+                pgn_move.nags.push(nag);
+                if let Some((&best_move, rest)) = move_analysis.best_line.split_first() {
+                    let mut variation = vec![PgnMove::with_eval(best_move, move_analysis.best_score)];
+                    variation.extend(rest.iter().map(|&mv| PgnMove::new(mv)));
+                    pgn_move.variations.push(variation);
+                }
+            }
+
+            moves_json.push(format!(
+                "{{\"ply\": {}, \"move\": {}, \"best\": {}, \"best_score\": {:.4}, \
+                 \"played_score\": {:.4}, \"loss\": {:.4}, \"judgement\": \"{}\"}}",
+                ply + 1,
+                json_string(&move_analysis.played),
+                json_string(&move_analysis.best),
+                move_analysis.best_score,
+                move_analysis.played_score,
+                move_analysis.loss,
+                judgement.name()
+            ));
+            board.make_move(pgn_move.mv);
+        }
+
+        let side_json = |side: Side| {
+            let losses = &side_losses[side.current()];
+            let counts = &side_counts[side.current()];
+            let average_loss = losses.iter().sum::<f32>() / losses.len().max(1) as f32;
+            format!(
+                "{{\"moves\": {}, \"average_loss\": {average_loss:.4}, \
+                 \"inaccuracies\": {}, \"mistakes\": {}, \"blunders\": {}}}",
+                losses.len(),
+                counts[Judgement::Inaccuracy as usize],
+                counts[Judgement::Mistake as usize],
+                counts[Judgement::Blunder as usize]
+            )
+        };
+
+        let summary = format!(
+            "  {{\"game\": {game_number}, \"event\": {}, \"white\": {}, \"black\": {}, \"result\": {}, \
+             \"plies\": {}, \"white_stats\": {}, \"black_stats\": {}, \"moves\": [\n    {}\n  ]}}",
+            json_string(game.tag("Event").unwrap_or("?")),
+            json_string(game.tag("White").unwrap_or("?")),
+            json_string(game.tag("Black").unwrap_or("?")),
+            json_string(game.result()),
+            analysis.len(),
+            side_json(Side::WHITE),
+            side_json(Side::BLACK),
+            moves_json.join(",\n    ")
+        );
+
+        (annotated, summary)
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for character in text.chars() {
+        match character {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            character if character.is_control() => result.push_str(&format!("\\u{:04x}", character as u32)),
+            character => result.push(character),
+        }
+    }
+    result.push('"');
+    result
+}
@@ -2,7 +2,7 @@ use colored::*;
 use std::time::Instant;
 
 use crate::{
-    core::create_board,
+    core::{create_board, MoveHistory},
     mcts::{Search, SearchRules, SearchTree},
 };
 
@@ -76,7 +76,7 @@ impl Benchmark {
             let board = create_board(fen);
             let mut search = Search::<false>::new(SearchTree::new(), None);
             let search_timer = Instant::now();
-            search.run::<false>(rules, &board);
+            search.run::<false>(rules, &board, &MoveHistory::new());
             let eclapsed = search_timer.elapsed().as_secs_f32();
             total_nodes = total_nodes + search.search_info().current_iterations;
             total_time = total_time + eclapsed;
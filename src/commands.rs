@@ -6,16 +6,18 @@ use std::{
 
 use crate::{
     benchmark::Benchmark,
-    core::{create_board, Board, MoveList, MoveProvider, Side},
+    core::{create_board, Board, Move, MoveHistory, Side},
     mcts::{Search, SearchRules, SearchTree},
     options::Options,
     perft::Perft,
+    pgn::PgnGame,
 };
 
 type CommandFn = Box<dyn Fn(&mut ContextVariables, &[String]) + Send + Sync + 'static>;
 
 struct ContextVariables {
     board: Board,
+    history: MoveHistory,
     previous_board: Arc<Mutex<Board>>,
     search: Arc<Mutex<Search<true>>>,
     interruption_token: Arc<RwLock<bool>>,
@@ -27,7 +29,14 @@ impl ContextVariables {
         let board = create_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
         let interruption_token = Arc::new(RwLock::new(false));
         let search = Arc::new(Mutex::new(Search::new(SearchTree::new(), Some(Arc::clone(&interruption_token)))));
-        Self { board, previous_board: Arc::new(Mutex::new(board)), search, interruption_token, uci_initialized: false }
+        Self {
+            board,
+            history: MoveHistory::new(),
+            previous_board: Arc::new(Mutex::new(board)),
+            search,
+            interruption_token,
+            uci_initialized: false,
+        }
     }
 }
 
@@ -46,12 +55,14 @@ impl Commands {
         commands.add_command("isready", Commands::is_ready_command);
         commands.add_command("ucinewgame", Commands::new_game_command);
         commands.add_command("position", Commands::position_command);
+        commands.add_command("pgn", Commands::pgn_command);
         commands.add_command("draw", Commands::draw_board_command);
         commands.add_command("go", Commands::go_command);
         commands.add_command("stop", Commands::stop_search_command);
         commands.add_command("tree", Commands::tree_command);
         commands.add_command("perft_bulk", Commands::perft_command);
         commands.add_command("perft", Commands::perft_no_bulk_command);
+        commands.add_command("perftsuite", Commands::perft_suite_command);
         commands.add_command("bench", Commands::bench_command);
 
         commands
@@ -112,44 +123,80 @@ impl Commands {
 
     fn new_game_command(context: &mut ContextVariables, args: &[String]) {
         context.board = create_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        context.history.clear();
         context.search =
             Arc::new(Mutex::new(Search::new(SearchTree::new(), Some(Arc::clone(&context.interruption_token)))));
     }
 
     fn position_command(context: &mut ContextVariables, args: &[String]) {
-        let apply_moves = |moves: &[String], board: &mut Board| {
+        //Returns false when any move can't be played. The whole command is rejected then, so the engine
+        //keeps the previous position instead of searching one the GUI never sent
+        let apply_moves = |moves: &[String], board: &mut Board, history: &mut MoveHistory| {
             if let Some(start_index) = moves.iter().position(|x| x == "moves") {
                 for move_str in &moves[start_index + 1..] {
-                    let mut move_list = MoveList::new();
-                    MoveProvider::generate_moves::<false>(&mut move_list, board);
-
-                    if let Some(mv) = move_list.iter().find(|&m| m.to_string() == *move_str) {
-                        board.make_move(*mv);
+                    match Move::from_uci(board, move_str) {
+                        Ok(mv) => {
+                            history.push(board);
+                            board.make_move(mv);
+                        }
+                        Err(err) => {
+                            println!("info string {err} '{move_str}' in {}, position ignored", board.get_fen());
+                            return false;
+                        }
                     }
                 }
             }
+
+            true
         };
 
-        match args.split_first() {
+        let (mut new_board, moves) = match args.split_first() {
             Some((first, rest)) if first.as_str() == "startpos" => {
-                let mut new_board = create_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
-                apply_moves(rest, &mut new_board);
-                context.board = new_board;
+                (create_board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"), rest)
             }
             Some((first, rest)) if first.as_str() == "fen" && rest.len() >= 6 => {
-                let fen = rest[..6].join(" ");
-                let mut new_board = create_board(&fen);
-
-                if rest.len() > 6 {
-                    apply_moves(&rest[6..], &mut new_board);
-                }
-
-                context.board = new_board;
+                (create_board(&rest[..6].join(" ")), &rest[6..])
             }
             _ => return,
+        };
+
+        let mut history = MoveHistory::new();
+        if apply_moves(moves, &mut new_board, &mut history) {
+            context.board = new_board;
+            context.history = history;
         }
     }
 
+    //Sets position of the game from PGN file, after given number of plies or at the end of the game
+    fn pgn_command(context: &mut ContextVariables, args: &[String]) {
+        let Some(path) = args.first() else {
+            return;
+        };
+
+        let games = match PgnGame::read_file(path) {
+            Ok(games) => games,
+            Err(err) => {
+                println!("info string {err}");
+                return;
+            }
+        };
+
+        let game_number = args.get(1).and_then(|number| number.parse::<usize>().ok()).unwrap_or(1);
+        let Some(game) = games.get(game_number.max(1) - 1) else {
+            println!("info string game {game_number} not found, '{path}' has {} games", games.len());
+            return;
+        };
+
+        let game_length = game.moves.len();
+        let ply = args.get(2).and_then(|ply| ply.parse::<usize>().ok()).unwrap_or(game_length).min(game_length);
+        (context.board, context.history) = game.position_at(ply);
+        println!(
+            "info string loaded game {game_number} {} - {}, ply {ply}/{game_length}",
+            game.tag("White").unwrap_or("?"),
+            game.tag("Black").unwrap_or("?")
+        );
+    }
+
     fn draw_board_command(context: &mut ContextVariables, args: &[String]) {
         context.board.draw_board();
     }
@@ -202,6 +249,7 @@ impl Commands {
         context.search.lock().unwrap().reuse_tree(&context.board, &*context.previous_board.lock().unwrap());
 
         let board = context.board;
+        let history = context.history.clone();
         let rules_final = rules;
         let search_clone = Arc::clone(&context.search);
         let previous_board_clone = Arc::clone(&context.previous_board);
@@ -209,9 +257,9 @@ impl Commands {
         *context.interruption_token.write().unwrap() = false;
         thread::spawn(move || {
             let result = if uci_initialized {
-                search_clone.lock().unwrap().run::<false>(rules_final, &board)
+                search_clone.lock().unwrap().run::<false>(rules_final, &board, &history)
             } else {
-                search_clone.lock().unwrap().run::<true>(rules_final, &board)
+                search_clone.lock().unwrap().run::<true>(rules_final, &board, &history)
             };
             println!("bestmove {}", result.to_string());
             *previous_board_clone.lock().unwrap() = board;
@@ -223,15 +271,16 @@ impl Commands {
     }
 
     fn tree_command(context: &mut ContextVariables, args: &[String]) {
+        //Tree is rooted in the position of the last search
+        let board = *context.previous_board.lock().unwrap();
         match args.len() {
-            0 => context.search.lock().unwrap().tree().draw_tree_from_root(1),
-            1 => context.search.lock().unwrap().tree().draw_tree_from_root(args[0].parse::<i32>().unwrap()),
-            2 => context
-                .search
-                .lock()
-                .unwrap()
-                .tree()
-                .draw_tree_from_node(args[1].parse::<i32>().unwrap(), args[0].parse::<i32>().unwrap()),
+            0 => context.search.lock().unwrap().tree().draw_tree_from_root(&board, 1),
+            1 => context.search.lock().unwrap().tree().draw_tree_from_root(&board, args[0].parse::<i32>().unwrap()),
+            2 => context.search.lock().unwrap().tree().draw_tree_from_node(
+                &board,
+                args[1].parse::<i32>().unwrap(),
+                args[0].parse::<i32>().unwrap(),
+            ),
             _ => return,
         }
     }
@@ -252,6 +301,15 @@ impl Commands {
         Perft::execute::<false>(&context.board, args[0].parse().unwrap_or_default(), true);
     }
 
+    fn perft_suite_command(_: &mut ContextVariables, args: &[String]) {
+        if args.is_empty() {
+            return;
+        }
+
+        let max_depth = args.get(1).and_then(|depth| depth.parse().ok()).unwrap_or(u8::MAX);
+        Perft::run_suite(&args[0], max_depth);
+    }
+
     fn bench_command(context: &mut ContextVariables, args: &[String]) {
         if args.len() != 1 {
             return;
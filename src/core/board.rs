@@ -5,6 +5,7 @@ use crate::{
         core_structs::{CastleRights, Move, Piece, Side, Square},
         zobrist::ZobristKey,
     },
+    eval::ValueEvaluator,
     mcts::qsearch,
 };
 use colored::*;
@@ -23,7 +24,21 @@ pub struct Board {
     pub en_passant: Square,
     pub side_to_move: Side,
     pub zobrist: ZobristKey,
-    move_history: MoveHistory,
+}
+
+//State of the board that can't be recovered from the position after the move
+#[allow(unused)]
+#[derive(Copy, Clone, PartialEq)]
+pub struct MoveUndo {
+    mv: Move,
+    captured_piece: usize,
+    castle_rights: CastleRights,
+    en_passant: Square,
+    half_moves: u8,
+    zobrist: ZobristKey,
+    checkers: Bitboard,
+    ortographic_pins: Bitboard,
+    diagonal_pins: Bitboard,
 }
 
 impl Board {
@@ -39,7 +54,6 @@ impl Board {
             en_passant: Square::NULL,
             side_to_move: Side::WHITE,
             zobrist: ZobristKey::NULL,
-            move_history: MoveHistory::new(),
         }
     }
 
@@ -211,15 +225,10 @@ impl Board {
         pawns && major_pieces && white_minor_pieces && black_minor_pieces
     }
 
+    //Position counts as drawn on its first repetition
     #[inline]
-    pub fn three_fold(&self) -> bool {
-        let mut appearance_count = 0;
-        for mv_key in self.move_history.range().rev() {
-            if self.move_history[mv_key] == self.zobrist.key {
-                appearance_count += 1;
-            }
-        }
-        appearance_count >= 2
+    pub fn three_fold(&self, history: &MoveHistory) -> bool {
+        history.count(self) > 0
     }
 
     pub fn make_move(&mut self, mv: Move) {
@@ -240,10 +249,8 @@ impl Board {
         self.set_piece_on_square(to_square, moving_piece.1, destination_piece);
 
         let remove_castle_rights = |board: &mut Board| {
-            board.castle_rights.remove_right(CastleRights::WHITE_KING + castle_rights_offset);
-            board.castle_rights.remove_right(CastleRights::WHITE_QUEEN + castle_rights_offset);
-            board.zobrist.update_castle_rights_hash((CastleRights::WHITE_KING + castle_rights_offset) as usize);
-            board.zobrist.update_castle_rights_hash((CastleRights::WHITE_QUEEN + castle_rights_offset) as usize);
+            board.remove_castle_right(CastleRights::WHITE_KING + castle_rights_offset);
+            board.remove_castle_right(CastleRights::WHITE_QUEEN + castle_rights_offset);
         };
 
         if mv.is_king_castle() {
@@ -269,11 +276,9 @@ impl Board {
             let queen_rook_position = Square::A1 + square_value_offset;
 
             if from_square == king_rook_position {
-                self.castle_rights.remove_right(CastleRights::WHITE_KING + castle_rights_offset);
-                self.zobrist.update_castle_rights_hash((CastleRights::WHITE_KING + castle_rights_offset) as usize);
+                self.remove_castle_right(CastleRights::WHITE_KING + castle_rights_offset);
             } else if from_square == queen_rook_position {
-                self.castle_rights.remove_right(CastleRights::WHITE_QUEEN + castle_rights_offset);
-                self.zobrist.update_castle_rights_hash((CastleRights::WHITE_QUEEN + castle_rights_offset) as usize);
+                self.remove_castle_right(CastleRights::WHITE_QUEEN + castle_rights_offset);
             }
         }
         if target_piece.0 == Piece::ROOK {
@@ -281,30 +286,24 @@ impl Board {
             let queen_rook_position = Square::A1 + (self.side_to_move.opposite() * 56);
 
             if to_square == king_rook_position {
-                self.castle_rights.remove_right(CastleRights::WHITE_KING + (self.side_to_move.opposite() * 2) as u8);
-                self.zobrist.update_castle_rights_hash(
-                    (CastleRights::WHITE_KING + (self.side_to_move.opposite() * 2) as u8) as usize,
-                );
+                self.remove_castle_right(CastleRights::WHITE_KING + (self.side_to_move.opposite() * 2) as u8);
             } else if to_square == queen_rook_position {
-                self.castle_rights.remove_right(CastleRights::WHITE_QUEEN + (self.side_to_move.opposite() * 2) as u8);
-                self.zobrist.update_castle_rights_hash(
-                    (CastleRights::WHITE_QUEEN + (self.side_to_move.opposite() * 2) as u8) as usize,
-                );
+                self.remove_castle_right(CastleRights::WHITE_QUEEN + (self.side_to_move.opposite() * 2) as u8);
             }
         }
 
+        if self.en_passant != Square::NULL {
+            self.zobrist.update_en_passant_hash(self.en_passant);
+            self.en_passant = Square::NULL;
+        }
         if mv.is_double_push() {
             self.en_passant = from_square ^ 24;
             self.zobrist.update_en_passant_hash(self.en_passant);
-        } else if self.en_passant != Square::NULL {
-            self.zobrist.update_en_passant_hash(self.en_passant);
-            self.en_passant = Square::NULL;
         }
 
         self.half_moves += 1;
         if mv.is_capture() || moving_piece.0 == Piece::PAWN {
             self.half_moves = 0;
-            self.move_history.clear();
         }
 
         self.side_to_move.mut_flip();
@@ -313,8 +312,107 @@ impl Board {
         self.checkers = Attacks::generate_checkers_mask(&self);
         self.ortographic_pins = Attacks::generate_ortographic_pins_mask(&self);
         self.diagonal_pins = Attacks::generate_diagonal_pins_mask(&self);
+    }
+
+    //Makes the move in place and returns the record needed to take it back with `unmake_move`
+    #[allow(unused)]
+    pub fn make_move_with_undo(&mut self, mv: Move) -> MoveUndo {
+        let target_piece_square = if mv.is_en_passant() { mv.get_to_square() ^ 8 } else { mv.get_to_square() };
+        let undo = MoveUndo {
+            mv,
+            captured_piece: self.get_piece_on_square(target_piece_square).0,
+            castle_rights: self.castle_rights,
+            en_passant: self.en_passant,
+            half_moves: self.half_moves,
+            zobrist: self.zobrist,
+            checkers: self.checkers,
+            ortographic_pins: self.ortographic_pins,
+            diagonal_pins: self.diagonal_pins,
+        };
+
+        self.make_move(mv);
+        undo
+    }
+
+    //Passes the turn to the opponent. Position can't be in check, otherwise the side that passed
+    //could have its king captured
+    #[allow(unused)]
+    pub fn make_null_move(&mut self) -> MoveUndo {
+        let undo = MoveUndo {
+            mv: Move::NULL,
+            captured_piece: Piece::NONE,
+            castle_rights: self.castle_rights,
+            en_passant: self.en_passant,
+            half_moves: self.half_moves,
+            zobrist: self.zobrist,
+            checkers: self.checkers,
+            ortographic_pins: self.ortographic_pins,
+            diagonal_pins: self.diagonal_pins,
+        };
+
+        if self.en_passant != Square::NULL {
+            self.zobrist.update_en_passant_hash(self.en_passant);
+            self.en_passant = Square::NULL;
+        }
+
+        self.half_moves += 1;
+        self.side_to_move.mut_flip();
+        self.zobrist.update_side_to_move_hash();
+
+        self.checkers = Bitboard::EMPTY;
+        self.ortographic_pins = Attacks::generate_ortographic_pins_mask(self);
+        self.diagonal_pins = Attacks::generate_diagonal_pins_mask(self);
+
+        undo
+    }
+
+    //Takes back the last move or null move made with `make_move_with_undo` or `make_null_move`.
+    //Pieces are moved back, everything else is restored from the record
+    #[allow(unused)]
+    pub fn unmake_move(&mut self, undo: &MoveUndo) {
+        self.side_to_move.mut_flip();
+
+        let mv = undo.mv;
+        if mv != Move::NULL {
+            let side = self.side_to_move;
+            let from_square = mv.get_from_square();
+            let to_square = mv.get_to_square();
+            let moved_piece = self.get_piece_on_square(to_square).0;
+
+            self.remove_piece_on_square(to_square, side, moved_piece);
+            self.set_piece_on_square(from_square, side, if mv.is_promotion() { Piece::PAWN } else { moved_piece });
+
+            if undo.captured_piece != Piece::NONE {
+                let target_piece_square = if mv.is_en_passant() { to_square ^ 8 } else { to_square };
+                self.set_piece_on_square(target_piece_square, side.flipped(), undo.captured_piece);
+            }
+
+            let square_value_offset = side.current() * 56;
+            if mv.is_king_castle() {
+                self.remove_piece_on_square(Square::F1 + square_value_offset, side, Piece::ROOK);
+                self.set_piece_on_square(Square::H1 + square_value_offset, side, Piece::ROOK);
+            } else if mv.is_queen_castle() {
+                self.remove_piece_on_square(Square::D1 + square_value_offset, side, Piece::ROOK);
+                self.set_piece_on_square(Square::A1 + square_value_offset, side, Piece::ROOK);
+            }
+        }
 
-        self.move_history.push(&self.zobrist);
+        self.castle_rights = undo.castle_rights;
+        self.en_passant = undo.en_passant;
+        self.half_moves = undo.half_moves;
+        self.zobrist = undo.zobrist;
+        self.checkers = undo.checkers;
+        self.ortographic_pins = undo.ortographic_pins;
+        self.diagonal_pins = undo.diagonal_pins;
+    }
+
+    //Rights are hashed only while they are present, removing a missing right can't touch the key
+    #[inline]
+    fn remove_castle_right(&mut self, right: u8) {
+        if self.castle_rights.has_right(right) {
+            self.castle_rights.remove_right(right);
+            self.zobrist.update_castle_rights_hash(right as usize);
+        }
     }
 
     #[allow(dead_code)]
@@ -342,7 +440,7 @@ impl Board {
         info.push(half_moves.as_str());
         let in_check = format!("In Check: {}", self.is_in_check());
         info.push(in_check.as_str());
-        let eval = format!("Evaluation: {}", qsearch(&self, -30_000, 30_000, 0));
+        let eval = format!("Evaluation: {}", qsearch(&self, &ValueEvaluator::current(), -30_000, 30_000, 0));
         info.push(eval.as_str());
 
         let mut result = " ------------------------\n".to_string();
@@ -377,15 +475,20 @@ impl Board {
     pub fn from_datapack(data: &[Bitboard; 12], side_to_move: u8) -> Board {
         let mut result = Board::new();
 
+        //Pieces are placed one by one, so the zobrist key of pieces and side to move is filled in as well.
+        //Castle rights and en passant square are not part of the datapack
         for piece_color in 0..2 {
             for piece_index in 0..6 {
-                let piece_board = data[piece_color * 6 + piece_index];
-                result.piece_maps[piece_color] |= piece_board;
-                result.pieces[piece_index] |= piece_board;
+                for square in data[piece_color * 6 + piece_index] {
+                    result.set_piece_on_square(square, Side::from_raw(piece_color), piece_index + 1);
+                }
             }
         }
 
         result.side_to_move = Side::from_raw(side_to_move as usize);
+        if result.side_to_move == Side::BLACK {
+            result.zobrist.update_side_to_move_hash();
+        }
 
         result
     }
@@ -547,8 +650,8 @@ pub fn create_board(fen: &str) -> Board {
     }
 
     board.en_passant = Square::NULL;
-    if splits[3] != "-" {
-        board.en_passant = Square::from_string(splits[3]);
+    if let Some(en_passant) = Square::from_string(splits[3]) {
+        board.en_passant = en_passant;
         board.zobrist.update_en_passant_hash(board.en_passant);
     }
 
@@ -562,8 +665,91 @@ pub fn create_board(fen: &str) -> Board {
     board.ortographic_pins = Attacks::generate_ortographic_pins_mask(&board);
     board.diagonal_pins = Attacks::generate_diagonal_pins_mask(&board);
 
-    board.move_history = MoveHistory::new();
-    board.move_history.push(&board.zobrist);
-
     return board;
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use super::*;
+    use crate::core::{MoveList, MoveProvider};
+
+    const FENS: [&str; 6] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    ];
+
+    //Plays seeded random games from the positions and calls the check in every position on the way.
+    //Returns moves and undo records of every game, so it can be unwound
+    fn play_random_games(mut check: impl FnMut(&Board, &MoveList)) -> Vec<(Board, Vec<(Board, MoveUndo)>)> {
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let mut games = Vec::new();
+
+        for game in 0..60 {
+            let start = create_board(FENS[game % FENS.len()]);
+            let mut board = start;
+            let mut played = Vec::new();
+
+            while played.len() < 200 && board.half_moves < 100 {
+                let mut move_list = MoveList::new();
+                MoveProvider::generate_moves::<false>(&mut move_list, &board);
+                check(&board, &move_list);
+
+                let Some(&mv) = move_list.choose(&mut rng) else {
+                    break;
+                };
+                played.push((board, board.make_move_with_undo(mv)));
+            }
+
+            games.push((board, played));
+        }
+
+        games
+    }
+
+    #[test]
+    fn unmake_restores_every_legal_move() {
+        play_random_games(|board, move_list| {
+            for &mv in move_list.iter() {
+                let mut copied = *board;
+                copied.make_move(mv);
+
+                let mut unmade = *board;
+                let undo = unmade.make_move_with_undo(mv);
+                assert!(unmade == copied, "make of {} differs in {}", mv.to_string(), board.get_fen());
+
+                unmade.unmake_move(&undo);
+                assert!(unmade == *board, "unmake of {} failed in {}", mv.to_string(), board.get_fen());
+            }
+        });
+    }
+
+    #[test]
+    fn unmake_restores_null_move() {
+        play_random_games(|board, _| {
+            if board.is_in_check() {
+                return;
+            }
+
+            let mut unmade = *board;
+            let undo = unmade.make_null_move();
+            unmade.unmake_move(&undo);
+            assert!(unmade == *board, "unmake of null move failed in {}", board.get_fen());
+        });
+    }
+
+    #[test]
+    fn unmake_unwinds_whole_game() {
+        for (mut board, mut played) in play_random_games(|_, _| {}) {
+            while let Some((previous, undo)) = played.pop() {
+                board.unmake_move(&undo);
+                assert!(board == previous, "unmake failed when unwinding to {}", previous.get_fen());
+            }
+        }
+    }
+}
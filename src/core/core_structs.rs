@@ -254,11 +254,13 @@ impl Square {
         return format!("{}{}", ('a' as usize + file) as u8 as char, rank);
     }
 
-    pub fn from_string(square: &str) -> Square {
-        let signatures: Vec<char> = square.chars().collect();
-        let file = (signatures[0] as u8 - 'a' as u8).into();
-        let rank = (signatures[1].to_string().parse::<u8>().unwrap() - 1).into();
-        Square::from_coords(rank, file)
+    pub fn from_string(square: &str) -> Option<Square> {
+        match square.as_bytes() {
+            &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] => {
+                Some(Square::from_coords(usize::from(rank - b'1'), usize::from(file - b'a')))
+            }
+            _ => None,
+        }
     }
 }
 impl Add<usize> for Square {
@@ -0,0 +1,265 @@
+use std::fmt::{Display, Formatter};
+
+use crate::core::{
+    attacks::Attacks,
+    bitboard::Bitboard,
+    board::Board,
+    core_structs::{CastleRights, Move, Piece, Side, Square},
+    rays::Ray,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MoveParseError {
+    InvalidFormat,
+    IllegalMove,
+    AmbiguousMove,
+}
+
+impl Display for MoveParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveParseError::InvalidFormat => write!(f, "invalid move format"),
+            MoveParseError::IllegalMove => write!(f, "illegal move"),
+            MoveParseError::AmbiguousMove => write!(f, "ambiguous move"),
+        }
+    }
+}
+
+impl std::error::Error for MoveParseError {}
+
+impl Move {
+    //Parses move in long algebraic notation (`e2e4`, `e7e8q`, castling as `e1g1`). Flags are taken
+    //from the position, so the result is the same move the move generator produces
+    pub fn from_uci(board: &Board, text: &str) -> Result<Move, MoveParseError> {
+        if !text.is_ascii() || (text.len() != 4 && text.len() != 5) {
+            return Err(MoveParseError::InvalidFormat);
+        }
+
+        let from_square = Square::from_string(&text[0..2]).ok_or(MoveParseError::InvalidFormat)?;
+        let to_square = Square::from_string(&text[2..4]).ok_or(MoveParseError::InvalidFormat)?;
+        let promotion_mask = match text.as_bytes().get(4) {
+            None => 0,
+            Some(b'n') => Move::PROMOTION_KNIGHT_MASK,
+            Some(b'b') => Move::PROMOTION_BISHOP_MASK,
+            Some(b'r') => Move::PROMOTION_ROOK_MASK,
+            Some(b'q') => Move::PROMOTION_QUEEN_MASK,
+            Some(_) => return Err(MoveParseError::InvalidFormat),
+        };
+
+        //Promotion suffix is only valid on a pawn move to the last rank, otherwise it would be dropped
+        //by the branches below (`e1g1q` read as castling)
+        let piece = board.get_piece_on_square(from_square).0;
+        let promotion_rank = Bitboard::RANK_8 >> (board.side_to_move.current() * 56) as u32;
+        if promotion_mask != 0 && (piece != Piece::PAWN || !promotion_rank.get_bit(to_square)) {
+            return Err(MoveParseError::InvalidFormat);
+        }
+
+        let capture_mask = if board.get_opponent_occupancy().get_bit(to_square) { Move::CAPTURE_MASK } else { 0 };
+        let square_offset = board.side_to_move.current() * 56;
+
+        let mask = if piece == Piece::PAWN && to_square == board.en_passant {
+            Move::CAPTURE_MASK | Move::EN_PASSANT_MASK
+        } else if piece == Piece::PAWN && from_square.get_value().abs_diff(to_square.get_value()) == 16 {
+            Move::DOUBLE_PUSH_MASK
+        } else if piece == Piece::KING && from_square == Square::E1 + square_offset {
+            if to_square == Square::G1 + square_offset {
+                Move::KING_CASTLE_MASK
+            } else if to_square == Square::C1 + square_offset {
+                Move::QUEEN_CASTLE_MASK
+            } else {
+                capture_mask
+            }
+        } else {
+            promotion_mask | capture_mask
+        };
+
+        let mv = Move::from_squares(from_square, to_square, mask);
+        if board.is_legal(mv) {
+            Ok(mv)
+        } else {
+            Err(MoveParseError::IllegalMove)
+        }
+    }
+}
+
+impl Board {
+    //Checks if the move could be produced by the move generator in this position, if leaving own
+    //king in check was allowed. Flags of the move have to match the position as well
+    pub fn is_pseudo_legal(&self, mv: Move) -> bool {
+        let side = self.side_to_move;
+        let from_square = mv.get_from_square();
+        let to_square = mv.get_to_square();
+        let (piece, piece_side) = self.get_piece_on_square(from_square);
+        let flags = mv.get_value() & 0xF000;
+
+        if mv == Move::NULL || piece == Piece::NONE || piece_side != side {
+            return false;
+        }
+
+        //Capture flag combined with castle flags is not used by any move
+        if flags == Move::CAPTURE_MASK | Move::KING_CASTLE_MASK || flags == Move::CAPTURE_MASK | Move::QUEEN_CASTLE_MASK
+        {
+            return false;
+        }
+
+        if self.get_allied_occupancy().get_bit(to_square) {
+            return false;
+        }
+
+        if mv.is_king_castle() || mv.is_queen_castle() {
+            return piece == Piece::KING && self.is_castle_pseudo_legal(mv);
+        }
+
+        if mv.is_en_passant() {
+            return piece == Piece::PAWN
+                && to_square == self.en_passant
+                && Attacks::get_pawn_attacks_for_square(from_square, side).get_bit(to_square);
+        }
+
+        if mv.is_capture() != self.get_opponent_occupancy().get_bit(to_square) {
+            return false;
+        }
+
+        if piece != Piece::PAWN {
+            if mv.is_promotion() || mv.is_double_push() {
+                return false;
+            }
+
+            let occupancy = self.get_occupancy();
+            let attacks = match piece {
+                Piece::KNIGHT => Attacks::get_knight_attacks_for_square(from_square),
+                Piece::BISHOP => Attacks::get_bishop_attacks_for_square(from_square, occupancy),
+                Piece::ROOK => Attacks::get_rook_attacks_for_square(from_square, occupancy),
+                Piece::QUEEN => {
+                    Attacks::get_bishop_attacks_for_square(from_square, occupancy)
+                        | Attacks::get_rook_attacks_for_square(from_square, occupancy)
+                }
+                _ => Attacks::get_king_attacks_for_square(from_square),
+            };
+            return attacks.get_bit(to_square);
+        }
+
+        let promotion_rank = Bitboard::RANK_8 >> (side.current() * 56) as u32;
+        if mv.is_promotion() != promotion_rank.get_bit(to_square) {
+            return false;
+        }
+
+        if mv.is_capture() {
+            return Attacks::get_pawn_attacks_for_square(from_square, side).get_bit(to_square);
+        }
+
+        let push_square = Move::PAWN_MOVES[side.current()][from_square.get_value()];
+        if mv.is_double_push() {
+            let start_rank = Bitboard::RANK_2 << (side.current() * 40) as u32;
+            let middle_square = from_square ^ 24;
+            return start_rank.get_bit(from_square)
+                && !self.get_occupancy().get_bit(middle_square)
+                && Move::PAWN_MOVES[side.current()][middle_square.get_value()].get_bit(to_square);
+        }
+
+        push_square.get_bit(to_square)
+    }
+
+    //Checks if the move is legal without generating all moves of the position
+    pub fn is_legal(&self, mv: Move) -> bool {
+        if !self.is_pseudo_legal(mv) {
+            return false;
+        }
+
+        let opponent = self.side_to_move.flipped();
+        if mv.is_king_castle() || mv.is_queen_castle() {
+            let king_square = mv.get_from_square();
+            let king_path = Ray::get_ray(king_square, mv.get_to_square());
+            return !self.is_in_check() && !self.any_squares_attacked(king_path, opponent);
+        }
+
+        let (pieces, occupancy) = self.pieces_after_move(mv);
+        let king_square = if self.get_piece_on_square(mv.get_from_square()).0 == Piece::KING {
+            mv.get_to_square()
+        } else {
+            self.get_king_square(self.side_to_move)
+        };
+
+        !is_attacked_by(king_square, opponent, &pieces[opponent.current()], occupancy)
+    }
+
+    //Checks if the move, expected to be legal, puts the opponent in check, directly or by
+    //discovering an attack of another piece
+    pub fn gives_check(&self, mv: Move) -> bool {
+        let (pieces, occupancy) = self.pieces_after_move(mv);
+        let opponent_king = self.get_king_square(self.side_to_move.flipped());
+        is_attacked_by(opponent_king, self.side_to_move, &pieces[self.side_to_move.current()], occupancy)
+    }
+
+    fn is_castle_pseudo_legal(&self, mv: Move) -> bool {
+        let square_offset = self.side_to_move.current() * 56;
+        let castle_rights_offset = (self.side_to_move.current() * 2) as u8;
+        let (right, king_destination, rook_square) = if mv.is_king_castle() {
+            (CastleRights::WHITE_KING, Square::G1, Square::H1)
+        } else {
+            (CastleRights::WHITE_QUEEN, Square::C1, Square::A1)
+        };
+
+        let king_square = Square::E1 + square_offset;
+        let king_destination = king_destination + square_offset;
+        let rook_square = rook_square + square_offset;
+        if mv.get_from_square() != king_square
+            || mv.get_to_square() != king_destination
+            || !self.castle_rights.has_right(right + castle_rights_offset)
+            || !self.get_piece_mask(Piece::ROOK, self.side_to_move).get_bit(rook_square)
+        {
+            return false;
+        }
+
+        let path = Ray::get_ray(king_square, king_destination) | Ray::get_ray(king_square, rook_square);
+        (path & self.get_occupancy().exclude(rook_square)).is_empty()
+    }
+
+    //Pieces of both sides and occupancy after the move, for castling the rook is moved as well
+    fn pieces_after_move(&self, mv: Move) -> ([[Bitboard; 6]; 2], Bitboard) {
+        let side = self.side_to_move.current();
+        let from_square = mv.get_from_square();
+        let to_square = mv.get_to_square();
+        let moving_piece = self.get_piece_on_square(from_square).0;
+        let destination_piece = if mv.is_promotion() { mv.get_promotion_piece() } else { moving_piece };
+        let target_piece_square = if mv.is_en_passant() { to_square ^ 8 } else { to_square };
+
+        let mut pieces = [[Bitboard::EMPTY; 6]; 2];
+        for (side_index, side_pieces) in pieces.iter_mut().enumerate() {
+            for (piece_index, piece_mask) in side_pieces.iter_mut().enumerate() {
+                *piece_mask = self.get_piece_mask(piece_index + 1, Side::from_raw(side_index));
+            }
+        }
+
+        for piece_mask in &mut pieces[1 - side] {
+            *piece_mask = piece_mask.exclude(target_piece_square);
+        }
+
+        pieces[side][moving_piece - 1] = pieces[side][moving_piece - 1].exclude(from_square);
+        pieces[side][destination_piece - 1] = pieces[side][destination_piece - 1].include(to_square);
+
+        if mv.is_king_castle() || mv.is_queen_castle() {
+            let square_offset = side * 56;
+            let (rook_square, rook_destination) =
+                if mv.is_king_castle() { (Square::H1, Square::F1) } else { (Square::A1, Square::D1) };
+            let rooks = &mut pieces[side][Piece::ROOK - 1];
+            *rooks = rooks.exclude(rook_square + square_offset).include(rook_destination + square_offset);
+        }
+
+        let occupancy = pieces.iter().flatten().fold(Bitboard::EMPTY, |occupancy, &mask| occupancy | mask);
+        (pieces, occupancy)
+    }
+}
+
+//Checks if the square is attacked by given pieces of the attacker, with sliders blocked by given
+//occupancy instead of the one on the board
+fn is_attacked_by(square: Square, attacker: Side, pieces: &[Bitboard; 6], occupancy: Bitboard) -> bool {
+    let bishops = pieces[Piece::BISHOP - 1] | pieces[Piece::QUEEN - 1];
+    let rooks = pieces[Piece::ROOK - 1] | pieces[Piece::QUEEN - 1];
+
+    (Attacks::get_bishop_attacks_for_square(square, occupancy) & bishops).is_not_empty()
+        || (Attacks::get_rook_attacks_for_square(square, occupancy) & rooks).is_not_empty()
+        || (Attacks::get_knight_attacks_for_square(square) & pieces[Piece::KNIGHT - 1]).is_not_empty()
+        || (Attacks::get_pawn_attacks_for_square(square, attacker.flipped()) & pieces[Piece::PAWN - 1]).is_not_empty()
+        || (Attacks::get_king_attacks_for_square(square) & pieces[Piece::KING - 1]).is_not_empty()
+}
@@ -3,8 +3,10 @@ mod bit_ops;
 mod bitboard;
 mod board;
 mod core_structs;
+mod legality;
 mod move_history;
 mod movegen;
+mod notation;
 mod rays;
 mod zobrist;
 
@@ -13,6 +15,11 @@ pub use attacks::Attacks;
 pub use bit_ops::get_bit;
 #[allow(unused_imports)]
 pub use bitboard::Bitboard;
+#[allow(unused_imports)]
+pub use board::MoveUndo;
 pub use board::{create_board, Board};
 pub use core_structs::{Move, MoveList, Piece, Side, Square};
+#[allow(unused_imports)]
+pub use legality::MoveParseError;
+pub use move_history::MoveHistory;
 pub use movegen::MoveProvider;
@@ -1,46 +1,55 @@
-use std::ops::{Index, IndexMut, Range};
+use super::board::Board;
 
-use super::zobrist::ZobristKey;
-
-#[derive(Clone, Copy, PartialEq)]
+//Zobrist keys of positions played before the current one. Board is copied on every move, so the
+//history is kept outside of it, by the game or search that plays the moves
+#[derive(Clone, Default, PartialEq)]
 pub struct MoveHistory {
-    values: [u64; 256],
-    length: u8,
+    keys: Vec<u64>,
 }
 
 impl MoveHistory {
     pub fn new() -> Self {
-        Self { values: [0; 256], length: 0 }
+        Self { keys: Vec::with_capacity(256) }
     }
 
+    //Has to be called with the position before the move is made on it
     #[inline]
-    pub fn push(&mut self, key: &ZobristKey) {
-        let last_index = self.length;
-        self[last_index] = key.key;
-        self.length += 1;
+    pub fn push(&mut self, board: &Board) {
+        self.keys.push(board.zobrist.key);
     }
 
     #[inline]
-    pub fn clear(&mut self) {
-        self.length = 0;
+    pub fn len(&self) -> usize {
+        self.keys.len()
     }
 
+    #[allow(unused)]
     #[inline]
-    pub fn range(&self) -> Range<u8> {
-        0..self.length
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
     }
-}
 
-impl Index<u8> for MoveHistory {
-    type Output = u64;
+    #[inline]
+    pub fn truncate(&mut self, length: usize) {
+        self.keys.truncate(length);
+    }
 
-    fn index(&self, index: u8) -> &Self::Output {
-        &self.values[index as usize]
+    #[inline]
+    pub fn clear(&mut self) {
+        self.keys.clear();
     }
-}
 
-impl IndexMut<u8> for MoveHistory {
-    fn index_mut(&mut self, index: u8) -> &mut Self::Output {
-        &mut self.values[index as usize]
+    //Counts appearances of the position since the last irreversible move. Side to move is part
+    //of the key, so only every second position can match
+    #[inline]
+    pub fn count(&self, board: &Board) -> usize {
+        self.keys
+            .iter()
+            .rev()
+            .take(usize::from(board.half_moves))
+            .skip(1)
+            .step_by(2)
+            .filter(|&&key| key == board.zobrist.key)
+            .count()
     }
 }
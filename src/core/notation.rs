@@ -0,0 +1,169 @@
+use crate::core::{
+    board::Board,
+    core_structs::{Move, MoveList, Piece, Square},
+    legality::MoveParseError,
+    movegen::MoveProvider,
+};
+
+const PIECE_LETTERS: [char; 6] = ['P', 'N', 'B', 'R', 'Q', 'K'];
+
+impl Board {
+    //Standard algebraic notation of a legal move (`Nbd7`, `exd5`, `e8=Q+`, `O-O#`)
+    pub fn move_to_san(&self, mv: Move) -> String {
+        let mut san = if mv.is_king_castle() {
+            "O-O".to_string()
+        } else if mv.is_queen_castle() {
+            "O-O-O".to_string()
+        } else {
+            self.move_to_san_body(mv)
+        };
+
+        if self.gives_check(mv) {
+            let mut new_board = *self;
+            new_board.make_move(mv);
+
+            let mut move_list = MoveList::new();
+            MoveProvider::generate_moves::<false>(&mut move_list, &new_board);
+            san.push(if move_list.is_empty() { '#' } else { '+' });
+        }
+
+        san
+    }
+
+    //Moves of the line, starting in this position, separated by spaces
+    pub fn line_to_san(&self, line: &[Move]) -> String {
+        let mut board = *self;
+        let mut result = Vec::with_capacity(line.len());
+        for &mv in line {
+            result.push(board.move_to_san(mv));
+            board.make_move(mv);
+        }
+
+        result.join(" ")
+    }
+
+    //Parses move in standard algebraic notation. Check and annotation suffixes are ignored, `0-0`,
+    //promotions without `=` and over-disambiguated moves are accepted as well
+    #[allow(unused)]
+    pub fn parse_san(&self, text: &str) -> Result<Move, MoveParseError> {
+        let text = text.trim_end_matches(['+', '#', '!', '?']);
+        if !text.is_ascii() || text.len() < 2 {
+            return Err(MoveParseError::InvalidFormat);
+        }
+
+        let mut move_list = MoveList::new();
+        MoveProvider::generate_moves::<false>(&mut move_list, self);
+
+        if matches!(text, "O-O" | "0-0" | "O-O-O" | "0-0-0") {
+            let is_king_castle = text.len() == 3;
+            return move_list
+                .iter()
+                .copied()
+                .find(|mv| if is_king_castle { mv.is_king_castle() } else { mv.is_queen_castle() })
+                .ok_or(MoveParseError::IllegalMove);
+        }
+
+        let bytes = text.as_bytes();
+        let (piece, mut rest) = match PIECE_LETTERS[1..].iter().position(|&letter| letter as u8 == bytes[0]) {
+            Some(index) => (index + 2, &bytes[1..]),
+            None => (Piece::PAWN, bytes),
+        };
+
+        //Destination rank is always a digit, so a letter after it is a promotion piece
+        let mut promotion = Piece::NONE;
+        if let [body @ .., letter] = rest {
+            if letter.is_ascii_alphabetic() {
+                let upper_letter = letter.to_ascii_uppercase();
+                let index = PIECE_LETTERS[1..5].iter().position(|&piece| piece as u8 == upper_letter);
+                promotion = index.ok_or(MoveParseError::InvalidFormat)? + 2;
+                rest = body.strip_suffix(b"=").unwrap_or(body);
+            }
+        }
+
+        let (disambiguation, to_square) = match rest {
+            [prefix @ .., file, rank] => {
+                let square = std::str::from_utf8(&[*file, *rank]).ok().and_then(Square::from_string);
+                (prefix.strip_suffix(b"x").unwrap_or(prefix), square.ok_or(MoveParseError::InvalidFormat)?)
+            }
+            _ => return Err(MoveParseError::InvalidFormat),
+        };
+
+        let mut from_file = None;
+        let mut from_rank = None;
+        for &byte in disambiguation {
+            match byte {
+                b'a'..=b'h' if from_file.is_none() && from_rank.is_none() => from_file = Some(usize::from(byte - b'a')),
+                b'1'..=b'8' if from_rank.is_none() => from_rank = Some(usize::from(byte - b'1')),
+                _ => return Err(MoveParseError::InvalidFormat),
+            }
+        }
+
+        let mut candidates = move_list.iter().copied().filter(|mv| {
+            let from_square = mv.get_from_square();
+            !mv.is_king_castle()
+                && !mv.is_queen_castle()
+                && mv.get_to_square() == to_square
+                && self.get_piece_on_square(from_square).0 == piece
+                && from_file.is_none_or(|file| from_square.get_file() == file)
+                && from_rank.is_none_or(|rank| from_square.get_rank() == rank)
+                && if mv.is_promotion() { mv.get_promotion_piece() == promotion } else { promotion == Piece::NONE }
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(mv), None) => Ok(mv),
+            (Some(_), Some(_)) => Err(MoveParseError::AmbiguousMove),
+            _ => Err(MoveParseError::IllegalMove),
+        }
+    }
+
+    //Piece letter, disambiguation, capture and destination square of a move other than castling
+    fn move_to_san_body(&self, mv: Move) -> String {
+        let from_square = mv.get_from_square();
+        let to_square = mv.get_to_square();
+        let piece = self.get_piece_on_square(from_square).0;
+        let mut san = String::new();
+
+        if piece == Piece::PAWN {
+            if mv.is_capture() {
+                san.push((b'a' + from_square.get_file() as u8) as char);
+            }
+        } else {
+            san.push(PIECE_LETTERS[piece - 1]);
+
+            //Other pieces of the same type, that can move to the same square
+            let mut move_list = MoveList::new();
+            MoveProvider::generate_moves::<false>(&mut move_list, self);
+            let others = move_list
+                .iter()
+                .filter(|other| other.get_to_square() == to_square)
+                .map(|other| other.get_from_square())
+                .filter(|&other| other != from_square && self.get_piece_on_square(other).0 == piece)
+                .collect::<Vec<Square>>();
+
+            if !others.is_empty() {
+                let file = (b'a' + from_square.get_file() as u8) as char;
+                let rank = (b'1' + from_square.get_rank() as u8) as char;
+                if others.iter().all(|other| other.get_file() != from_square.get_file()) {
+                    san.push(file);
+                } else if others.iter().all(|other| other.get_rank() != from_square.get_rank()) {
+                    san.push(rank);
+                } else {
+                    san.push(file);
+                    san.push(rank);
+                }
+            }
+        }
+
+        if mv.is_capture() {
+            san.push('x');
+        }
+        san.push_str(&to_square.to_string());
+
+        if mv.is_promotion() {
+            san.push('=');
+            san.push(PIECE_LETTERS[mv.get_promotion_piece() - 1]);
+        }
+
+        san
+    }
+}
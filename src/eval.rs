@@ -2,10 +2,15 @@ mod pesto;
 mod policy_network;
 mod value_network;
 
-use crate::core::{Board, Move, Piece, Side, Bitboard};
+use crate::core::{Board, Move, Bitboard};
+use crate::options::Options;
 
 use goober::SparseVector;
+use std::cell::RefCell;
+use std::sync::Arc;
 
+#[allow(unused)]
+pub use pesto::{Pesto, PestoWeights};
 #[allow(unused)]
 pub use policy_network::PolicyNetwork;
 #[allow(unused)]
@@ -19,42 +24,61 @@ pub const VALUE_NETWORK: ValueNetwork =
 pub const POLICY_NETWORK: PolicyNetwork =
     unsafe { std::mem::transmute(*include_bytes!("../resources/nets/policy_008.net")) };
 
-pub struct Evaluation;
-impl Evaluation {
-    #[inline]
-    pub fn evaluate(board: &Board) -> i32 {
-        (VALUE_NETWORK.evaluate(&board) * 400.0) as i32
-    }
-
-    #[inline]
-    pub fn get_policy_value(board: &Board, mv: &Move, inputs: &SparseVector, threats: Bitboard) -> f32 {
-        POLICY_NETWORK.evaluate(&board, &mv, &inputs, threats)
-    }
+//Networks loaded at runtime replace the built-in ones only for searches on the thread that set
+//them, so engines with different networks can play each other in the same process
+#[derive(Default)]
+struct ThreadNetworks {
+    value: Option<Arc<ValueNetwork>>,
+    policy: Option<Arc<PolicyNetwork>>,
+}
 
-    pub fn get_policy_inputs(board: &Board) -> SparseVector {
-        let mut result = SparseVector::with_capacity(32);
-        let flip = board.side_to_move == Side::BLACK;
+thread_local! {
+    static THREAD_NETWORKS: RefCell<ThreadNetworks> = RefCell::new(ThreadNetworks::default());
+}
 
-        for piece in Piece::PAWN..=Piece::KING {
-            let piece_index = 64 * (piece - Piece::PAWN);
+//Value evaluation chosen once per search, so leaves are evaluated without looking up the PestoEval
+//option and networks of the thread
+#[derive(Clone)]
+pub enum ValueEvaluator {
+    Pesto,
+    Network(Option<Arc<ValueNetwork>>),
+}
 
-            let mut stm_bitboard = board.get_piece_mask(piece, board.side_to_move);
-            let mut nstm_bitboard = board.get_piece_mask(piece, board.side_to_move.flipped());
+impl ValueEvaluator {
+    pub fn current() -> Self {
+        //PeSTO is tuned to the same scale as the value network, so it can replace it when speed
+        //matters more than accuracy
+        if Options::pesto_eval() {
+            return ValueEvaluator::Pesto;
+        }
 
-            if flip {
-                stm_bitboard = stm_bitboard.flip();
-                nstm_bitboard = nstm_bitboard.flip();
-            }
+        ValueEvaluator::Network(THREAD_NETWORKS.with(|networks| networks.borrow().value.clone()))
+    }
 
-            for square in stm_bitboard {
-                result.push(piece_index + square.get_value());
-            }
+    #[inline]
+    pub fn evaluate(&self, board: &Board) -> i32 {
+        let output = match self {
+            ValueEvaluator::Pesto => return Pesto::evaluate(board),
+            ValueEvaluator::Network(Some(network)) => network.evaluate(board),
+            ValueEvaluator::Network(None) => VALUE_NETWORK.evaluate(board),
+        };
+        (output * 400.0) as i32
+    }
+}
 
-            for square in nstm_bitboard {
-                result.push(384 + piece_index + square.get_value());
-            }
-        }
+pub struct Evaluation;
+impl Evaluation {
+    #[inline]
+    pub fn get_policy_value(board: &Board, mv: &Move, inputs: &SparseVector, threats: Bitboard) -> f32 {
+        THREAD_NETWORKS.with(|networks| match &networks.borrow().policy {
+            Some(network) => network.evaluate(board, mv, inputs, threats),
+            None => POLICY_NETWORK.evaluate(board, mv, inputs, threats),
+        })
+    }
 
-        result
+    //None restores the built-in network
+    #[allow(unused)]
+    pub fn set_thread_networks(value: Option<Arc<ValueNetwork>>, policy: Option<Arc<PolicyNetwork>>) {
+        THREAD_NETWORKS.with(|networks| *networks.borrow_mut() = ThreadNetworks { value, policy });
     }
 }
@@ -95,27 +95,76 @@ static ENDGAME_PIECE_TABLE: [[i32; 64]; 6] = [
     ENDGAME_KING_TABLE,
 ];
 
+//Piece values and tables of the tapered evaluation, from white perspective with a8 as the first
+//square. Tables are indexed by piece, starting with the pawn
+#[allow(unused)]
+#[derive(Clone, Copy)]
+pub struct PestoWeights {
+    pub midgame_piece_values: [i32; 6],
+    pub endgame_piece_values: [i32; 6],
+    pub midgame_tables: [[i32; 64]; 6],
+    pub endgame_tables: [[i32; 64]; 6],
+}
+
 pub struct Pesto;
+#[allow(unused)]
 impl Pesto {
-    #[allow(unused)]
+    pub const MAX_PHASE: i32 = 24;
+
+    pub fn weights() -> PestoWeights {
+        PestoWeights {
+            midgame_piece_values: MIDGAME_PIECE_VALUE,
+            endgame_piece_values: ENDGAME_PIECE_VALUE,
+            midgame_tables: MIDGAME_PIECE_TABLE,
+            endgame_tables: ENDGAME_PIECE_TABLE,
+        }
+    }
+
+    //Calls the method with piece index (pawn is 0), table index and side of every piece. Tuner
+    //reads positions through here, so tuned tables match the evaluation
+    pub fn map_pieces<F: FnMut(usize, usize, Side)>(board: &Board, mut method: F) {
+        for square in board.get_occupancy() {
+            let (piece_index, side) = board.get_piece_on_square(square);
+            let table_index = if side == Side::WHITE { square.get_value() ^ 56 } else { square.get_value() };
+            method(piece_index - 1, table_index, side);
+        }
+    }
+
+    //Midgame weight of the position, from 0 in bare endgames to 24 with all pieces on the board.
+    //Promotions can push the sum past 24, so it's capped
+    pub fn phase(board: &Board) -> i32 {
+        let mut game_phase = 0;
+        Self::map_pieces(board, |piece_index, _, _| game_phase += PHASE[piece_index]);
+        game_phase.min(Self::MAX_PHASE)
+    }
+
+    //Score from white perspective in centipawns
     pub fn get_score(board: &Board) -> i32 {
         let mut midgame_score = 0;
         let mut endgame_score = 0;
         let mut game_phase = 0;
 
-        for square in board.get_occupancy() {
-            let (piece_index, side) = board.get_piece_on_square(square);
-            let piece_table_index = if side == Side::WHITE { square.get_value() ^ 56 } else { square.get_value() };
+        Self::map_pieces(board, |piece_index, table_index, side| {
             let multiplier = (side.current() as i32 * -2) + 1;
-            midgame_score += multiplier
-                * (MIDGAME_PIECE_TABLE[piece_index - 1][piece_table_index] + MIDGAME_PIECE_VALUE[piece_index - 1]);
-            endgame_score += multiplier
-                * (ENDGAME_PIECE_TABLE[piece_index - 1][piece_table_index] + ENDGAME_PIECE_VALUE[piece_index - 1]);
-            game_phase += PHASE[piece_index - 1];
-        }
+            midgame_score +=
+                multiplier * (MIDGAME_PIECE_TABLE[piece_index][table_index] + MIDGAME_PIECE_VALUE[piece_index]);
+            endgame_score +=
+                multiplier * (ENDGAME_PIECE_TABLE[piece_index][table_index] + ENDGAME_PIECE_VALUE[piece_index]);
+            game_phase += PHASE[piece_index];
+        });
+
+        let midgame_phase = game_phase.min(Self::MAX_PHASE);
+        let endgame_phase = Self::MAX_PHASE - midgame_phase;
+        (midgame_score * midgame_phase + endgame_score * endgame_phase) / Self::MAX_PHASE
+    }
 
-        let midgame_phase = game_phase.max(24);
-        let endgame_phase = 24 - midgame_phase;
-        (midgame_score * midgame_phase + endgame_score * endgame_phase) / 24
+    //Score from side to move perspective, in the same scale as the value network evaluation
+    pub fn evaluate(board: &Board) -> i32 {
+        let score = Self::get_score(board);
+        if board.side_to_move == Side::WHITE {
+            score
+        } else {
+            -score
+        }
     }
 }
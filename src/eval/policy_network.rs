@@ -1,8 +1,8 @@
 use goober::{activation, layer::SparseConnected, FeedForwardNetwork, Matrix, SparseVector, Vector};
 
 use crate::{
-    core::{Board, Move, Side, Bitboard},
-    see::SEE
+    core::{Board, Move, Bitboard},
+    features::Features
 };
 
 #[allow(unused)]
@@ -51,15 +51,9 @@ impl PolicyNetwork {
 
     #[inline]
     pub fn evaluate(&self, board: &Board, mv: &Move, inputs: &SparseVector, threats: Bitboard) -> f32 {
-        let flip = if board.side_to_move == Side::WHITE { 0 } else { 56 };
-
-        let threat = usize::from((threats & (1 << mv.get_from_square().get_value())).is_not_empty());
-        let from_subnet = &self.subnets[usize::from(mv.get_from_square().get_value() ^ flip)][threat];
-        let from_vec = from_subnet.out(inputs);
-
-        let see = usize::from(SEE::static_exchange_evaluation(board, *mv, -108));
-        let to_subnet = &self.subnets[64 + usize::from(mv.get_to_square().get_value() ^ flip)][see];
-        let to_vec = to_subnet.out(inputs);
+        let indices = Features::policy_subnets(board, mv, threats);
+        let from_vec = self.subnets[indices.from][indices.threat].out(inputs);
+        let to_vec = self.subnets[64 + indices.to][indices.see].out(inputs);
 
         //let hce = self.hce.out(&Self::get_hce_feats(board, mv))[0];
 
@@ -0,0 +1,93 @@
+use goober::SparseVector;
+
+use crate::core::{Bitboard, Board, Move, Piece, Side, Square};
+use crate::see::SEE;
+
+//Subnets of the policy network used for a move. From and to are square indices from side to
+//move perspective, the to subnet is stored 64 places after the from subnet of the same square
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PolicySubnets {
+    pub from: usize,
+    pub threat: usize,
+    pub to: usize,
+    pub see: usize,
+}
+
+//Inputs of the engine networks. Engine and trainer both read boards through here, so nets are
+//trained on exactly what the engine feeds them
+pub struct Features;
+#[allow(unused)]
+impl Features {
+    pub const POLICY_SEE_THRESHOLD: i32 = -108;
+
+    //Value inputs from side to move perspective, mirrored horizontally when the king of the side
+    //to move stands on the e-h files
+    pub fn map_value_inputs<F: FnMut(usize)>(board: &Board, mut method: F) {
+        let horizontal_mirror = if board.get_king_square(board.side_to_move).get_value() % 8 > 3 { 7 } else { 0 };
+        Self::map_inputs(board, |index| method(index ^ horizontal_mirror));
+    }
+
+    pub fn value_inputs(board: &Board) -> SparseVector {
+        let mut result = SparseVector::with_capacity(32);
+        Self::map_value_inputs(board, |index| result.push(index));
+        result
+    }
+
+    pub fn policy_inputs(board: &Board) -> SparseVector {
+        let mut result = SparseVector::with_capacity(32);
+        Self::map_inputs(board, |index| result.push(index));
+        result
+    }
+
+    //Squares attacked by the opponent, sliders see through the king of the side to move
+    pub fn policy_threats(board: &Board) -> Bitboard {
+        board.get_attack_map(board.side_to_move.flipped())
+    }
+
+    pub fn policy_subnets(board: &Board, mv: &Move, threats: Bitboard) -> PolicySubnets {
+        let flip = if board.side_to_move == Side::WHITE { 0 } else { 56 };
+
+        //Boards rebuilt from training records have no en passant square, it's recovered from the
+        //move so SEE sees the same board as during the search
+        let see = if mv.is_en_passant() && board.en_passant == Square::NULL {
+            let mut board = *board;
+            board.en_passant = mv.get_to_square();
+            SEE::static_exchange_evaluation(&board, *mv, Self::POLICY_SEE_THRESHOLD)
+        } else {
+            SEE::static_exchange_evaluation(board, *mv, Self::POLICY_SEE_THRESHOLD)
+        };
+
+        PolicySubnets {
+            from: mv.get_from_square().get_value() ^ flip,
+            threat: usize::from((threats & (1 << mv.get_from_square().get_value())).is_not_empty()),
+            to: mv.get_to_square().get_value() ^ flip,
+            see: usize::from(see),
+        }
+    }
+
+    //Pieces of the side to move take the first 384 inputs, the board is flipped vertically when
+    //black is to move
+    fn map_inputs<F: FnMut(usize)>(board: &Board, mut method: F) {
+        let flip = board.side_to_move == Side::BLACK;
+
+        for piece in Piece::PAWN..=Piece::KING {
+            let piece_index = 64 * (piece - Piece::PAWN);
+
+            let mut stm_bitboard = board.get_piece_mask(piece, board.side_to_move);
+            let mut nstm_bitboard = board.get_piece_mask(piece, board.side_to_move.flipped());
+
+            if flip {
+                stm_bitboard = stm_bitboard.flip();
+                nstm_bitboard = nstm_bitboard.flip();
+            }
+
+            for square in stm_bitboard {
+                method(piece_index + square.get_value())
+            }
+
+            for square in nstm_bitboard {
+                method(384 + piece_index + square.get_value())
+            }
+        }
+    }
+}
@@ -1,25 +1,37 @@
+mod analyse;
 mod benchmark;
 mod commands;
 mod core;
 mod eval;
+mod features;
 mod mcts;
 mod neural;
 mod options;
 mod perft;
+mod pgn;
 mod search_report;
 mod see;
 
+pub use analyse::Analyser;
 pub use commands::Commands;
 pub use core::Side;
-pub use core::{create_board, get_bit, Bitboard, Board, Move, MoveList, MoveProvider, Square};
+pub use core::{
+    create_board, get_bit, Bitboard, Board, Move, MoveHistory, MoveList, MoveParseError, MoveProvider, MoveUndo, Piece,
+    Square,
+};
 pub use eval::Evaluation;
+pub use eval::{Pesto, PestoWeights};
 pub use eval::PolicyNetwork;
 pub use eval::SubNet;
 pub use eval::ValueNetwork;
+pub use eval::ValueEvaluator;
+pub use features::{Features, PolicySubnets};
 pub use mcts::GameResult;
 pub use mcts::Search;
 pub use mcts::SearchRules;
 pub use mcts::SearchTree;
 pub use neural::DenseLayer;
+pub use options::{Options, TunableOption};
+pub use pgn::{PgnError, PgnGame, PgnMove};
 pub use see::SEE;
 pub use core::Attacks;
@@ -1,14 +1,18 @@
+mod analyse;
 mod benchmark;
 mod commands;
 mod core;
 mod eval;
+mod features;
 mod mcts;
 mod neural;
 mod options;
 mod perft;
+mod pgn;
 mod search_report;
 mod see;
 
+use analyse::Analyser;
 use benchmark::Benchmark;
 use commands::Commands;
 use std::{env, io::stdin, process::Command};
@@ -26,6 +30,11 @@ fn main() {
             });
             return;
         }
+
+        if arg == "analyse" {
+            Analyser::run(&args[index + 1..]);
+            return;
+        }
     }
 
     println!("Javelin v{} by Tomasz Jaworski\n", env!("CARGO_PKG_VERSION"));
@@ -1,3 +1,4 @@
+mod gumbel_root;
 mod node;
 mod phantom_node;
 mod qsearch;
@@ -12,11 +13,11 @@ pub use search_rules::SearchRules;
 pub use search_tree::SearchTree;
 use std::sync::RwLock;
 
-use self::{node::Node, phantom_node::PhantomNode};
+use self::{gumbel_root::GumbelRoot, node::Node, phantom_node::PhantomNode};
 use crate::{
-    core::{Board, Move, MoveList, MoveProvider},
-    eval::Evaluation,
-    options::Options,
+    core::{Board, Move, MoveHistory, MoveList, MoveProvider},
+    eval::{Evaluation, ValueEvaluator},
+    options::{Options, SearchOptions},
     search_report::SearchReport,
 };
 use std::{sync::Arc, time::Instant};
@@ -25,10 +26,22 @@ pub struct Search<const LOG: bool> {
     tree: SearchTree,
     interrupt_token: Option<Arc<RwLock<bool>>>,
     search_info: SearchInfo,
+    gumbel_root: GumbelRoot,
+    history: MoveHistory,
+    options: SearchOptions,
+    evaluator: ValueEvaluator,
 }
 impl<'a, const LOG: bool> Search<LOG> {
     pub fn new(tree: SearchTree, interrupt_token: Option<Arc<RwLock<bool>>>) -> Self {
-        Self { tree, interrupt_token, search_info: SearchInfo::new() }
+        Self {
+            tree,
+            interrupt_token,
+            search_info: SearchInfo::new(),
+            gumbel_root: GumbelRoot::new(),
+            history: MoveHistory::new(),
+            options: SearchOptions::current(),
+            evaluator: ValueEvaluator::current(),
+        }
     }
 
     #[inline]
@@ -41,6 +54,12 @@ impl<'a, const LOG: bool> Search<LOG> {
         &self.search_info
     }
 
+    #[allow(unused)]
+    #[inline]
+    pub fn improved_policy(&self) -> Vec<(Move, f32)> {
+        GumbelRoot::improved_policy(&self.tree)
+    }
+
     pub fn reuse_tree(&mut self, board: &Board, previous_board: &Board) {
         let is_tree_same_size = SearchTree::mem_to_capacity(Options::hash() as usize) == self.tree.capacity();
         if board != previous_board && is_tree_same_size {
@@ -63,7 +82,14 @@ impl<'a, const LOG: bool> Search<LOG> {
         self.search_info = SearchInfo::new();
     }
 
-    pub fn run<const PRETTY_PRINT: bool>(&mut self, search_rules: SearchRules, root_position: &Board) -> Move {
+    //History holds positions of the game played before the root position, it's used to detect
+    //repetitions in the tree
+    pub fn run<const PRETTY_PRINT: bool>(
+        &mut self,
+        search_rules: SearchRules,
+        root_position: &Board,
+        history: &MoveHistory,
+    ) -> Move {
         if PRETTY_PRINT && LOG {
             println!("   Depth   Score    Time      Nodes     Speed        Usage   Pv Line");
         }
@@ -73,12 +99,27 @@ impl<'a, const LOG: bool> Search<LOG> {
         let mut last_time_stamp = 0u128;
         let mut last_report: String = String::new();
 
+        //Options and networks can't change during the search, so they are read once
+        self.options = SearchOptions::current();
+        self.evaluator = ValueEvaluator::current();
+
+        self.history.clone_from(history);
+        let root_history_length = self.history.len();
+
         //If tree is complitly empty we want to reset it in order to spawn
         //and expand root node
         if self.tree.node_count() == 0 {
             self.tree.reset_tree(root_position);
         }
 
+        //Gumbel root selection requires known node budget, so for searches limited by time or depth
+        //we always fall back to PUCT at the root
+        if search_rules.gumbel_root && search_rules.max_nodes > 0 {
+            self.gumbel_root.initialize(&self.tree, search_rules.max_nodes);
+        } else {
+            self.gumbel_root.disable();
+        }
+
         //Iteration loop that breaks, when search rules decide seach should not longer continue
         //or when iteration returns 'true' which is search-break token
         while search_rules.continue_search(&self.search_info, &self.tree) {
@@ -87,6 +128,7 @@ impl<'a, const LOG: bool> Search<LOG> {
             let mut position = *root_position;
             let mut current_depth = 0;
             self.perform_iteration_step(self.tree.root_index(), &mut position, &mut current_depth);
+            self.history.truncate(root_history_length);
 
             if self.search_info.current_iterations % 128 == 0 {
                 self.search_info.time_passed = timer.elapsed().as_millis();
@@ -117,7 +159,7 @@ impl<'a, const LOG: bool> Search<LOG> {
             {
                 self.search_info.time_passed = timer.elapsed().as_millis();
                 if LOG {
-                    self.print_report::<PRETTY_PRINT>(self.search_info, &mut last_report);
+                    self.print_report::<PRETTY_PRINT>(root_position, self.search_info, &mut last_report);
                 }
                 current_avg_depth = current_avg_depth.max(self.search_info.get_avg_depth());
                 last_time_stamp = self.search_info.time_passed;
@@ -128,10 +170,14 @@ impl<'a, const LOG: bool> Search<LOG> {
         //last raport to make sure we don't print duplicates
         self.search_info.time_passed = timer.elapsed().as_millis();
         if LOG {
-            self.print_report::<PRETTY_PRINT>(self.search_info, &mut last_report);
+            self.print_report::<PRETTY_PRINT>(root_position, self.search_info, &mut last_report);
         }
 
-        self.tree.get_best_phantom().mv()
+        if self.gumbel_root.is_active() {
+            self.gumbel_root.best_move(&self.tree)
+        } else {
+            self.tree.get_best_phantom().mv()
+        }
     }
 
     fn perform_iteration_step(
@@ -160,12 +206,14 @@ impl<'a, const LOG: bool> Search<LOG> {
             //On second visit we extend the node, if it wasn't already extended.
             //This allows us to reduce amount of time we evaluate policy net
             if !self.tree[current_node_index].is_extended() {
-                self.tree[current_node_index].expand::<false>(&current_board);
+                self.tree[current_node_index].expand(&current_board, self.options.non_root_pst);
             }
 
             //Select best phantom child (selection returns index of the move from it's parent)
-            //based on PUCT formula
-            let new_child_index = if *current_depth == 1 {
+            //based on PUCT formula, or sequential halving schedule when gumbel root is enabled
+            let new_child_index = if *current_depth == 1 && self.gumbel_root.is_active() {
+                self.gumbel_root.select(&self.tree)
+            } else if *current_depth == 1 {
                 self.select_node::<true>(current_node_index)
             } else {
                 self.select_node::<false>(current_node_index)
@@ -182,6 +230,7 @@ impl<'a, const LOG: bool> Search<LOG> {
                 let selected_node_phantom = self.tree.get_phantom(current_node_index, new_child_index);
                 let mut child_node_index = selected_node_phantom.index();
 
+                self.history.push(current_board);
                 current_board.make_move(selected_node_phantom.mv());
 
                 //If index of corresponding tree node is equal to -1, it means that node
@@ -239,19 +288,19 @@ impl<'a, const LOG: bool> Search<LOG> {
         let mut win_len = 0;
         let mut best = 0;
         let mut max = f32::NEG_INFINITY;
-        let mut c = if ROOT { Options::root_c_value() } else { Options::c_value() };
+        let mut c = if ROOT { self.options.root_c_value } else { self.options.c_value };
 
         //Increase C value as node is visited more
-        let scale = (Options::c_visits_scale() * 128) as u32;
+        let scale = (self.options.c_visits_scale * 128) as u32;
         c *= 1.0 + (((parent_phantom.visits() + scale) / scale) as f32).ln();
 
         //Scale C value with variance of value of a node
         if parent_phantom.visits() > 1 {
-            let frac = parent_phantom.variance().sqrt() / Options::c_variance_scale();
-            c *= 1.0 + Options::c_variance_weight() * (frac - 1.0);
+            let frac = parent_phantom.variance().sqrt() / self.options.c_variance_scale;
+            c *= 1.0 + self.options.c_variance_weight * (frac - 1.0);
         }
 
-        let numerator = c * (Options::expl_tau() * (parent_phantom.visits().max(1) as f32).ln()).exp();
+        let numerator = c * (self.options.expl_tau * (parent_phantom.visits().max(1) as f32).ln()).exp();
 
         //Iterate though all children of the node and calculate puct value of each of them in
         //order to find the child with the highest PUCT score
@@ -311,7 +360,7 @@ impl<'a, const LOG: bool> Search<LOG> {
     #[inline]
     fn get_node_score(&self, node_index: i32, board: &Board) -> f32 {
         match self.tree[node_index].result() {
-            GameResult::None => sigmoid(qsearch(board, -30_000, 30_000, 0)),
+            GameResult::None => sigmoid(qsearch(board, &self.evaluator, -30_000, 30_000, 0)),
             GameResult::Win(_) => 1.0,
             GameResult::Lose(_) => 0.0,
             GameResult::Draw => 0.5,
@@ -320,7 +369,7 @@ impl<'a, const LOG: bool> Search<LOG> {
 
     #[inline]
     fn get_node_result(&self, board: &Board) -> GameResult {
-        if board.is_insufficient_material() || board.three_fold() || board.half_moves >= 100 {
+        if board.is_insufficient_material() || board.three_fold(&self.history) || board.half_moves >= 100 {
             return GameResult::Draw;
         }
 
@@ -334,13 +383,19 @@ impl<'a, const LOG: bool> Search<LOG> {
         GameResult::None
     }
 
-    fn print_report<const PRETTY_PRINT: bool>(&mut self, search_info: SearchInfo, last_report: &mut String) {
+    fn print_report<const PRETTY_PRINT: bool>(
+        &mut self,
+        root_position: &Board,
+        search_info: SearchInfo,
+        last_report: &mut String,
+    ) {
         let best_phantom = self.tree.get_best_phantom();
         let game_result =
             if best_phantom.index() != -1 { self.tree[best_phantom.index()].result() } else { GameResult::None };
         let report = SearchReport::print_report::<PRETTY_PRINT>(
             &search_info,
-            self.tree.get_pv_line(),
+            root_position,
+            &self.tree.get_pv_line(),
             best_phantom.avg_score(),
             game_result,
             &self.tree,
@@ -0,0 +1,184 @@
+use rand::Rng;
+
+use crate::{core::Move, mcts::SearchTree, options::Options};
+
+//Gumbel top-k root selection with sequential halving (Danihelka et al. 2022). Instead of spreading
+//small node budgets over the whole root with PUCT, we sample k actions without replacement using
+//gumbel noise on top of the policy logits, and split the budget between them in phases, dropping
+//the worse half of candidates after every phase
+pub struct GumbelRoot {
+    candidates: Vec<usize>,
+    noise: Vec<f32>,
+    visits: Vec<u32>,
+    phase_target: u32,
+    phase_count: u32,
+    budget: u32,
+    active: bool,
+}
+impl GumbelRoot {
+    pub fn new() -> Self {
+        Self {
+            candidates: Vec::new(),
+            noise: Vec::new(),
+            visits: Vec::new(),
+            phase_target: 0,
+            phase_count: 0,
+            budget: 0,
+            active: false,
+        }
+    }
+
+    #[inline]
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    #[inline]
+    pub fn disable(&mut self) {
+        self.active = false;
+    }
+
+    pub fn initialize(&mut self, tree: &SearchTree, budget: u32) {
+        let children = tree[tree.root_index()].children();
+
+        //With only one legal move there is nothing to choose from
+        self.active = children.len() > 1;
+        if !self.active {
+            return;
+        }
+
+        //Perturb policy logits with gumbel noise and take top k actions, which is equivalent
+        //to sampling k actions from the policy without replacement
+        let mut rng = rand::thread_rng();
+        self.noise =
+            children.iter().map(|_| -(-rng.gen_range(f32::MIN_POSITIVE..1.0f32).ln()).ln()).collect::<Vec<f32>>();
+        self.visits = vec![0; children.len()];
+
+        let mut order = (0..children.len()).collect::<Vec<usize>>();
+        order.sort_by(|&a, &b| {
+            let a_value = self.noise[a] + logit(children[a].policy());
+            let b_value = self.noise[b] + logit(children[b].policy());
+            b_value.total_cmp(&a_value)
+        });
+
+        let action_count = (Options::gumbel_actions() as usize).min(children.len());
+        order.truncate(action_count);
+        self.candidates = order;
+
+        self.budget = budget;
+        self.phase_count = (action_count as f32).log2().ceil().max(1.0) as u32;
+        self.phase_target = self.phase_budget();
+    }
+
+    //Returns index of the root child that should be visited next. Candidates are visited
+    //evenly until all of them reached visits target of current phase, then the worse half
+    //is dropped and the next phase starts
+    pub fn select(&mut self, tree: &SearchTree) -> usize {
+        let phase_finished = self.candidates.iter().all(|&child| self.visits[child] >= self.phase_target);
+
+        if phase_finished && self.candidates.len() > 1 {
+            let max_visits = max_root_visits(tree);
+            let mut candidates = std::mem::take(&mut self.candidates);
+            candidates.sort_by(|&a, &b| self.score(tree, b, max_visits).total_cmp(&self.score(tree, a, max_visits)));
+            candidates.truncate(candidates.len().div_ceil(2));
+            self.candidates = candidates;
+            self.phase_target += self.phase_budget();
+        }
+
+        let mut selected = self.candidates[0];
+        for &child in &self.candidates {
+            if self.visits[child] < self.visits[selected] {
+                selected = child;
+            }
+        }
+
+        self.visits[selected] += 1;
+        selected
+    }
+
+    pub fn best_move(&self, tree: &SearchTree) -> Move {
+        let children = tree[tree.root_index()].children();
+        let max_visits = max_root_visits(tree);
+
+        let mut best = self.candidates[0];
+        for &child in &self.candidates {
+            if self.score(tree, child, max_visits) > self.score(tree, best, max_visits) {
+                best = child;
+            }
+        }
+
+        children[best].mv()
+    }
+
+    //Improved policy target pi' = softmax(logits + sigma(completed Q)). Unvisited actions
+    //use mixed value approximation in place of Q, so the target is defined for every root child
+    pub fn improved_policy(tree: &SearchTree) -> Vec<(Move, f32)> {
+        let children = tree[tree.root_index()].children();
+        let max_visits = max_root_visits(tree);
+
+        let mut total_visits = 0.0;
+        let mut visited_policy = 0.0;
+        let mut weighted_q = 0.0;
+        for child_phantom in children {
+            if child_phantom.visits() > 0 {
+                total_visits += child_phantom.visits() as f32;
+                visited_policy += child_phantom.policy();
+                weighted_q += child_phantom.policy() * child_phantom.avg_score();
+            }
+        }
+
+        //Root phantom holds score from opponent perspective
+        let root_value = 1.0 - tree.get_phantom(-1, 0).avg_score();
+        let mixed_value = if total_visits == 0.0 || visited_policy <= 0.0 {
+            root_value
+        } else {
+            (root_value + total_visits * weighted_q / visited_policy) / (1.0 + total_visits)
+        };
+
+        let mut result = children
+            .iter()
+            .map(|child_phantom| {
+                let q = if child_phantom.visits() > 0 { child_phantom.avg_score() } else { mixed_value };
+                (child_phantom.mv(), logit(child_phantom.policy()) + sigma(q, max_visits))
+            })
+            .collect::<Vec<(Move, f32)>>();
+
+        let max_value = result.iter().fold(f32::NEG_INFINITY, |max, (_, value)| max.max(*value));
+        let mut total = 0.0;
+        for (_, value) in result.iter_mut() {
+            *value = (*value - max_value).exp();
+            total += *value;
+        }
+
+        for (_, value) in result.iter_mut() {
+            *value /= total;
+        }
+
+        result
+    }
+
+    fn phase_budget(&self) -> u32 {
+        (self.budget / (self.phase_count * self.candidates.len() as u32)).max(1)
+    }
+
+    fn score(&self, tree: &SearchTree, child_index: usize, max_visits: u32) -> f32 {
+        let child_phantom = &tree[tree.root_index()].children()[child_index];
+        self.noise[child_index] + logit(child_phantom.policy()) + sigma(child_phantom.avg_score(), max_visits)
+    }
+}
+
+#[inline]
+fn logit(policy: f32) -> f32 {
+    policy.max(1e-6).ln()
+}
+
+//Monotonic transformation of Q value, that grows with the visits in order to trust the search more than prior
+#[inline]
+fn sigma(q: f32, max_visits: u32) -> f32 {
+    (Options::gumbel_c_visit() as f32 + max_visits as f32) * Options::gumbel_c_scale() * q
+}
+
+#[inline]
+fn max_root_visits(tree: &SearchTree) -> u32 {
+    tree[tree.root_index()].children().iter().map(|child_phantom| child_phantom.visits()).max().unwrap_or(0)
+}
@@ -1,5 +1,6 @@
 use crate::{
     core::{Board, MoveList, MoveProvider},
+    features::Features,
     mcts::Evaluation,
     options::Options,
 };
@@ -103,7 +104,7 @@ impl Node {
         self.child = 0;
     }
 
-    pub fn expand<const ROOT: bool>(&mut self, board: &Board) {
+    pub fn expand(&mut self, board: &Board, pst: f32) {
         //Generate all possible moves from the node
         let mut move_list = MoveList::new();
         MoveProvider::generate_moves::<false>(&mut move_list, &board);
@@ -112,8 +113,8 @@ impl Node {
         let mut max_policy_value = f32::NEG_INFINITY;
 
         //Generate inputs for the policy network
-        let policy_inputs = Evaluation::get_policy_inputs(board);
-        let threats = board.get_attack_map(board.side_to_move.flipped());
+        let policy_inputs = Features::policy_inputs(board);
+        let threats = Features::policy_threats(board);
         self.children = Vec::with_capacity(move_list.len());
 
         //Prebake new children with raw policy
@@ -127,7 +128,6 @@ impl Node {
         }
 
         let mut total_policy = 0.0;
-        let pst = if ROOT { Options::root_pst() } else { Options::non_root_pst() };
 
         //Iterate through created children to apply first part of softmax and pst dampening
         for child_phantom in self.children_mut() {
@@ -152,8 +152,8 @@ impl Node {
         let mut max_policy_value = f32::NEG_INFINITY;
 
         //Generate inputs for the policy network
-        let policy_inputs = Evaluation::get_policy_inputs(board);
-        let threats = board.get_attack_map(board.side_to_move.flipped());
+        let policy_inputs = Features::policy_inputs(board);
+        let threats = Features::policy_threats(board);
 
         //Update children
         for child_phantom in self.children_mut() {
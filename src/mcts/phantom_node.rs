@@ -85,15 +85,15 @@ impl PhantomNode {
         &self,
         prefix: &str,
         is_root: bool,
+        move_text: &str,
         heat_min_value: f32,
         heat_max_value: f32,
-        has_promotion: bool,
         game_result: GameResult,
     ) {
         let move_str = if is_root {
             "root".truecolor(192, 210, 255).to_string()
         } else {
-            format!("{:<6} {}", self.index().to_string() + ".", self.mv.to_string().truecolor(192, 210, 255))
+            format!("{:<6} {}", self.index().to_string() + ".", move_text.truecolor(192, 210, 255))
         };
 
         let get_node_value = || -> f32 {
@@ -129,10 +129,9 @@ impl PhantomNode {
 
         if is_root {
             println!("{}{:<30}{:<35}{:<35}", prefix, move_str, q_text, n_text);
-        } else if has_promotion {
-            println!("{}{:<36}{:<35}{:<35}{:<30}{}", prefix, move_str, q_text, n_text, p_text, t_text);
         } else {
-            println!("{}{:<35}{:<35}{:<35}{:<30}{}", prefix, move_str, q_text, n_text, p_text, t_text);
+            let width = move_text.len() + 31;
+            println!("{}{:<width$}{:<35}{:<35}{:<30}{}", prefix, move_str, q_text, n_text, p_text, t_text);
         }
     }
 }
@@ -2,16 +2,18 @@ use arrayvec::ArrayVec;
 
 use crate::{
     core::{Board, Move, MoveList, MoveProvider},
-    eval::Evaluation,
+    eval::ValueEvaluator,
     see::SEE,
 };
 
-pub fn qsearch<'a>(board: &Board, mut alpha: i32, beta: i32, depth: u8) -> i32 {
-    if board.is_insufficient_material() || board.three_fold() || board.half_moves >= 100 {
+//Qsearch plays only captures, so positions it visits can't repeat. Repetition of the position it
+//starts from is checked by the search before
+pub fn qsearch<'a>(board: &Board, evaluator: &ValueEvaluator, mut alpha: i32, beta: i32, depth: u8) -> i32 {
+    if board.is_insufficient_material() || board.half_moves >= 100 {
         return 0;
     }
 
-    let evaluation = Evaluation::evaluate(&board);
+    let evaluation = evaluator.evaluate(&board);
 
     if depth > 128 {
         return evaluation;
@@ -38,7 +40,7 @@ pub fn qsearch<'a>(board: &Board, mut alpha: i32, beta: i32, depth: u8) -> i32 {
         let mut board_copy = board.clone();
         board_copy.make_move(mv);
 
-        let score = -qsearch(&board_copy, -beta, -alpha, depth + 1);
+        let score = -qsearch(&board_copy, evaluator, -beta, -alpha, depth + 1);
 
         if score >= beta {
             return beta;
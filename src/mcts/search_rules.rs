@@ -8,10 +8,11 @@ pub struct SearchRules {
     pub max_depth: u32,
     pub max_nodes: u32,
     pub infinite: bool,
+    pub gumbel_root: bool,
 }
 impl SearchRules {
     pub fn new() -> Self {
-        Self { time_for_move: 0, max_depth: 0, max_nodes: 0, infinite: false }
+        Self { time_for_move: 0, max_depth: 0, max_nodes: 0, infinite: false, gumbel_root: false }
     }
 
     pub fn continue_search(&self, search_info: &SearchInfo, tree: &SearchTree) -> bool {
@@ -41,7 +42,8 @@ impl SearchRules {
     pub fn calculate_time(time_remaining: u64, time_increment: u64, moves_to_go: u64) -> u64 {
         let divider = if moves_to_go > 0 { moves_to_go } else { Options::tc() as u64 };
         let inc = (time_increment as f32 * Options::tc_inc()) as u64;
-        (time_remaining / divider.max(1) + inc - Options::move_overhead() as u64)
+        (time_remaining / divider.max(1) + inc)
+            .saturating_sub(Options::move_overhead() as u64)
             .max(1)
             .min((time_remaining as f32 / 2.0) as u64)
     }
@@ -140,7 +140,7 @@ impl SearchTree {
         self[end_index].set_forward_link(-1);
 
         let mut root_node = Node::new(GameResult::None, -1, 0);
-        root_node.expand::<true>(&current_board);
+        root_node.expand(&current_board, Options::root_pst());
         let root_index = self.push(root_node);
         self.set_root_index(root_index);
     }
@@ -243,17 +243,17 @@ impl SearchTree {
         self.get_best_child_for_node(self.root_index())
     }
 
-    pub fn get_pv_line(&self) -> String {
-        let mut pv_line: Vec<String> = Vec::new();
+    pub fn get_pv_line(&self) -> Vec<Move> {
+        let mut pv_line: Vec<Move> = Vec::new();
         let mut phantom_node = self.get_best_child_for_node(self.root_index());
-        pv_line.push(phantom_node.mv().to_string());
+        pv_line.push(phantom_node.mv());
 
         while (phantom_node.index() as usize) < self.capacity() && !self[phantom_node.index()].children().is_empty() {
             phantom_node = self.get_best_child_for_node(phantom_node.index());
-            pv_line.push(phantom_node.mv().to_string());
+            pv_line.push(phantom_node.mv());
         }
 
-        pv_line.join(" ")
+        pv_line
     }
 
     fn get_best_child_for_node(&self, node_index: i32) -> &PhantomNode {
@@ -286,24 +286,25 @@ impl SearchTree {
         best_node
     }
 
+    //Board is the position at the root of the tree, it's used to write moves in chosen notation
     #[allow(unused)]
-    pub fn draw_tree_from_root(&self, max_depth: i32) {
+    pub fn draw_tree_from_root(&self, board: &Board, max_depth: i32) {
         self.print_tree_usage();
         if !self.tree.is_empty() {
-            self.draw_tree(&self.root_phantom, "".to_string(), false, true, max_depth, 0, 0.0, 0.0, false);
+            self.draw_tree(&self.root_phantom, board, "", "".to_string(), false, true, max_depth, 0, 0.0, 0.0);
         }
     }
 
     #[allow(unused)]
-    pub fn draw_tree_from_node(&self, node_index: i32, max_depth: i32) {
+    pub fn draw_tree_from_node(&self, board: &Board, node_index: i32, max_depth: i32) {
         if node_index == -1 {
             return;
         }
 
-        let (node_phantom, node_depth) = self.find_node_phantom(node_index);
+        let (node_phantom, node_depth, node_board) = self.find_node_phantom(node_index, board);
         self.print_tree_usage();
         if !self.tree.is_empty() {
-            self.draw_tree(&node_phantom, "".to_string(), false, true, max_depth, node_depth, 0.0, 0.0, false);
+            self.draw_tree(&node_phantom, &node_board, "", "".to_string(), false, true, max_depth, node_depth, 0.0, 0.0);
         }
     }
 
@@ -317,9 +318,12 @@ impl SearchTree {
         );
     }
 
+    //Board is the position after the move of the phantom node
     fn draw_tree(
         &self,
         phantom_node: &PhantomNode,
+        board: &Board,
+        move_text: &str,
         prefix: String,
         last: bool,
         is_root: bool,
@@ -327,7 +331,6 @@ impl SearchTree {
         current_depth: u32,
         heat_min_value: f32,
         heat_max_value: f32,
-        has_promotion: bool,
     ) {
         if max_depth < 0 {
             return;
@@ -342,9 +345,9 @@ impl SearchTree {
         phantom_node.print_node(
             if is_root { "" } else { prefix_string.as_str() },
             is_root,
+            move_text,
             heat_min_value,
             heat_max_value,
-            has_promotion,
             game_result,
         );
 
@@ -356,18 +359,34 @@ impl SearchTree {
         let children = self[index].children();
         let mut heat_min_value = f32::MAX;
         let mut heat_max_value = f32::MIN;
-        let mut has_promotion = false;
         for child_phantom in children {
             heat_min_value = heat_min_value.min(child_phantom.policy());
             heat_max_value = heat_max_value.max(child_phantom.policy());
-            if child_phantom.mv().is_promotion() {
-                has_promotion = true;
-            }
         }
+
+        let use_san = Options::notation() == "san";
+        let move_texts = children
+            .iter()
+            .map(|child_phantom| {
+                if use_san {
+                    board.move_to_san(child_phantom.mv())
+                } else {
+                    child_phantom.mv().to_string()
+                }
+            })
+            .collect::<Vec<String>>();
+
+        //Moves are padded to the longest sibling, so columns of the siblings stay aligned
+        let move_width = move_texts.iter().map(|text| text.len()).max().unwrap_or(0).max(4);
+
         for (i, child_phantom) in children.iter().enumerate() {
             let is_last_child = i == children.len() - 1;
+            let mut child_board = *board;
+            child_board.make_move(child_phantom.mv());
             self.draw_tree(
                 child_phantom,
+                &child_board,
+                &format!("{:<move_width$}", move_texts[i]),
                 prefix.clone() + if is_root { "" } else { &new_prefix },
                 is_last_child,
                 false,
@@ -375,40 +394,43 @@ impl SearchTree {
                 current_depth + 1,
                 heat_min_value,
                 heat_max_value,
-                has_promotion,
             );
         }
     }
 
-    fn find_node_phantom(&self, node_index: i32) -> (PhantomNode, u32) {
-        self.find_node_phantom_step(node_index, &self.root_phantom)
+    //Returns the phantom of the node and the position after its move
+    fn find_node_phantom(&self, node_index: i32, board: &Board) -> (PhantomNode, u32, Board) {
+        self.find_node_phantom_step(node_index, &self.root_phantom, board)
     }
 
     fn find_node_phantom_step<'a>(
         &self,
         target_node_index: i32,
         phantom_to_process: &'a PhantomNode,
-    ) -> (PhantomNode, u32) {
+        board: &Board,
+    ) -> (PhantomNode, u32, Board) {
         if phantom_to_process.index() == -1 {
-            return (self.root_phantom, 0);
+            return (self.root_phantom, 0, *board);
         }
 
         if phantom_to_process.index() == target_node_index {
-            return (*phantom_to_process, 0);
+            return (*phantom_to_process, 0, *board);
         }
 
         if phantom_to_process.visits() == 0 || self[phantom_to_process.index()].is_terminal() {
-            return (self.root_phantom, 0);
+            return (self.root_phantom, 0, *board);
         }
 
         for child_phantom in self[phantom_to_process.index()].children() {
-            let result = self.find_node_phantom_step(target_node_index, child_phantom);
+            let mut child_board = *board;
+            child_board.make_move(child_phantom.mv());
+            let result = self.find_node_phantom_step(target_node_index, child_phantom, &child_board);
             if result.0 != self.root_phantom {
                 return result;
             }
         }
 
-        return (self.root_phantom, 0);
+        return (self.root_phantom, 0, *board);
     }
 }
 
@@ -1,4 +1,4 @@
-use crate::core::{Board, Piece, Side};
+use crate::{core::Board, features::Features};
 
 use super::{activation::ActivationFunction, ScReLUActivation};
 
@@ -60,7 +60,7 @@ impl<const INPUTS: usize, const OUTPUTS: usize> SparseLayer<INPUTS, OUTPUTS>
     pub fn forward(&self, board: &Board) -> Accumulator<OUTPUTS> {
         let mut result = self.layer.biases;
 
-        Self::map_value_inputs(board, |weight_index| {
+        Features::map_value_inputs(board, |weight_index| {
             for (i, weight) in result.vals.iter_mut().zip(&self.layer.weights[weight_index].vals) {
                 *i += *weight;
             }
@@ -68,31 +68,6 @@ impl<const INPUTS: usize, const OUTPUTS: usize> SparseLayer<INPUTS, OUTPUTS>
 
         result
     }
-
-    fn map_value_inputs<F: FnMut(usize)>(board: &Board, mut method: F) {
-        let flip = board.side_to_move == Side::BLACK;
-        let horizontal_mirror = if board.get_king_square(board.side_to_move).get_value() % 8 > 3 { 7 } else { 0 };
-
-        for piece in Piece::PAWN..=Piece::KING {
-            let piece_index = 64 * (piece - Piece::PAWN);
-
-            let mut stm_bitboard = board.get_piece_mask(piece, board.side_to_move);
-            let mut nstm_bitboard = board.get_piece_mask(piece, board.side_to_move.flipped());
-
-            if flip {
-                stm_bitboard = stm_bitboard.flip();
-                nstm_bitboard = nstm_bitboard.flip();
-            }
-
-            for square in stm_bitboard {
-                method(piece_index + (square.get_value() ^ horizontal_mirror))
-            }
-
-            for square in nstm_bitboard {
-                method(384 + piece_index + (square.get_value() ^ horizontal_mirror))
-            }
-        }
-    }
 }
 
 #[allow(unused)]
@@ -1,4 +1,5 @@
 use once_cell::sync::Lazy;
+use std::cell::RefCell;
 use std::sync::{Arc, RwLock};
 
 static OPTIONS: Lazy<Options> = Lazy::new(Options::new);
@@ -9,6 +10,17 @@ macro_rules! create_option_structs {
             $(pub $name: $type,)*
         }
 
+        //Values set for a single thread take precedence over global ones, so engines with
+        //different settings can search in the same process
+        #[derive(Default)]
+        struct ThreadOptions {
+            $($name: Option<<$type as OptionTrait>::ValueType>,)*
+        }
+
+        thread_local! {
+            static THREAD_OPTIONS: RefCell<ThreadOptions> = RefCell::new(ThreadOptions::default());
+        }
+
         impl Options {
             fn new() -> Self {
                 Self {
@@ -23,6 +35,36 @@ macro_rules! create_option_structs {
                 }
             }
 
+            #[allow(unused)]
+            pub fn set_for_thread(key: &str, new_value: &str) -> Result<(), String> {
+                match key {
+                    $($option_name => {
+                        let value = OPTIONS.$name.parse(new_value).ok_or(format!("Invalid value '{new_value}' for {key}"))?;
+                        THREAD_OPTIONS.with(|options| options.borrow_mut().$name = Some(value));
+                    })*
+                    _ => return Err(format!("Option {key} doesn't exist")),
+                }
+
+                Ok(())
+            }
+
+            #[allow(unused)]
+            pub fn clear_thread_options() {
+                THREAD_OPTIONS.with(|options| *options.borrow_mut() = ThreadOptions::default());
+            }
+
+            //Options with a tuning range, with their default values
+            #[allow(unused)]
+            pub fn tunable() -> Vec<TunableOption> {
+                let mut result = Vec::new();
+                $(
+                    if let Some(option) = OPTIONS.$name.tunable(stringify!($name), $option_name, stringify!($type)) {
+                        result.push(option);
+                    }
+                )*
+                result
+            }
+
             pub fn print() {
                 $(
                     OPTIONS.$name.print($option_name);
@@ -32,7 +74,9 @@ macro_rules! create_option_structs {
             #[inline]
             $(
                 pub fn $name() -> <$type as OptionTrait>::ValueType {
-                    OPTIONS.$name.get()
+                    THREAD_OPTIONS
+                        .with(|options| options.borrow().$name.clone())
+                        .unwrap_or_else(|| OPTIONS.$name.get())
                 }
             )*
 
@@ -43,27 +87,127 @@ macro_rules! create_option_structs {
     };
 }
 
+//Spin options with `.tune(min, max, step)` are picked up by the SPSA tuner. Step is the size of
+//the perturbation at the end of tuning, in the same units as the value
 create_option_structs!(
     hash: SpinOptionInt => SpinOptionInt::new(64, 1, 65536), "Hash",
     move_overhead: SpinOptionInt => SpinOptionInt::new(10, 0, 500), "MoveOverhead",
-    root_pst: SpinOptionFloat => SpinOptionFloat::new(4.0, 0.1, 10.0), "RootPST",
-    non_root_pst: SpinOptionFloat => SpinOptionFloat::new(1.0, 0.1, 10.0), "NonRootPST",
-    c_value: SpinOptionFloat => SpinOptionFloat::new(0.65, 0.1, 10.0), "CValue",
-    root_c_value: SpinOptionFloat => SpinOptionFloat::new(0.65, 0.1, 10.0), "RootCValue",
-    c_variance_weight: SpinOptionFloat => SpinOptionFloat::new(0.85, 0.0, 2.0), "CVarianceWeight",
-    c_variance_scale: SpinOptionFloat => SpinOptionFloat::new(0.2, 0.0, 2.0), "CVarianceScale",
-    c_visits_scale: SpinOptionInt => SpinOptionInt::new(64, 1, 512), "CVisitsScale",
-    expl_tau: SpinOptionFloat => SpinOptionFloat::new(0.5, 0.1, 1.0), "ExpansionTau",
-    tc: SpinOptionInt => SpinOptionInt::new(20, 1, 50), "TcDivider",
-    tc_inc: SpinOptionFloat => SpinOptionFloat::new(0.5, 0.0, 1.0), "TcIncPercentage",
+    root_pst: SpinOptionFloat => SpinOptionFloat::new(4.0, 0.1, 10.0).tune(1.0, 8.0, 0.4), "RootPST",
+    non_root_pst: SpinOptionFloat => SpinOptionFloat::new(1.0, 0.1, 10.0).tune(0.5, 2.0, 0.1), "NonRootPST",
+    c_value: SpinOptionFloat => SpinOptionFloat::new(0.65, 0.1, 10.0).tune(0.2, 2.0, 0.06), "CValue",
+    root_c_value: SpinOptionFloat => SpinOptionFloat::new(0.65, 0.1, 10.0).tune(0.2, 2.0, 0.06), "RootCValue",
+    c_variance_weight: SpinOptionFloat => SpinOptionFloat::new(0.85, 0.0, 2.0).tune(0.0, 2.0, 0.08), "CVarianceWeight",
+    c_variance_scale: SpinOptionFloat => SpinOptionFloat::new(0.2, 0.0, 2.0).tune(0.0, 1.0, 0.04), "CVarianceScale",
+    c_visits_scale: SpinOptionInt => SpinOptionInt::new(64, 1, 512).tune(8, 256, 8), "CVisitsScale",
+    expl_tau: SpinOptionFloat => SpinOptionFloat::new(0.5, 0.1, 1.0).tune(0.2, 1.0, 0.04), "ExpansionTau",
+    tc: SpinOptionInt => SpinOptionInt::new(20, 1, 50).tune(8, 40, 2), "TcDivider",
+    tc_inc: SpinOptionFloat => SpinOptionFloat::new(0.5, 0.0, 1.0).tune(0.2, 1.0, 0.05), "TcIncPercentage",
+    gumbel_actions: SpinOptionInt => SpinOptionInt::new(16, 2, 64), "GumbelActions",
+    gumbel_c_visit: SpinOptionInt => SpinOptionInt::new(50, 1, 1000).tune(10, 200, 8), "GumbelCVisit",
+    gumbel_c_scale: SpinOptionFloat => SpinOptionFloat::new(1.0, 0.01, 10.0).tune(0.1, 3.0, 0.1), "GumbelCScale",
+    pesto_eval: CheckOption => CheckOption::new(false), "PestoEval",
+    notation: ComboOption => ComboOption::new("uci", &["uci", "san"]), "Notation",
 );
 
+//Options used on every selection step and node expansion, read once per search. Getters check
+//thread overrides before the global value, which is too slow to repeat on every node
+#[derive(Clone, Copy)]
+pub struct SearchOptions {
+    pub c_value: f32,
+    pub root_c_value: f32,
+    pub c_visits_scale: i32,
+    pub c_variance_scale: f32,
+    pub c_variance_weight: f32,
+    pub expl_tau: f32,
+    pub non_root_pst: f32,
+}
+
+impl SearchOptions {
+    pub fn current() -> Self {
+        Self {
+            c_value: Options::c_value(),
+            root_c_value: Options::root_c_value(),
+            c_visits_scale: Options::c_visits_scale(),
+            c_variance_scale: Options::c_variance_scale(),
+            c_variance_weight: Options::c_variance_weight(),
+            expl_tau: Options::expl_tau(),
+            non_root_pst: Options::non_root_pst(),
+        }
+    }
+}
+
+//Tuning range of an option, limited to the range the option accepts
+#[derive(Clone, Copy)]
+pub struct TuningRange {
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+}
+
+//Option known to the tuner. Values are real option values, float options are set through
+//`setoption` multiplied by 100
+#[allow(unused)]
+#[derive(Clone)]
+pub struct TunableOption {
+    pub field: &'static str,
+    pub name: &'static str,
+    pub kind: &'static str,
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub range: TuningRange,
+    pub is_float: bool,
+}
+
+#[allow(unused)]
+impl TunableOption {
+    //Value in the format `setoption` and `Options::set_for_thread` take
+    pub fn option_value(&self, value: f32) -> String {
+        let value = value.clamp(self.range.min, self.range.max);
+        if self.is_float {
+            ((value * 100.0).round() as i32).to_string()
+        } else {
+            (value.round() as i32).to_string()
+        }
+    }
+
+    //Line of `create_option_structs!` with given default value
+    pub fn declaration(&self, value: f32) -> String {
+        let format = |value: f32| {
+            if self.is_float {
+                format!("{:?}", (value * 100.0).round() / 100.0)
+            } else {
+                format!("{}", value.round() as i32)
+            }
+        };
+
+        format!(
+            "{}: {} => {}::new({}, {}, {}).tune({}, {}, {}), \"{}\",",
+            self.field,
+            self.kind,
+            self.kind,
+            format(value.clamp(self.range.min, self.range.max)),
+            format(self.min),
+            format(self.max),
+            format(self.range.min),
+            format(self.range.max),
+            if self.is_float { format!("{:?}", self.range.step) } else { format(self.range.step) },
+            self.name
+        )
+    }
+}
+
 #[allow(dead_code)]
 pub trait OptionTrait {
     type ValueType;
     fn set(&self, new_value: &str);
+    fn parse(&self, new_value: &str) -> Option<Self::ValueType>;
     fn get(&self) -> Self::ValueType;
     fn print(&self, name: &str);
+
+    fn tunable(&self, _field: &'static str, _name: &'static str, _kind: &'static str) -> Option<TunableOption> {
+        None
+    }
 }
 
 pub struct SpinOptionInt {
@@ -71,11 +215,18 @@ pub struct SpinOptionInt {
     default: i32,
     min: i32,
     max: i32,
+    tuning: Option<TuningRange>,
 }
 
 impl SpinOptionInt {
     fn new(value: i32, min: i32, max: i32) -> Self {
-        Self { value: Arc::new(RwLock::new(value)), default: value, min, max }
+        Self { value: Arc::new(RwLock::new(value)), default: value, min, max, tuning: None }
+    }
+
+    fn tune(mut self, min: i32, max: i32, step: i32) -> Self {
+        let (min, max) = (min.max(self.min), max.min(self.max));
+        self.tuning = Some(TuningRange { min: min as f32, max: max as f32, step: step as f32 });
+        self
     }
 
     fn set_value(&self, new_value: i32) {
@@ -103,6 +254,10 @@ impl OptionTrait for SpinOptionInt {
         }
     }
 
+    fn parse(&self, new_value: &str) -> Option<i32> {
+        new_value.parse::<i32>().ok().filter(|value| *value >= self.min && *value <= self.max)
+    }
+
     #[inline]
     fn get(&self) -> i32 {
         self.get()
@@ -111,6 +266,19 @@ impl OptionTrait for SpinOptionInt {
     fn print(&self, name: &str) {
         println!("option name {} type spin default {:?} min {:?} max {:?}", name, self.default, self.min, self.max);
     }
+
+    fn tunable(&self, field: &'static str, name: &'static str, kind: &'static str) -> Option<TunableOption> {
+        Some(TunableOption {
+            field,
+            name,
+            kind,
+            value: self.default as f32,
+            min: self.min as f32,
+            max: self.max as f32,
+            range: self.tuning?,
+            is_float: false,
+        })
+    }
 }
 
 pub struct SpinOptionFloat {
@@ -118,11 +286,17 @@ pub struct SpinOptionFloat {
     default: f32,
     min: f32,
     max: f32,
+    tuning: Option<TuningRange>,
 }
 
 impl SpinOptionFloat {
     fn new(value: f32, min: f32, max: f32) -> Self {
-        Self { value: Arc::new(RwLock::new(value)), default: value, min, max }
+        Self { value: Arc::new(RwLock::new(value)), default: value, min, max, tuning: None }
+    }
+
+    fn tune(mut self, min: f32, max: f32, step: f32) -> Self {
+        self.tuning = Some(TuningRange { min: min.max(self.min), max: max.min(self.max), step });
+        self
     }
 
     fn set_value(&self, new_value: i32) {
@@ -151,6 +325,11 @@ impl OptionTrait for SpinOptionFloat {
         }
     }
 
+    fn parse(&self, new_value: &str) -> Option<f32> {
+        let adjusted = new_value.parse::<i32>().ok()? as f32 / 100.0;
+        Some(adjusted).filter(|value| *value >= self.min && *value <= self.max)
+    }
+
     #[inline]
     fn get(&self) -> f32 {
         self.get()
@@ -165,6 +344,19 @@ impl OptionTrait for SpinOptionFloat {
             (self.max * 100.0) as i32
         );
     }
+
+    fn tunable(&self, field: &'static str, name: &'static str, kind: &'static str) -> Option<TunableOption> {
+        Some(TunableOption {
+            field,
+            name,
+            kind,
+            value: self.default,
+            min: self.min,
+            max: self.max,
+            range: self.tuning?,
+            is_float: true,
+        })
+    }
 }
 
 pub struct CheckOption {
@@ -199,6 +391,10 @@ impl OptionTrait for CheckOption {
         }
     }
 
+    fn parse(&self, new_value: &str) -> Option<bool> {
+        new_value.parse::<bool>().ok()
+    }
+
     #[inline]
     fn get(&self) -> bool {
         self.get()
@@ -237,6 +433,10 @@ impl OptionTrait for StringOption {
         self.set_value(new_value.to_string());
     }
 
+    fn parse(&self, new_value: &str) -> Option<String> {
+        Some(new_value.to_string())
+    }
+
     #[inline]
     fn get(&self) -> String {
         self.get()
@@ -246,3 +446,46 @@ impl OptionTrait for StringOption {
         println!("option name {} type string default {}", name, self.default);
     }
 }
+
+pub struct ComboOption {
+    value: Arc<RwLock<String>>,
+    default: &'static str,
+    choices: &'static [&'static str],
+}
+
+impl ComboOption {
+    fn new(value: &'static str, choices: &'static [&'static str]) -> Self {
+        Self { value: Arc::new(RwLock::new(value.to_string())), default: value, choices }
+    }
+
+    #[inline]
+    fn get(&self) -> String {
+        self.value.read().unwrap().clone()
+    }
+}
+
+impl OptionTrait for ComboOption {
+    type ValueType = String;
+
+    fn set(&self, new_value: &str) {
+        if let Some(parsed_value) = self.parse(new_value) {
+            *self.value.write().unwrap() = parsed_value;
+        } else {
+            println!("Invalid value for option.");
+        }
+    }
+
+    fn parse(&self, new_value: &str) -> Option<String> {
+        self.choices.iter().find(|choice| choice.eq_ignore_ascii_case(new_value)).map(|choice| choice.to_string())
+    }
+
+    #[inline]
+    fn get(&self) -> String {
+        self.get()
+    }
+
+    fn print(&self, name: &str) {
+        let vars = self.choices.iter().map(|choice| format!(" var {choice}")).collect::<String>();
+        println!("option name {} type combo default {}{}", name, self.default, vars);
+    }
+}
@@ -1,16 +1,57 @@
+use std::fs;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 use std::time::Instant;
 
-use crate::core::{create_board, Board, MoveList, MoveProvider};
+use crate::core::{create_board, Board, Move, MoveList, MoveProvider};
+use crate::options::Options;
+
+//Node counts of visited subtrees, keyed by zobrist key and depth. Entries are written without
+//locks, key is stored xored with the data, so torn writes from two threads are detected as a miss
+struct PerftTable {
+    entries: Vec<(AtomicU64, AtomicU64)>,
+}
+
+impl PerftTable {
+    fn new(mem_size: usize) -> Self {
+        let entry_count = (mem_size * 1024 * 1024 / std::mem::size_of::<(AtomicU64, AtomicU64)>()).max(1);
+        Self { entries: (0..entry_count).map(|_| (AtomicU64::new(0), AtomicU64::new(0))).collect() }
+    }
+
+    #[inline]
+    fn probe(&self, key: u64, depth: u8) -> Option<u64> {
+        let (check, data) = &self.entries[key as usize % self.entries.len()];
+        let data = data.load(Ordering::Relaxed);
+        let is_hit = check.load(Ordering::Relaxed) ^ data == key && data & 0xFF == u64::from(depth);
+        if is_hit {
+            Some(data >> 8)
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    fn store(&self, key: u64, depth: u8, nodes: u64) {
+        let (check, data) = &self.entries[key as usize % self.entries.len()];
+        let new_data = nodes << 8 | u64::from(depth);
+        check.store(key ^ new_data, Ordering::Relaxed);
+        data.store(new_data, Ordering::Relaxed);
+    }
+}
 
 pub struct Perft;
 impl Perft {
     pub fn execute<const BULK: bool>(board: &Board, depth: u8, log: bool) -> u64 {
         let timer = Instant::now();
-        let nodes = Perft::internal_execute::<BULK>(board, if depth < 1 { 1 } else { depth }, log);
+        let table = PerftTable::new(Options::hash() as usize);
+        let divide = Perft::divide::<BULK>(board, if depth < 1 { 1 } else { depth }, &table);
+        let nodes = divide.iter().map(|(_, nodes)| nodes).sum::<u64>();
         let duration = timer.elapsed().as_secs_f64();
         let nodes_per_second = (nodes as f64 / duration) as u64;
 
         if log {
+            Perft::print_divide(&divide);
             print!("-----------------------------------------------------------\n");
             print!("  Perft ended! {} nodes, {}s, {:.2} Mnps\n", nodes, duration, nodes_per_second as f64 / 1000000f64);
             print!("-----------------------------------------------------------\n");
@@ -19,92 +60,149 @@ impl Perft {
         nodes
     }
 
-    fn internal_execute<const BULK: bool>(board: &Board, depth: u8, first_iteration: bool) -> u64 {
-        if depth == 0 {
-            return 1;
-        }
-
-        let mut nodes = 0u64;
+    //Runs every position of perft EPD file (`<fen> ;D1 20 ;D2 400 ...`) up to given depth, and
+    //prints node counts of every root move for depths that don't match
+    pub fn run_suite(path: &str, max_depth: u8) {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) => {
+                println!("Failed to read '{path}': {err}");
+                return;
+            }
+        };
 
-        let mut move_list = MoveList::new();
-        MoveProvider::generate_moves::<false>(&mut move_list, board);
+        let timer = Instant::now();
+        let table = PerftTable::new(Options::hash() as usize);
+        let mut passed = 0;
+        let mut failed = 0;
+        let mut nodes = 0;
+
+        for (line_index, line) in content.lines().enumerate() {
+            let mut parts = line.split(';');
+            let fen = parts.next().unwrap_or_default().trim();
+            if fen.is_empty() || fen.starts_with('#') {
+                continue;
+            }
 
-        if BULK && depth == 1 && !first_iteration {
-            return move_list.len() as u64;
-        }
+            if fen.split_whitespace().count() < 4 {
+                println!("Line {}: invalid fen '{fen}'", line_index + 1);
+                failed += 1;
+                continue;
+            }
 
-        let mut has_promotion = false;
-        if first_iteration {
-            for mv in &move_list {
-                if mv.is_promotion() {
-                    has_promotion = true;
+            let board = create_board(fen);
+            println!("{fen}");
+
+            for entry in parts {
+                let mut fields = entry.split_whitespace();
+                //Divide needs at least one move to split, so `D0` is rejected like any other malformed entry
+                let depth = fields
+                    .next()
+                    .and_then(|depth| depth.strip_prefix('D'))
+                    .and_then(|depth| depth.parse::<u8>().ok())
+                    .filter(|&depth| depth > 0);
+                let expected = fields.next().and_then(|expected| expected.parse::<u64>().ok());
+                let (Some(depth), Some(expected)) = (depth, expected) else {
+                    println!("  invalid entry '{}'", entry.trim());
+                    failed += 1;
+                    continue;
+                };
+
+                if depth > max_depth {
+                    continue;
                 }
-            }
-        }
 
-        for mv in &move_list {
-            let mut new_board = *board;
-            new_board.make_move(*mv);
-            let new_nodes = Perft::internal_execute::<BULK>(&new_board, depth - 1, false);
-            nodes += new_nodes;
+                let divide = Perft::divide::<true>(&board, depth, &table);
+                let result = divide.iter().map(|(_, nodes)| nodes).sum::<u64>();
+                nodes += result;
 
-            if first_iteration {
-                if has_promotion {
-                    print!("{:<7}{}\n", mv.to_string() + ":", new_nodes);
+                if result == expected {
+                    println!("  D{depth} {result} ok");
+                    passed += 1;
                 } else {
-                    print!("{:<6}{}\n", mv.to_string() + ":", new_nodes);
+                    println!("  D{depth} {result} expected {expected}");
+                    Perft::print_divide(&divide);
+                    failed += 1;
                 }
             }
         }
 
-        nodes
+        let duration = timer.elapsed().as_secs_f64();
+        print!("-----------------------------------------------------------\n");
+        print!("  Perft suite ended! {passed} passed, {failed} failed, {nodes} nodes, {duration:.2}s\n");
+        print!("-----------------------------------------------------------\n");
     }
 
-    #[allow(dead_code)]
-    pub fn perft_test() {
-        {
-            let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
-            let brd = create_board(fen);
-            print!("{}\n", fen);
-            print!("{}\n\n", if Perft::execute::<true>(&brd, 6, false) == 119060324 { "passed" } else { "not passed" });
+    //Node counts of every root move. Root moves are split between threads, which share the table
+    fn divide<const BULK: bool>(board: &Board, depth: u8, table: &PerftTable) -> Vec<(Move, u64)> {
+        let mut move_list = MoveList::new();
+        MoveProvider::generate_moves::<false>(&mut move_list, board);
+
+        let results = Mutex::new(vec![0u64; move_list.len()]);
+        let next_move = AtomicUsize::new(0);
+        let thread_count = thread::available_parallelism().map_or(1, |count| count.get()).min(move_list.len());
+
+        thread::scope(|scope| {
+            for _ in 0..thread_count {
+                scope.spawn(|| loop {
+                    let index = next_move.fetch_add(1, Ordering::Relaxed);
+                    if index >= move_list.len() {
+                        break;
+                    }
+
+                    let mut new_board = *board;
+                    new_board.make_move(move_list[index]);
+                    let nodes = Perft::internal_execute::<BULK>(&new_board, depth - 1, table);
+                    results.lock().unwrap()[index] = nodes;
+                });
+            }
+        });
+
+        move_list.iter().copied().zip(results.into_inner().unwrap()).collect()
+    }
+
+    fn internal_execute<const BULK: bool>(board: &Board, depth: u8, table: &PerftTable) -> u64 {
+        if depth == 0 {
+            return 1;
         }
 
-        {
-            let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
-            let brd = create_board(fen);
-            print!("{}\n", fen);
-            print!("{}\n\n", if Perft::execute::<true>(&brd, 5, false) == 193690690 { "passed" } else { "not passed" });
+        //Last ply is cheaper to generate than to probe, so it's never stored
+        let use_table = depth > 1;
+        if use_table {
+            if let Some(nodes) = table.probe(board.zobrist.key, depth) {
+                return nodes;
+            }
         }
 
-        {
-            let fen = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
-            let brd = create_board(fen);
-            print!("{}\n", fen);
-            print!("{}\n\n", if Perft::execute::<true>(&brd, 7, false) == 178633661 { "passed" } else { "not passed" });
+        let mut move_list = MoveList::new();
+        MoveProvider::generate_moves::<false>(&mut move_list, board);
+
+        if BULK && depth == 1 {
+            return move_list.len() as u64;
         }
 
-        {
-            let fen = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
-            let brd = create_board(fen);
-            print!("{}\n", fen);
-            print!("{}\n\n", if Perft::execute::<true>(&brd, 6, false) == 706045033 { "passed" } else { "not passed" });
+        let mut nodes = 0u64;
+        for mv in &move_list {
+            let mut new_board = *board;
+            new_board.make_move(*mv);
+            nodes += Perft::internal_execute::<BULK>(&new_board, depth - 1, table);
         }
 
-        {
-            let fen = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
-            let brd = create_board(fen);
-            print!("{}\n", fen);
-            print!(
-                "{}\n\n",
-                if Perft::execute::<true>(&brd, 6, false) == 3048196529 { "passed" } else { "not passed" }
-            );
+        if use_table {
+            table.store(board.zobrist.key, depth, nodes);
         }
 
-        {
-            let fen = "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
-            let brd = create_board(fen);
-            print!("{}\n", fen);
-            print!("{}\n\n", if Perft::execute::<true>(&brd, 5, false) == 164075551 { "passed" } else { "not passed" });
+        nodes
+    }
+
+    fn print_divide(divide: &[(Move, u64)]) {
+        let has_promotion = divide.iter().any(|(mv, _)| mv.is_promotion());
+        for (mv, nodes) in divide {
+            if has_promotion {
+                print!("{:<7}{}\n", mv.to_string() + ":", nodes);
+            } else {
+                print!("{:<6}{}\n", mv.to_string() + ":", nodes);
+            }
         }
     }
 }
@@ -0,0 +1,459 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+
+use crate::core::{create_board, Board, Move, MoveHistory, MoveParseError, Side};
+
+const START_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgnError {
+    InvalidTag(String),
+    InvalidFen(String),
+    UnclosedComment,
+    UnbalancedVariation,
+    InvalidMove { text: String, fen: String, error: MoveParseError },
+}
+
+impl Display for PgnError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnError::InvalidTag(tag) => write!(f, "invalid tag '{tag}'"),
+            PgnError::InvalidFen(fen) => write!(f, "invalid fen '{fen}'"),
+            PgnError::UnclosedComment => write!(f, "unclosed comment"),
+            PgnError::UnbalancedVariation => write!(f, "unbalanced variation"),
+            PgnError::InvalidMove { text, fen, error } => write!(f, "{error} '{text}' in {fen}"),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+#[derive(Clone)]
+pub struct PgnMove {
+    pub mv: Move,
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+    //Alternatives to this move, played from the position before it
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(mv: Move) -> Self {
+        Self { mv, nags: Vec::new(), comment: None, variations: Vec::new() }
+    }
+
+    //Move with the expected score of the side that played it, written as a comment in pawns
+    pub fn with_eval(mv: Move, score: f32) -> Self {
+        Self { comment: Some(format_eval(score)), ..Self::new(mv) }
+    }
+}
+
+//`create_board` expects well formed fields, so they are checked before the position is created: 8 ranks
+//of 8 files with known pieces, one king of each side, side to move and a numeric halfmove clock
+fn is_valid_fen(fen: &str) -> bool {
+    let fields = fen.split_whitespace().collect::<Vec<&str>>();
+    if fields.len() < 4 || !matches!(fields[1], "w" | "b") {
+        return false;
+    }
+
+    let ranks = fields[0].split('/').collect::<Vec<&str>>();
+    let is_rank_valid = |rank: &&str| {
+        let mut files = 0;
+        for character in rank.chars() {
+            match character {
+                '1'..='8' => files += character as usize - '0' as usize,
+                'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => files += 1,
+                _ => return false,
+            }
+        }
+
+        files == 8
+    };
+
+    ranks.len() == 8
+        && ranks.iter().all(is_rank_valid)
+        && fields[0].matches('K').count() == 1
+        && fields[0].matches('k').count() == 1
+        && fields.get(4).is_none_or(|half_moves| half_moves.parse::<u8>().is_ok())
+}
+
+//Expected score converted to pawns, with a sign
+pub fn format_eval(score: f32) -> String {
+    let pawns = -4.0 * (1.0 / score.clamp(0.001, 0.999) - 1.0).ln();
+
+    //Adding zero turns negative zero after rounding into positive one
+    let pawns = (pawns * 100.0).round() / 100.0 + 0.0;
+    format!("{pawns:+.2}")
+}
+
+#[derive(Clone)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub start_position: Board,
+    pub moves: Vec<PgnMove>,
+}
+
+impl PgnGame {
+    //Game with the seven tag roster. FEN and SetUp tags are added when the game doesn't start from
+    //the standard position
+    #[allow(unused)]
+    pub fn new(start_position: &Board) -> Self {
+        let mut game = Self { tags: Vec::new(), start_position: *start_position, moves: Vec::new() };
+        for (name, value) in [
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "?"),
+            ("White", "?"),
+            ("Black", "?"),
+            ("Result", "*"),
+        ] {
+            game.set_tag(name, value);
+        }
+
+        if *start_position != create_board(START_POSITION) {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &start_position.get_fen());
+        }
+
+        game
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(tag_name, _)| tag_name == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag_name, _)| tag_name == name) {
+            Some((_, tag_value)) => *tag_value = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn result(&self) -> &str {
+        self.tag("Result").filter(|result| RESULTS.contains(result)).unwrap_or("*")
+    }
+
+    //Position after given number of plies of the main line, together with positions played before it
+    pub fn position_at(&self, ply: usize) -> (Board, MoveHistory) {
+        let mut board = self.start_position;
+        let mut history = MoveHistory::new();
+        for pgn_move in self.moves.iter().take(ply) {
+            history.push(&board);
+            board.make_move(pgn_move.mv);
+        }
+
+        (board, history)
+    }
+
+    pub fn read_file(path: &str) -> Result<Vec<PgnGame>, String> {
+        let content = fs::read_to_string(path).map_err(|err| format!("Failed to read '{path}': {err}"))?;
+        PgnGame::parse_all(&content).map_err(|err| format!("Failed to parse '{path}': {err}"))
+    }
+
+    //Parses every game of PGN text. Moves of the main line and variations are checked to be legal,
+    //comments placed before the first move of a line are dropped
+    pub fn parse_all(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+        let tokens = tokenize(text)?;
+        let mut games = Vec::new();
+        let mut index = 0;
+
+        while index < tokens.len() {
+            let mut tags = Vec::new();
+            while let Some(Token::Tag(name, value)) = tokens.get(index) {
+                tags.push((name.clone(), value.clone()));
+                index += 1;
+            }
+
+            //Movetext ends with a result, or with the tags of the next game if the result is missing
+            let movetext_length = tokens[index..]
+                .iter()
+                .position(|token| matches!(token, Token::Result(_) | Token::Tag(..)))
+                .unwrap_or(tokens.len() - index);
+            let movetext = &tokens[index..index + movetext_length];
+            index += movetext_length;
+
+            let result = match tokens.get(index) {
+                Some(Token::Result(result)) => {
+                    index += 1;
+                    Some(result.clone())
+                }
+                _ => None,
+            };
+
+            if tags.is_empty() && movetext.is_empty() && result.is_none() {
+                continue;
+            }
+
+            let fen = tags.iter().find(|(name, _)| name == "FEN").map_or(START_POSITION, |(_, fen)| fen.as_str());
+            if !is_valid_fen(fen) {
+                return Err(PgnError::InvalidFen(fen.to_string()));
+            }
+
+            let start_position = create_board(fen);
+            let mut game = PgnGame { tags, start_position, moves: Vec::new() };
+            match result {
+                Some(result) => game.set_tag("Result", &result),
+                None if game.tag("Result").is_none() => game.set_tag("Result", "*"),
+                None => {}
+            }
+
+            let mut movetext_index = 0;
+            game.moves = parse_line(movetext, &mut movetext_index, game.start_position, false)?;
+            games.push(game);
+        }
+
+        Ok(games)
+    }
+
+    //Export format: tags in stored order, movetext in SAN wrapped to 80 columns
+    pub fn to_pgn(&self) -> String {
+        let mut result = String::new();
+        for (name, value) in &self.tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            result.push_str(&format!("[{name} \"{value}\"]\n"));
+        }
+        result.push('\n');
+
+        let mut tokens = Vec::new();
+        write_line(self.start_position, self.start_ply(), &self.moves, &mut tokens);
+        tokens.push(self.result().to_string());
+
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length > 0 && line_length + token.len() + 1 > 80 {
+                result.push('\n');
+                line_length = 0;
+            } else if line_length > 0 {
+                result.push(' ');
+                line_length += 1;
+            }
+
+            result.push_str(&token);
+            line_length += token.len();
+        }
+        result.push_str("\n\n");
+
+        result
+    }
+
+    //Number of plies played before the start position, taken from the move number of FEN tag
+    fn start_ply(&self) -> usize {
+        let move_number = self
+            .tag("FEN")
+            .and_then(|fen| fen.split_whitespace().nth(5))
+            .and_then(|number| number.parse::<usize>().ok())
+            .unwrap_or(1);
+        (move_number.max(1) - 1) * 2 + self.start_position.side_to_move.current()
+    }
+}
+
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    Result(String),
+    Move(String),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+
+    while let Some(character) = chars.next() {
+        let is_line_start = line_start;
+        line_start = character == '\n';
+
+        match character {
+            //Escape lines and rest of line comments, both end with the end of the line
+            '%' if is_line_start => {
+                chars.by_ref().find(|&character| character == '\n');
+                line_start = true;
+            }
+            ';' => {
+                let comment = chars.by_ref().take_while(|&character| character != '\n').collect::<String>();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+                line_start = true;
+            }
+            '[' => {
+                let mut tag = String::new();
+                let mut in_quotes = false;
+                let mut is_closed = false;
+                while let Some(character) = chars.next() {
+                    match character {
+                        '\\' if in_quotes => tag.extend(chars.next()),
+                        '"' => in_quotes = !in_quotes,
+                        ']' if !in_quotes => {
+                            is_closed = true;
+                            break;
+                        }
+                        _ => {}
+                    }
+                    if character != '\\' {
+                        tag.push(character);
+                    }
+                }
+
+                let tag = tag.trim();
+                let parsed = tag.split_once(char::is_whitespace).and_then(|(name, value)| {
+                    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+                    Some(Token::Tag(name.to_string(), value.to_string()))
+                });
+                match parsed {
+                    Some(token) if is_closed => tokens.push(token),
+                    _ => return Err(PgnError::InvalidTag(tag.to_string())),
+                }
+            }
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(character) => comment.push(character),
+                        None => return Err(PgnError::UnclosedComment),
+                    }
+                }
+                tokens.push(Token::Comment(comment.split_whitespace().collect::<Vec<&str>>().join(" ")));
+            }
+            '(' => tokens.push(Token::VariationStart),
+            ')' => tokens.push(Token::VariationEnd),
+            '*' => tokens.push(Token::Result("*".to_string())),
+            '$' => {
+                let mut number = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    number.push(digit);
+                }
+                if let Ok(nag) = number.parse() {
+                    tokens.push(Token::Nag(nag));
+                }
+            }
+            '!' | '?' => {
+                let mut suffix = character.to_string();
+                while let Some(next) = chars.next_if(|&next| next == '!' || next == '?') {
+                    suffix.push(next);
+                }
+                if let Some(nag) = ["!", "?", "!!", "??", "!?", "?!"].iter().position(|&known| known == suffix) {
+                    tokens.push(Token::Nag(nag as u8 + 1));
+                }
+            }
+            _ if character.is_ascii_alphanumeric() => {
+                let mut symbol = character.to_string();
+                while let Some(next) = chars.next_if(|&next| next.is_ascii_alphanumeric() || "_+#=:-/".contains(next)) {
+                    symbol.push(next);
+                }
+
+                //Move numbers are skipped, periods after them are skipped as any other character
+                if RESULTS.contains(&symbol.as_str()) {
+                    tokens.push(Token::Result(symbol));
+                } else if !symbol.chars().all(|character| character.is_ascii_digit()) {
+                    tokens.push(Token::Move(symbol));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(tokens)
+}
+
+//Moves of a line starting in given position. Variation ends with a closing parenthesis, while
+//main line takes all given tokens
+fn parse_line(
+    tokens: &[Token],
+    index: &mut usize,
+    mut board: Board,
+    is_variation: bool,
+) -> Result<Vec<PgnMove>, PgnError> {
+    let mut moves: Vec<PgnMove> = Vec::new();
+    let mut previous_board = board;
+
+    while let Some(token) = tokens.get(*index) {
+        *index += 1;
+        match token {
+            Token::Move(text) => {
+                let mv = board.parse_san(text).map_err(|error| PgnError::InvalidMove {
+                    text: text.clone(),
+                    fen: board.get_fen(),
+                    error,
+                })?;
+                previous_board = board;
+                board.make_move(mv);
+                moves.push(PgnMove::new(mv));
+            }
+            Token::Comment(text) => {
+                if let Some(last) = moves.last_mut() {
+                    last.comment = Some(match last.comment.take() {
+                        Some(comment) => format!("{comment} {text}"),
+                        None => text.clone(),
+                    });
+                }
+            }
+            Token::Nag(nag) => {
+                if let Some(last) = moves.last_mut() {
+                    last.nags.push(*nag);
+                }
+            }
+            Token::VariationStart => {
+                if moves.is_empty() {
+                    return Err(PgnError::UnbalancedVariation);
+                }
+                let variation = parse_line(tokens, index, previous_board, true)?;
+                moves.last_mut().unwrap().variations.push(variation);
+            }
+            Token::VariationEnd => {
+                return if is_variation { Ok(moves) } else { Err(PgnError::UnbalancedVariation) };
+            }
+            Token::Tag(..) | Token::Result(_) => {}
+        }
+    }
+
+    if is_variation {
+        Err(PgnError::UnbalancedVariation)
+    } else {
+        Ok(moves)
+    }
+}
+
+//Appends SAN movetext of the line, with move numbers, annotations, comments and variations
+fn write_line(mut board: Board, mut ply: usize, moves: &[PgnMove], tokens: &mut Vec<String>) {
+    let mut needs_number = true;
+    for pgn_move in moves {
+        //Move number is kept in the same token as the move, so it's never wrapped apart from it
+        let san = board.move_to_san(pgn_move.mv);
+        if board.side_to_move == Side::WHITE {
+            tokens.push(format!("{}. {san}", ply / 2 + 1));
+        } else if needs_number {
+            tokens.push(format!("{}... {san}", ply / 2 + 1));
+        } else {
+            tokens.push(san);
+        }
+        tokens.extend(pgn_move.nags.iter().map(|nag| format!("${nag}")));
+
+        //Comment is split to words, so long comments can be wrapped. Comments can't be escaped, so a
+        //closing brace inside, possible in a comment read after `;`, is dropped
+        if let Some(comment) = &pgn_move.comment {
+            let start = tokens.len();
+            tokens.extend(comment.replace('}', " ").split_whitespace().map(str::to_string));
+            if tokens.len() == start {
+                tokens.push(String::new());
+            }
+            tokens[start].insert(0, '{');
+            tokens.last_mut().unwrap().push('}');
+        }
+
+        for variation in pgn_move.variations.iter().filter(|variation| !variation.is_empty()) {
+            let start = tokens.len();
+            write_line(board, ply, variation, tokens);
+            tokens[start].insert(0, '(');
+            tokens.last_mut().unwrap().push(')');
+        }
+
+        needs_number = pgn_move.comment.is_some() || !pgn_move.variations.is_empty();
+        board.make_move(pgn_move.mv);
+        ply += 1;
+    }
+}
@@ -1,11 +1,14 @@
+use crate::core::{Board, Move};
 use crate::mcts::{GameResult, SearchInfo, SearchTree};
+use crate::options::Options;
 use colored::*;
 
 pub struct SearchReport;
 impl SearchReport {
     pub fn print_report<const PRETTY_PRINT: bool>(
         search_info: &SearchInfo,
-        pv_line: String,
+        root_position: &Board,
+        pv_line: &[Move],
         best_score: f32,
         result: GameResult,
         tree: &SearchTree,
@@ -16,6 +19,13 @@ impl SearchReport {
         let iterations = search_info.current_iterations;
         let nps = (iterations as u128) * 1000 / time.max(1);
 
+        //UCI output always uses long algebraic notation, GUIs don't parse SAN
+        let pv_line = if PRETTY_PRINT && Options::notation() == "san" {
+            root_position.line_to_san(pv_line)
+        } else {
+            pv_line.iter().map(|mv| mv.to_string()).collect::<Vec<String>>().join(" ")
+        };
+
         if PRETTY_PRINT {
             SearchReport::pretty_report(depth, seldepth, time, iterations, nps, best_score, result, pv_line, tree)
         } else {
@@ -0,0 +1,48 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+//Command line arguments after the subcommand. Every `--key` takes a value unless it's one of
+//the given flags, the rest is positional
+pub struct Args {
+    pub positional: Vec<String>,
+    options: HashMap<String, String>,
+    flags: HashSet<String>,
+}
+
+impl Args {
+    pub fn parse(args: &[String], flags: &[&str]) -> Result<Self, String> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        let mut set_flags = HashSet::new();
+
+        let mut index = 0;
+        while index < args.len() {
+            match args[index].strip_prefix("--") {
+                Some(key) if flags.contains(&key) => {
+                    set_flags.insert(key.to_string());
+                }
+                Some(key) => {
+                    let value = args.get(index + 1).ok_or(format!("Missing value for --{key}"))?;
+                    options.insert(key.to_string(), value.clone());
+                    index += 1;
+                }
+                None => positional.push(args[index].clone()),
+            }
+
+            index += 1;
+        }
+
+        Ok(Self { positional, options, flags: set_flags })
+    }
+
+    pub fn get<T: FromStr>(&self, key: &str, default: T) -> Result<T, String> {
+        match self.options.get(key) {
+            Some(value) => value.parse::<T>().map_err(|_| format!("Invalid value '{value}' for --{key}")),
+            None => Ok(default),
+        }
+    }
+
+    pub fn flag(&self, key: &str) -> bool {
+        self.flags.contains(key)
+    }
+}
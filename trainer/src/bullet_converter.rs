@@ -1,77 +1,289 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{FromEntropy, SeedableRng};
 use std::fs::File;
 use std::io::{BufReader, BufWriter, Read, Write};
 use std::mem::size_of;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
 use bullet::format::ChessBoard;
-use datagen::PieceBoard;
+use datagen::{is_game_file, read_games, shard_paths, Files, GameMove, PieceBoard};
 use javelin::{Bitboard, Board, Side, Square};
 
+use crate::args::Args;
+
 const DATA_PATH: &str = "../../resources/data/value.data";
 const OUTPUT_PATH: &str = "../../resources/data/bullet_data.data";
 
-#[allow(unused)]
-pub fn convert_file() {
-    let input_file_path = Path::new(DATA_PATH);
-    let output_file_path = Path::new(OUTPUT_PATH);
+pub const CONVERT_HELP: &str = "Usage: trainer convert [inputs...] [options]
+
+Converts value and game files into bullet data. Without inputs the legacy value file and every
+value or game shard in the data directory are converted.
+
+Options:
+  --output <path>      Output file (default ../../resources/data/bullet_data.data)
+  --max-score <cp>     Skip positions with absolute score above the limit (default 32000)
+  --min-ply <n>        Skip first plies of every game, only applies to game files (default 0)
+  --min-pieces <n>     Skip positions with fewer pieces (default 2)
+  --max-pieces <n>     Skip positions with more pieces (default 32)
+  --skip-in-check      Skip positions with side to move in check
+  --shuffle            Shuffle the output in memory before writing
+  --seed <n>           Shuffle seed, 0 uses a random seed (default 0)
+  --wdl <w>            Result weight used for blended target statistics (default 0.7)";
+
+pub const IMPORT_HELP: &str = "Usage: trainer import <inputs...> [--output <path>]
+
+Converts bullet data back into value records. Bullet boards are stored from side to move
+perspective, so imported positions always have white to move.
+
+Options:
+  --output <path>      Output file (default ../../resources/data/value_imported.data)";
+
+pub struct ConvertSettings {
+    pub inputs: Vec<PathBuf>,
+    pub output: PathBuf,
+    pub max_score: i16,
+    pub min_ply: usize,
+    pub min_pieces: u32,
+    pub max_pieces: u32,
+    pub skip_in_check: bool,
+    pub shuffle: bool,
+    pub seed: u64,
+    pub wdl: f32,
+}
+
+impl ConvertSettings {
+    pub const FLAGS: [&'static str; 2] = ["skip-in-check", "shuffle"];
 
-    let input_file = File::open(input_file_path).unwrap();
-    let input_metadata = input_file.metadata().unwrap();
-    let mut reader = BufReader::new(input_file);
+    pub fn from_args(args: &Args) -> Result<Self, String> {
+        let mut inputs = args.positional.iter().map(PathBuf::from).collect::<Vec<_>>();
+        if inputs.is_empty() {
+            inputs.push(PathBuf::from(DATA_PATH));
+            inputs.extend(shard_paths(Path::new(Files::DATA_DIRECTORY), "value"));
+            inputs.extend(shard_paths(Path::new(Files::DATA_DIRECTORY), "games"));
+            inputs.retain(|path| path.exists());
+        }
 
-    let output_file = File::create(output_file_path).unwrap();
+        Ok(Self {
+            inputs,
+            output: PathBuf::from(args.get::<String>("output", OUTPUT_PATH.to_string())?),
+            max_score: args.get("max-score", 32000)?,
+            min_ply: args.get("min-ply", 0)?,
+            min_pieces: args.get("min-pieces", 2)?,
+            max_pieces: args.get("max-pieces", 32)?,
+            skip_in_check: args.flag("skip-in-check"),
+            shuffle: args.flag("shuffle"),
+            seed: args.get("seed", 0)?,
+            wdl: args.get("wdl", 0.7)?,
+        })
+    }
+}
+
+pub fn convert_file(settings: &ConvertSettings) {
+    let output_file = File::create(&settings.output).unwrap();
     let mut writer = BufWriter::new(output_file);
 
-    let piece_board_size = size_of::<PieceBoard>();
-    let chess_board_size = size_of::<ChessBoard>();
+    let mut progress = ConvertProgress::new(
+        settings
+            .inputs
+            .iter()
+            .filter(|path| !is_game_file(path))
+            .map(|path| path.metadata().unwrap().len() / size_of::<PieceBoard>() as u64)
+            .sum(),
+    );
+
+    let mut converted = Vec::new();
+    for path in &settings.inputs {
+        if is_game_file(path) {
+            for game in read_games(path).unwrap() {
+                //Expanded positions skip value filtered moves, so plies are recovered from the flags
+                let plies = game
+                    .moves
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (entry, _))| entry.flags & GameMove::VALUE_FILTERED == 0)
+                    .map(|(ply, _)| ply);
+                for (ply, piece_board) in plies.zip(game.expand().0) {
+                    progress.data_loaded += 1;
+                    if let Some(chess_board) = progress.convert_entry(&piece_board, Some(ply), settings) {
+                        converted.push(chess_board);
+                    }
+                }
+
+                if !settings.shuffle {
+                    converted.drain(..).for_each(|chess_board| write_chess_board(&chess_board, &mut writer));
+                }
+            }
+            continue;
+        }
+
+        let mut reader = BufReader::new(File::open(path).unwrap());
+        let mut buffer = vec![0u8; size_of::<PieceBoard>()];
+        while reader.read_exact(&mut buffer).is_ok() {
+            let piece_board: PieceBoard = unsafe { std::ptr::read(buffer.as_ptr() as *const _) };
+            if let Some(chess_board) = progress.convert_entry(&piece_board, None, settings) {
+                if settings.shuffle {
+                    converted.push(chess_board);
+                } else {
+                    write_chess_board(&chess_board, &mut writer);
+                }
+            }
+        }
+    }
+
+    if settings.shuffle {
+        println!("\nShuffling {} entries...", converted.len());
+        let mut rng = match settings.seed {
+            0 => StdRng::from_entropy(),
+            seed => StdRng::seed_from_u64(seed),
+        };
+        converted.shuffle(&mut rng);
+        converted.iter().for_each(|chess_board| write_chess_board(chess_board, &mut writer));
+    }
+
+    writer.flush().unwrap();
+
+    println!("File converted!");
+    println!("Data loaded: {}", progress.data_loaded);
+    println!("Total entries: {}", progress.entry_count);
+    println!("Entries written: {}", progress.data_written);
+    println!("Entries filtered: {}", progress.data_filtered);
+    println!(" - Mate score: {}", progress.mate_filtered);
+    println!(" - Score past limit: {}", progress.score_filtered);
+    println!(" - Opening ply: {}", progress.ply_filtered);
+    println!(" - Piece count: {}", progress.pieces_filtered);
+    println!(" - In check: {}", progress.check_filtered);
+    progress.print_blend_stats(settings.wdl);
+}
+
+//Bullet boards are side to move relative, so the imported record always has white to move and
+//both score and result are taken from side to move perspective
+pub fn import_file(inputs: &[PathBuf], output: &Path) {
+    let mut writer = BufWriter::new(File::create(output).unwrap());
+    let mut buffer = vec![0u8; size_of::<ChessBoard>()];
+    let mut imported = 0u64;
 
-    let mut buffer = vec![0u8; piece_board_size];
+    for path in inputs {
+        let mut reader = BufReader::new(File::open(path).unwrap());
+        while reader.read_exact(&mut buffer).is_ok() {
+            let chess_board: ChessBoard = unsafe { std::ptr::read(buffer.as_ptr() as *const _) };
+            let piece_board = convert_to_piece_board(chess_board);
+            writer.write_all(bytemuck::bytes_of(&piece_board)).unwrap();
+            imported += 1;
+        }
+    }
 
-    let data_loaded = input_metadata.len() / piece_board_size as u64;
+    writer.flush().unwrap();
+    println!("Imported {imported} entries into {}", output.display());
+}
 
-    let mut entry_count = 0;
-    let mut data_written = 0;
-    let mut data_filtered = 0;
-    let mut mate_filtered = 0;
+struct ConvertProgress {
+    data_loaded: u64,
+    entry_count: u64,
+    data_written: u64,
+    data_filtered: u64,
+    mate_filtered: u64,
+    score_filtered: u64,
+    ply_filtered: u64,
+    pieces_filtered: u64,
+    check_filtered: u64,
+    results: [u64; 3],
+    score_sum: f64,
+    result_sum: f64,
+    error_sum: f64,
+    timer: Instant,
+    entries_this_second: u64,
+}
 
-    let mut timer = Instant::now();
-    let mut entries_this_second = 0u64;
+impl ConvertProgress {
+    fn new(data_loaded: u64) -> Self {
+        Self {
+            data_loaded,
+            entry_count: 0,
+            data_written: 0,
+            data_filtered: 0,
+            mate_filtered: 0,
+            score_filtered: 0,
+            ply_filtered: 0,
+            pieces_filtered: 0,
+            check_filtered: 0,
+            results: [0; 3],
+            score_sum: 0.0,
+            result_sum: 0.0,
+            error_sum: 0.0,
+            timer: Instant::now(),
+            entries_this_second: 0,
+        }
+    }
 
-    while reader.read_exact(&mut buffer).is_ok() {
-        let piece_board: PieceBoard = unsafe { std::ptr::read(buffer.as_ptr() as *const _) };
-        entry_count += 1;
-        entries_this_second += 1;
+    fn convert_entry(
+        &mut self,
+        piece_board: &PieceBoard,
+        ply: Option<usize>,
+        settings: &ConvertSettings,
+    ) -> Option<ChessBoard> {
+        self.entry_count += 1;
+        self.entries_this_second += 1;
 
-        if timer.elapsed().as_secs_f32() >= 1.0 {
-            let entries_to_go = data_loaded - entry_count;
-            let seconds_remaining = entries_to_go / entries_this_second.max(1);
+        if self.timer.elapsed().as_secs_f32() >= 1.0 {
+            let entries_to_go = self.data_loaded.saturating_sub(self.entry_count);
+            let seconds_remaining = entries_to_go / self.entries_this_second.max(1);
             let minutes = seconds_remaining / 60;
 
-            print!("Entry {:.2}k/{:.2}k ({entries_this_second}). Current staus: {:.2}k/{:.2}k (written/filtered). Time remaining: {minutes}m {}s\r", 
-                entry_count as f32 / 1000.0, 
-                data_loaded as f32 / 1000.0, 
-                data_written as f32 / 1000.0, 
-                data_filtered as f32/ 1000.0, 
+            print!("Entry {:.2}k/{:.2}k ({}). Current staus: {:.2}k/{:.2}k (written/filtered). Time remaining: {minutes}m {}s\r",
+                self.entry_count as f32 / 1000.0,
+                self.data_loaded as f32 / 1000.0,
+                self.entries_this_second,
+                self.data_written as f32 / 1000.0,
+                self.data_filtered as f32/ 1000.0,
                 seconds_remaining % 60);
             let _ = std::io::stdout().flush();
 
-            timer = Instant::now();
-            entries_this_second = 0;
+            self.timer = Instant::now();
+            self.entries_this_second = 0;
         }
 
         if piece_board.score <= 0.0 || piece_board.score >= 1.0 {
-            data_filtered += 1;
-            mate_filtered += 1;
-            continue;
+            self.data_filtered += 1;
+            self.mate_filtered += 1;
+            return None;
         }
 
-        let board = Board::from_datapack(&convert_to_12_bitboards(piece_board.piece_boards), piece_board.side_to_move);
+        let board = Board::from_datapack(&piece_board.to_bitboards(), piece_board.side_to_move);
 
         let perspective_score = if piece_board.side_to_move == 0 { piece_board.score } else { 1.0 - piece_board.score };
         let score = -(400.0 * (1.0 / perspective_score - 1.0).ln()) as i16;
         let result = (piece_board.result + 1) as f32 / 2.0;
 
+        if score.saturating_abs() > settings.max_score {
+            self.data_filtered += 1;
+            self.score_filtered += 1;
+            return None;
+        }
+
+        if ply.is_some_and(|ply| ply < settings.min_ply) {
+            self.data_filtered += 1;
+            self.ply_filtered += 1;
+            return None;
+        }
+
+        let piece_count = board.get_occupancy().pop_count();
+        if piece_count < settings.min_pieces || piece_count > settings.max_pieces {
+            self.data_filtered += 1;
+            self.pieces_filtered += 1;
+            return None;
+        }
+
+        //Datapack board has no checkers set, so the check is detected from attacks
+        if settings.skip_in_check
+            && board.is_square_attacked(board.get_king_square(board.side_to_move), board.side_to_move.flipped())
+        {
+            self.data_filtered += 1;
+            self.check_filtered += 1;
+            return None;
+        }
+
         let bbs = [
             board.get_occupancy_for_side(Side::WHITE).get_value(),
             board.get_occupancy_for_side(Side::BLACK).get_value(),
@@ -85,37 +297,58 @@ pub fn convert_file() {
 
         let chess_board = ChessBoard::from_raw(bbs, board.side_to_move.current(), score, result).unwrap();
 
-        let chess_board_bytes = unsafe {
-            std::slice::from_raw_parts(
-                (&chess_board as *const ChessBoard) as *const u8,
-                chess_board_size,
-            )
-        };
+        self.data_written += 1;
+        self.results[(piece_board.result.clamp(-1, 1) + 1) as usize] += 1;
+        self.score_sum += f64::from(perspective_score);
+        self.result_sum += f64::from(result);
+        self.error_sum += f64::from((perspective_score - result).powi(2));
 
-        writer.write_all(chess_board_bytes).unwrap();
-        data_written += 1;
+        Some(chess_board)
     }
 
-    println!("File converted!");
-    println!("Data loaded: {}", data_loaded);
-    println!("Total entries: {}", entry_count);
-    println!("Entries written: {}", data_written);
-    println!("Entries filtered: {}", data_filtered);
-    println!(" - Mate score: {}", mate_filtered);
+    //Shows how far search scores are from game results, which is what the wdl blend of the
+    //value trainer mixes together
+    fn print_blend_stats(&self, wdl: f32) {
+        if self.data_written == 0 {
+            return;
+        }
+
+        let count = self.data_written as f64;
+        let wdl = f64::from(wdl);
+        let score_mean = self.score_sum / count;
+        let result_mean = self.result_sum / count;
+
+        println!("\nBlend statistics (white perspective):");
+        println!(" - W/D/L: {}/{}/{}", self.results[2], self.results[1], self.results[0]);
+        println!(" - Average score: {score_mean:.4}");
+        println!(" - Average result: {result_mean:.4}");
+        println!(" - Score to result MSE: {:.4}", self.error_sum / count);
+        println!(" - Average target at wdl {wdl:.2}: {:.4}", wdl * result_mean + (1.0 - wdl) * score_mean);
+    }
 }
 
-fn convert_to_12_bitboards(board: [Bitboard; 4]) -> [Bitboard; 12] {
-    let mut result = [Bitboard::EMPTY; 12];
-    for square_index in 0..64 {
-        let square = Square::from_raw(square_index);
-        let piece_index: usize = (if board[0].get_bit(square) { 1 } else { 0 }
-            | if board[1].get_bit(square) { 2 } else { 0 }
-            | if board[2].get_bit(square) { 4 } else { 0 })
-            + if board[3].get_bit(square) { 6 } else { 0 };
-        if piece_index == 0 {
-            continue;
-        }
-        result[piece_index - 1].set_bit(square);
+fn write_chess_board(chess_board: &ChessBoard, writer: &mut BufWriter<File>) {
+    let chess_board_bytes =
+        unsafe { std::slice::from_raw_parts((chess_board as *const ChessBoard) as *const u8, size_of::<ChessBoard>()) };
+
+    writer.write_all(chess_board_bytes).unwrap();
+}
+
+//Bullet pieces are `color << 3 | piece`, with pieces ordered from pawn to king and color 0
+//being the side to move
+fn convert_to_piece_board(chess_board: ChessBoard) -> PieceBoard {
+    let mut bitboards = [Bitboard::EMPTY; 12];
+    let score = chess_board.score();
+    let result = chess_board.result();
+
+    for (piece, square) in chess_board.into_iter() {
+        let piece_index = usize::from(piece & 7) + if piece & 8 > 0 { 6 } else { 0 };
+        bitboards[piece_index].set_bit(Square::from_raw(usize::from(square)));
     }
-    result
+
+    let board = Board::from_datapack(&bitboards, 0);
+    let mut piece_board = PieceBoard::from_board(&board);
+    piece_board.score = 1.0 / (1.0 + (-f32::from(score) / 400.0).exp());
+    piece_board.result = (result * 2.0).round() as i8 - 1;
+    piece_board
 }
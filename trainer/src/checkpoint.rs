@@ -0,0 +1,117 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+//Everything needed to continue training: the network, Adam moments and the position in the
+//schedule and in the data
+pub struct TrainingState<T> {
+    pub network: Box<T>,
+    pub momentum: Box<T>,
+    pub velocity: Box<T>,
+    pub superbatch_index: usize,
+    pub learning_rate: f32,
+    pub data_index: usize,
+}
+
+impl<T> TrainingState<T> {
+    pub fn new(network: Box<T>, learning_rate: f32) -> Self {
+        Self {
+            network,
+            momentum: boxed_and_zeroed(),
+            velocity: boxed_and_zeroed(),
+            superbatch_index: 0,
+            learning_rate,
+            data_index: 0,
+        }
+    }
+
+    pub fn save(&self, directory: &Path) -> io::Result<()> {
+        fs::create_dir_all(directory)?;
+        export(self.network.as_ref(), &directory.join("network.net"));
+        export(self.momentum.as_ref(), &directory.join("momentum.net"));
+        export(self.velocity.as_ref(), &directory.join("velocity.net"));
+        fs::write(
+            directory.join("state.txt"),
+            format!(
+                "superbatch = {}\nlearning_rate = {}\ndata_index = {}\n",
+                self.superbatch_index, self.learning_rate, self.data_index
+            ),
+        )
+    }
+
+    pub fn load(directory: &Path) -> io::Result<Self> {
+        let state = fs::read_to_string(directory.join("state.txt"))?;
+        let value = |key: &str| {
+            state
+                .lines()
+                .filter_map(|line| line.split_once('='))
+                .find(|(name, _)| name.trim() == key)
+                .map(|(_, value)| value.trim().to_string())
+                .ok_or(io::Error::new(io::ErrorKind::InvalidData, format!("Missing '{key}' in checkpoint state")))
+        };
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "Invalid checkpoint state");
+
+        Ok(Self {
+            network: load(&directory.join("network.net"))?,
+            momentum: load(&directory.join("momentum.net"))?,
+            velocity: load(&directory.join("velocity.net"))?,
+            superbatch_index: value("superbatch")?.parse().map_err(|_| invalid())?,
+            learning_rate: value("learning_rate")?.parse().map_err(|_| invalid())?,
+            data_index: value("data_index")?.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+pub fn boxed_and_zeroed<T>() -> Box<T> {
+    unsafe {
+        let layout = std::alloc::Layout::new::<T>();
+        let ptr = std::alloc::alloc_zeroed(layout);
+        if ptr.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        Box::from_raw(ptr.cast())
+    }
+}
+
+pub fn export<T>(net: &T, path: &Path) {
+    let size = std::mem::size_of::<T>();
+
+    let mut file = std::fs::File::create(path).unwrap();
+
+    unsafe {
+        let slice: *const u8 = std::slice::from_ref(net).as_ptr().cast();
+        let struct_bytes: &[u8] = std::slice::from_raw_parts(slice, size);
+        file.write_all(struct_bytes).expect("Failed to write data!");
+    }
+}
+
+pub fn load<T>(path: &Path) -> io::Result<Box<T>> {
+    let bytes = fs::read(path)?;
+    if bytes.len() != std::mem::size_of::<T>() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} has unexpected size", path.display())));
+    }
+
+    let mut net = boxed_and_zeroed::<T>();
+    unsafe {
+        let slice: *mut u8 = std::slice::from_mut(net.as_mut()).as_mut_ptr().cast();
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), slice, bytes.len());
+    }
+
+    Ok(net)
+}
+
+//Copies a network into another type with the same memory layout
+pub fn reinterpret<T, U>(net: &T) -> Box<U> {
+    assert_eq!(std::mem::size_of::<T>(), std::mem::size_of::<U>());
+
+    let mut result = boxed_and_zeroed::<U>();
+    unsafe {
+        std::ptr::copy_nonoverlapping(
+            std::slice::from_ref(net).as_ptr().cast::<u8>(),
+            std::slice::from_mut(result.as_mut()).as_mut_ptr().cast::<u8>(),
+            std::mem::size_of::<T>(),
+        );
+    }
+
+    result
+}
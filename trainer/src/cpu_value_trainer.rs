@@ -0,0 +1,248 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use datagen::{is_game_file, read_games, shard_paths, Files, PieceBoard};
+use goober::{activation, layer::DenseConnected, layer::SparseConnected, FeedForwardNetwork, Matrix, OutputLayer};
+use goober::{SparseVector, Vector};
+use javelin::{Board, Features, ValueNetwork};
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+
+use crate::checkpoint::{boxed_and_zeroed, export, TrainingState};
+use crate::value_trainer::ValueSettings;
+
+//Same layers as the engine value network. Goober layers apply the activation to their own
+//output, while the engine applies it to the input of the next layer, so both compute the same
+//function and share the memory layout
+#[repr(C)]
+#[derive(Clone, Copy, FeedForwardNetwork)]
+pub struct ValueNet {
+    input_layer: SparseConnected<activation::SCReLU, 768, 512>,
+    hidden_layer: DenseConnected<activation::SCReLU, 512, 32>,
+    output_layer: DenseConnected<activation::Identity, 32, 1>,
+}
+
+const _: () = assert!(size_of::<ValueNet>() == size_of::<ValueNetwork>());
+
+pub struct CpuValueTrainer;
+impl CpuValueTrainer {
+    pub fn run(settings: &ValueSettings) -> io::Result<()> {
+        let superbatches = settings.superbatches;
+        let batch_size = settings.batch_size.max(1);
+        let batches_per_superbatch = settings.batches_per_superbatch.max(1);
+        let threads = settings.threads.max(1);
+
+        let mut data_paths = settings.data_paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+        if data_paths.is_empty() {
+            data_paths.push(Path::new(Files::DATA_DIRECTORY).join(Files::VALUE_FILE));
+            data_paths.extend(shard_paths(Path::new(Files::DATA_DIRECTORY), "value"));
+            data_paths.extend(shard_paths(Path::new(Files::DATA_DIRECTORY), "games"));
+            data_paths.retain(|path| path.exists());
+        }
+
+        let mut train_data = load_entries(&data_paths)?;
+        if train_data.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "No value data found"));
+        }
+
+        let validation_data = match &settings.validation {
+            Some(path) => load_entries(&[PathBuf::from(path)])?,
+            None => Vec::new(),
+        };
+
+        let mut state = match &settings.resume {
+            Some(checkpoint) => TrainingState::<ValueNet>::load(Path::new(checkpoint))?,
+            None => TrainingState::new(rand_init(), settings.learning_rate),
+        };
+        let output_directory = Path::new(&settings.output_directory);
+        let throughput = superbatches.saturating_sub(state.superbatch_index) * batches_per_superbatch * batch_size;
+
+        println!("Network Name: {}", settings.name);
+        println!("Export Path: {}", output_directory.display());
+        println!("Thread Count: {threads}");
+        println!("Loaded Positions: {}", train_data.len());
+        println!("Validation Positions: {}", validation_data.len());
+        println!("Superbatches: {superbatches}");
+        println!("LR Step: {}", settings.lr_step);
+        println!("Start LR: {}", settings.learning_rate);
+        println!("WDL: {}", settings.wdl);
+        if settings.resume.is_some() {
+            println!("Resumed From Superbatch: {}", state.superbatch_index);
+        }
+        println!("Epochs {:.2}\n", throughput as f64 / train_data.len() as f64);
+
+        let mut rng = thread_rng();
+        train_data.shuffle(&mut rng);
+        state.data_index %= train_data.len();
+
+        while state.superbatch_index < superbatches {
+            //Step schedule counted from the first superbatch, the same way bullet's StepLR does it
+            let superbatch = state.superbatch_index + 1;
+            state.learning_rate =
+                settings.learning_rate * settings.lr_gamma.powi(((superbatch - 1) / settings.lr_step.max(1)) as i32);
+
+            let timer = Instant::now();
+            let mut running_error = 0.0;
+
+            for batch_index in 0..batches_per_superbatch {
+                if state.data_index + batch_size > train_data.len() {
+                    train_data.shuffle(&mut rng);
+                    state.data_index = 0;
+                }
+
+                let batch_end = (state.data_index + batch_size).min(train_data.len());
+                let batch = &train_data[state.data_index..batch_end];
+                state.data_index = batch_end % train_data.len();
+
+                let mut grad = boxed_and_zeroed::<ValueNet>();
+                running_error += gradient_batch(threads, &state.network, &mut grad, batch, settings.wdl);
+                let adj = 1.0 / batch.len() as f32;
+                state.network.adam(&grad, &mut state.momentum, &mut state.velocity, adj, state.learning_rate);
+
+                print!(
+                    "> Superbatch {superbatch}/{superbatches} Batch {}/{batches_per_superbatch} Speed {:.0}\r",
+                    batch_index + 1,
+                    ((batch_index + 1) * batch_size) as f32 / timer.elapsed().as_secs_f32()
+                );
+                let _ = std::io::stdout().flush();
+            }
+
+            state.superbatch_index = superbatch;
+            print!(
+                "> Superbatch {superbatch}/{superbatches} Running Loss {}",
+                running_error / (batches_per_superbatch * batch_size) as f32
+            );
+            if !validation_data.is_empty() {
+                print!(" Validation Loss {}", validation_loss(threads, &state.network, &validation_data, settings.wdl));
+            }
+            println!(" LR {}", state.learning_rate);
+
+            if superbatch % settings.save_rate.max(1) == 0 || superbatch == superbatches {
+                let name = format!("{}-sb{superbatch}", settings.name);
+                export(state.network.as_ref(), &output_directory.join(format!("{name}.net")));
+                state.save(&output_directory.join(name))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn load_entries(paths: &[PathBuf]) -> io::Result<Vec<PieceBoard>> {
+    let mut result = Vec::new();
+    for path in paths {
+        if is_game_file(path) {
+            for game in read_games(path)? {
+                result.extend(game.expand().0);
+            }
+            continue;
+        }
+
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut buffer = vec![0u8; size_of::<PieceBoard>()];
+        while reader.read_exact(&mut buffer).is_ok() {
+            result.push(bytemuck::pod_read_unaligned::<PieceBoard>(&buffer));
+        }
+    }
+
+    //Mate scores have no finite centipawn equivalent, the converter drops them as well
+    result.retain(|entry| entry.score > 0.0 && entry.score < 1.0);
+    Ok(result)
+}
+
+pub fn extract_inputs(entry: &PieceBoard) -> SparseVector {
+    Features::value_inputs(&Board::from_datapack(&entry.to_bitboards(), entry.side_to_move))
+}
+
+//Blend of game result and search score, both from side to move perspective
+fn target(entry: &PieceBoard, wdl: f32) -> f32 {
+    let white_result = f32::from(entry.result + 1) / 2.0;
+    let result = if entry.side_to_move == 0 { white_result } else { 1.0 - white_result };
+    wdl * result + (1.0 - wdl) * entry.score
+}
+
+fn sigmoid(value: f32) -> f32 {
+    1.0 / (1.0 + (-value).exp())
+}
+
+fn gradient_batch(threads: usize, net: &ValueNet, grad: &mut ValueNet, batch: &[PieceBoard], wdl: f32) -> f32 {
+    let size = (batch.len() / threads).max(1);
+    let mut errors = vec![0.0; threads];
+
+    std::thread::scope(|s| {
+        batch
+            .chunks(size)
+            .zip(errors.iter_mut())
+            .map(|(chunk, error)| {
+                s.spawn(move || {
+                    let mut inner_grad = boxed_and_zeroed();
+                    for entry in chunk {
+                        update_single_grad(entry, net, &mut inner_grad, error, wdl);
+                    }
+                    inner_grad
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|p| p.join().unwrap())
+            .for_each(|part| *grad += part.as_ref());
+    });
+
+    errors.iter().sum::<f32>()
+}
+
+//Sigmoid MSE, matching bullet's SigmoidMSE with eval scale 400. Engine evaluation is the raw
+//output multiplied by the same scale
+fn update_single_grad(entry: &PieceBoard, net: &ValueNet, grad: &mut ValueNet, error: &mut f32, wdl: f32) {
+    let input = extract_inputs(entry);
+    let layers = net.out_with_layers(&input);
+    let prediction = sigmoid(layers.output_layer()[0]);
+    let difference = prediction - target(entry, wdl);
+
+    *error += difference.powi(2);
+
+    let output_error = 2.0 * difference * prediction * (1.0 - prediction);
+    net.backprop(&input, grad, Vector::from_fn(|_| output_error), &layers);
+}
+
+fn validation_loss(threads: usize, net: &ValueNet, data: &[PieceBoard], wdl: f32) -> f32 {
+    let size = (data.len() / threads).max(1);
+
+    let error = std::thread::scope(|s| {
+        data.chunks(size)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|entry| (sigmoid(net.out(&extract_inputs(entry))[0]) - target(entry, wdl)).powi(2))
+                        .sum::<f32>()
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|p| p.join().unwrap())
+            .sum::<f32>()
+    });
+
+    error / data.len() as f32
+}
+
+//Uniform initialization scaled by the number of inputs of the layer. Sparse layer sees about
+//32 active inputs at once, so it's scaled by that instead of its full size
+pub fn rand_init() -> Box<ValueNet> {
+    let mut net = boxed_and_zeroed::<ValueNet>();
+    let mut rng = thread_rng();
+    let mut random = |fan_in: f32| (rng.gen::<f32>() * 2.0 - 1.0) / fan_in.sqrt();
+
+    net.input_layer =
+        SparseConnected::from_raw(Matrix::from_fn(|_, _| random(32.0)), Vector::from_fn(|_| random(32.0)));
+    net.hidden_layer =
+        DenseConnected::from_raw(Matrix::from_fn(|_, _| random(512.0)), Vector::from_fn(|_| random(512.0)));
+    net.output_layer =
+        DenseConnected::from_raw(Matrix::from_fn(|_, _| random(32.0)), Vector::from_fn(|_| random(32.0)));
+
+    net
+}
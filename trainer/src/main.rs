@@ -1,27 +1,144 @@
+mod args;
+#[cfg(feature = "bullet")]
 mod bullet_converter;
+mod checkpoint;
+mod cpu_value_trainer;
+mod net_evaluator;
+mod parity_checker;
 mod policy_data_loader;
 mod policy_trainer;
+mod texel_tuner;
 mod value_trainer;
 
-#[allow(unused)]
-use bullet_converter::convert_file;
+use args::Args;
+#[cfg(feature = "bullet")]
+use bullet_converter::{ConvertSettings, CONVERT_HELP, IMPORT_HELP};
 use colored::Colorize;
+use cpu_value_trainer::CpuValueTrainer;
+use net_evaluator::{EvaluateSettings, EVALUATE_HELP};
+use parity_checker::{ParitySettings, PARITY_HELP};
 use javelin::{Bitboard, Side, Square};
-use policy_trainer::PolicyTrainer;
+use policy_trainer::{PolicySettings, PolicyTrainer, POLICY_HELP};
+use texel_tuner::{TexelSettings, TEXEL_HELP};
+use std::env;
+#[cfg(feature = "bullet")]
+use std::path::PathBuf;
+#[cfg(feature = "bullet")]
 use value_trainer::ValueTrainer;
+use value_trainer::{ValueSettings, VALUE_HELP};
+
+const HELP: &str = "Usage: trainer <command> [options]
+
+Commands:
+  value      Train the value network with bullet
+  value-cpu  Train the value network on the CPU
+  policy     Train the policy network
+  convert    Convert value and game data into bullet data
+  import     Convert bullet data back into value records
+  evaluate   Report loss and accuracy of nets on a dataset
+  parity     Check that the trainer reads positions the same way the engine does
+  texel      Tune PeSTO piece values and tables to value data
+
+Run `trainer <command> --help` to see options of the command. Commands using bullet are only
+available when the trainer is built with the default `bullet` feature.";
 
 fn main() {
-    value_trainer();
-}
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let Some(command) = args.first() else {
+        println!("{HELP}");
+        return;
+    };
 
-#[allow(unused)]
-fn value_trainer() {
-    ValueTrainer::run();
+    let args = &args[1..];
+    match command.as_str() {
+        #[cfg(feature = "bullet")]
+        "value" => {
+            let settings = parse_settings(args, &[], VALUE_HELP, ValueSettings::from_args);
+            ValueTrainer::run(&settings);
+        }
+        "value-cpu" => {
+            let settings = parse_settings(args, &[], VALUE_HELP, ValueSettings::from_args);
+            if let Err(err) = CpuValueTrainer::run(&settings) {
+                eprintln!("Value training failed: {err}");
+                std::process::exit(1);
+            }
+        }
+        "policy" => {
+            let settings = parse_settings(args, &[], POLICY_HELP, PolicySettings::from_args);
+            if let Err(err) = PolicyTrainer::train(&settings) {
+                eprintln!("Policy training failed: {err}");
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "bullet")]
+        "convert" => {
+            let settings = parse_settings(args, &ConvertSettings::FLAGS, CONVERT_HELP, ConvertSettings::from_args);
+            bullet_converter::convert_file(&settings);
+        }
+        #[cfg(feature = "bullet")]
+        "import" => {
+            let (inputs, output) = parse_settings(args, &[], IMPORT_HELP, |args| {
+                if args.positional.is_empty() {
+                    return Err("Missing <inputs> argument".to_string());
+                }
+
+                let output = args.get::<String>("output", "../../resources/data/value_imported.data".to_string())?;
+                Ok((args.positional.iter().map(PathBuf::from).collect::<Vec<_>>(), PathBuf::from(output)))
+            });
+            bullet_converter::import_file(&inputs, &output);
+        }
+        "evaluate" => {
+            let settings = parse_settings(args, &[], EVALUATE_HELP, EvaluateSettings::from_args);
+            if let Err(err) = net_evaluator::evaluate(&settings) {
+                eprintln!("Evaluation failed: {err}");
+                std::process::exit(1);
+            }
+        }
+        "parity" => {
+            let settings = parse_settings(args, &[], PARITY_HELP, ParitySettings::from_args);
+            match parity_checker::check(&settings) {
+                Ok(true) => println!("\nTrainer and engine features match"),
+                Ok(false) => std::process::exit(1),
+                Err(err) => {
+                    eprintln!("Parity check failed: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        "texel" => {
+            let settings = parse_settings(args, &[], TEXEL_HELP, TexelSettings::from_args);
+            if let Err(err) = texel_tuner::tune(&settings) {
+                eprintln!("Texel tuning failed: {err}");
+                std::process::exit(1);
+            }
+        }
+        "help" | "--help" | "-h" => println!("{HELP}"),
+        _ => {
+            eprintln!("Unknown command '{command}'\n\n{HELP}");
+            std::process::exit(1);
+        }
+    }
 }
 
-#[allow(unused)]
-fn policy_trainer() {
-    PolicyTrainer::train("policy_008b", 7, 60, 0.001, 25);
+//Prints command help and exits when asked for it or when the arguments are invalid
+fn parse_settings<T>(
+    args: &[String],
+    flags: &[&str],
+    help: &str,
+    from_args: impl FnOnce(&Args) -> Result<T, String>,
+) -> T {
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{help}");
+        std::process::exit(0);
+    }
+
+    match Args::parse(args, flags).and_then(|args| from_args(&args)) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("{err}\n\n{help}");
+            std::process::exit(1);
+        }
+    }
 }
 
 #[allow(unused)]
@@ -0,0 +1,372 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use datagen::{is_game_file, ChessPolicyData, PieceBoard};
+use javelin::{Board, Features, Move, PolicyNetwork, ValueNetwork};
+use rayon::prelude::*;
+
+use crate::args::Args;
+use crate::checkpoint::load;
+use crate::cpu_value_trainer::load_entries;
+use crate::policy_data_loader::PolicyRecordReader;
+
+pub const EVALUATE_HELP: &str = "Usage: trainer evaluate <data files...> [options]
+
+Reports loss and accuracy of value and policy nets on a dataset, broken down by game phase. Nets
+are evaluated with the same code the engine uses. Game files are used for both nets, other files
+are read as policy records when their name contains `policy` and as value records otherwise.
+
+Options:
+  --value <nets>              Comma separated value nets to evaluate
+  --policy <nets>             Comma separated policy nets to evaluate
+  --limit <n>                 Maximum number of positions read for each net type, 0 reads all (default 100000)
+  --bins <n>                  Number of calibration bins (default 10)";
+
+pub struct EvaluateSettings {
+    pub data_paths: Vec<PathBuf>,
+    pub value_nets: Vec<PathBuf>,
+    pub policy_nets: Vec<PathBuf>,
+    pub limit: usize,
+    pub bins: usize,
+}
+
+impl EvaluateSettings {
+    pub fn from_args(args: &Args) -> Result<Self, String> {
+        if args.positional.is_empty() {
+            return Err("Missing <data files> argument".to_string());
+        }
+
+        let nets = |key: &str| -> Result<Vec<PathBuf>, String> {
+            Ok(args
+                .get::<String>(key, String::new())?
+                .split(',')
+                .filter(|s| !s.is_empty())
+                .map(PathBuf::from)
+                .collect())
+        };
+
+        let value_nets = nets("value")?;
+        let policy_nets = nets("policy")?;
+        if value_nets.is_empty() && policy_nets.is_empty() {
+            return Err("Nothing to evaluate, pass nets with --value or --policy".to_string());
+        }
+
+        Ok(Self {
+            data_paths: args.positional.iter().map(PathBuf::from).collect(),
+            value_nets,
+            policy_nets,
+            limit: args.get("limit", 100_000)?,
+            bins: args.get::<usize>("bins", 10)?.max(1),
+        })
+    }
+}
+
+const PHASES: [&str; 3] = ["opening", "middlegame", "endgame"];
+const MOVE_TYPES: [&str; 4] = ["quiet", "capture", "promotion", "castling"];
+
+pub fn evaluate(settings: &EvaluateSettings) -> io::Result<()> {
+    if !settings.value_nets.is_empty() {
+        let value_paths = settings
+            .data_paths
+            .iter()
+            .filter(|path| is_game_file(path) || !is_policy_file(path))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut entries = load_entries(&value_paths)?;
+        if settings.limit > 0 {
+            entries.truncate(settings.limit);
+        }
+        if entries.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "No value data found"));
+        }
+
+        for path in &settings.value_nets {
+            let net = load::<ValueNetwork>(path)?;
+            print_value_report(path, &evaluate_value_net(&net, &entries, settings.bins));
+        }
+    }
+
+    if !settings.policy_nets.is_empty() {
+        let policy_paths = settings
+            .data_paths
+            .iter()
+            .filter(|path| is_game_file(path) || is_policy_file(path))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut reader = PolicyRecordReader::new(policy_paths);
+        let mut entries = Vec::new();
+        while settings.limit == 0 || entries.len() < settings.limit {
+            match reader.next()? {
+                Some(entry) if entry.board.num > 0 => entries.push(entry),
+                Some(_) => (),
+                None => break,
+            }
+        }
+        if entries.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "No policy data found"));
+        }
+
+        for path in &settings.policy_nets {
+            let net = load::<PolicyNetwork>(path)?;
+            print_policy_report(path, &evaluate_policy_net(&net, &entries));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn is_policy_file(path: &Path) -> bool {
+    path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.contains("policy"))
+}
+
+//Phase from remaining material, weighted the same way PeSTO tapers its evaluation
+fn game_phase(entry: &PieceBoard) -> usize {
+    let bitboards = entry.to_bitboards();
+    let phase = [0, 1, 1, 2, 4, 0]
+        .iter()
+        .enumerate()
+        .map(|(piece, weight)| weight * (bitboards[piece].pop_count() + bitboards[piece + 6].pop_count()) as usize)
+        .sum::<usize>()
+        .min(24);
+
+    match phase {
+        17.. => 0,
+        8..=16 => 1,
+        _ => 2,
+    }
+}
+
+pub fn to_board(entry: &PieceBoard) -> Board {
+    Board::from_datapack(&entry.to_bitboards(), entry.side_to_move)
+}
+
+fn sigmoid(value: f32) -> f32 {
+    1.0 / (1.0 + (-value).exp())
+}
+
+#[derive(Clone, Copy, Default)]
+struct ValueStats {
+    positions: usize,
+    prediction: f64,
+    score: f64,
+    result: f64,
+    score_error: f64,
+    result_error: f64,
+}
+
+impl ValueStats {
+    fn add(&mut self, prediction: f32, score: f32, result: f32) {
+        self.positions += 1;
+        self.prediction += f64::from(prediction);
+        self.score += f64::from(score);
+        self.result += f64::from(result);
+        self.score_error += f64::from((prediction - score).powi(2));
+        self.result_error += f64::from((prediction - result).powi(2));
+    }
+
+    fn mean(&self, value: f64) -> f64 {
+        value / self.positions.max(1) as f64
+    }
+}
+
+struct ValueReport {
+    total: ValueStats,
+    phases: [ValueStats; 3],
+    calibration: Vec<ValueStats>,
+}
+
+fn evaluate_value_net(net: &ValueNetwork, entries: &[PieceBoard], bins: usize) -> ValueReport {
+    let samples = entries
+        .par_iter()
+        .map(|entry| {
+            //Engine evaluation is the raw output scaled by 400, the same scale the training target uses
+            let prediction = sigmoid(net.evaluate(&to_board(entry)));
+            let white_result = f32::from(entry.result + 1) / 2.0;
+            let result = if entry.side_to_move == 0 { white_result } else { 1.0 - white_result };
+            (game_phase(entry), prediction, entry.score, result)
+        })
+        .collect::<Vec<_>>();
+
+    let mut report = ValueReport {
+        total: ValueStats::default(),
+        phases: [ValueStats::default(); 3],
+        calibration: vec![ValueStats::default(); bins],
+    };
+    for (phase, prediction, score, result) in samples {
+        report.total.add(prediction, score, result);
+        report.phases[phase].add(prediction, score, result);
+        report.calibration[((prediction * bins as f32) as usize).min(bins - 1)].add(prediction, score, result);
+    }
+
+    report
+}
+
+fn print_value_report(path: &Path, report: &ValueReport) {
+    println!("Value net {}", path.display());
+    println!("  {:<12} {:>10} {:>11} {:>11}", "Phase", "Positions", "MSE score", "MSE result");
+    let rows = std::iter::once(("all", &report.total)).chain(PHASES.iter().copied().zip(report.phases.iter()));
+    for (name, stats) in rows {
+        println!(
+            "  {:<12} {:>10} {:>11.5} {:>11.5}",
+            name,
+            stats.positions,
+            stats.mean(stats.score_error),
+            stats.mean(stats.result_error)
+        );
+    }
+
+    println!("\n  Calibration");
+    println!(
+        "  {:<12} {:>10} {:>11} {:>11} {:>11}",
+        "Predicted", "Positions", "Mean pred", "Mean score", "Mean result"
+    );
+    let bins = report.calibration.len();
+    for (index, stats) in report.calibration.iter().enumerate() {
+        if stats.positions == 0 {
+            continue;
+        }
+
+        let range = format!("{:.2}-{:.2}", index as f32 / bins as f32, (index + 1) as f32 / bins as f32);
+        println!(
+            "  {:<12} {:>10} {:>11.3} {:>11.3} {:>11.3}",
+            range,
+            stats.positions,
+            stats.mean(stats.prediction),
+            stats.mean(stats.score),
+            stats.mean(stats.result)
+        );
+    }
+    println!();
+}
+
+#[derive(Clone, Copy, Default)]
+struct PolicyStats {
+    positions: usize,
+    cross_entropy: f64,
+    top1: usize,
+    top3: usize,
+}
+
+impl PolicyStats {
+    fn merge(&mut self, other: &Self) {
+        self.positions += other.positions;
+        self.cross_entropy += other.cross_entropy;
+        self.top1 += other.top1;
+        self.top3 += other.top3;
+    }
+
+    fn accuracy(&self, hits: usize) -> f64 {
+        hits as f64 / self.positions.max(1) as f64 * 100.0
+    }
+}
+
+struct PolicyReport {
+    total: PolicyStats,
+    phases: [PolicyStats; 3],
+    move_types: [PolicyStats; 4],
+}
+
+//Cross-entropy of the softmax of policy outputs against the visit distribution, and whether the
+//most visited move is among the best one or three moves of the net. Move type is the one of the
+//most visited move
+fn evaluate_policy_entry(net: &PolicyNetwork, entry: &ChessPolicyData) -> Option<(PolicyStats, usize)> {
+    let moves = &entry.moves[..usize::from(entry.board.num)];
+    let total_visits = moves.iter().map(|info| f32::from(info.visits)).sum::<f32>();
+    if total_visits == 0.0 {
+        return None;
+    }
+
+    let board = to_board(&entry.board);
+    let inputs = Features::policy_inputs(&board);
+    let threats = Features::policy_threats(&board);
+
+    let outputs =
+        moves.iter().map(|info| net.evaluate(&board, &Move::from_raw(info.mv), &inputs, threats)).collect::<Vec<_>>();
+    let max = outputs.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exp_sum = outputs.iter().map(|output| (output - max).exp()).sum::<f32>();
+
+    let cross_entropy = moves
+        .iter()
+        .zip(&outputs)
+        .map(|(info, output)| {
+            let probability = ((output - max).exp() / exp_sum).max(f32::MIN_POSITIVE);
+            -f32::from(info.visits) / total_visits * probability.ln()
+        })
+        .sum::<f32>();
+
+    let best_index = (0..moves.len()).max_by_key(|&index| moves[index].visits).unwrap_or_default();
+    //Ties count against the net, so a net giving every move the same output isn't always right
+    let rank = (0..outputs.len()).filter(|&index| index != best_index && outputs[index] >= outputs[best_index]).count();
+
+    let best_move = Move::from_raw(moves[best_index].mv);
+    let move_type = if best_move.is_king_castle() || best_move.is_queen_castle() {
+        3
+    } else if best_move.is_promotion() {
+        2
+    } else if best_move.is_capture() {
+        1
+    } else {
+        0
+    };
+
+    let stats = PolicyStats {
+        positions: 1,
+        cross_entropy: f64::from(cross_entropy),
+        top1: usize::from(rank == 0),
+        top3: usize::from(rank < 3),
+    };
+    Some((stats, move_type))
+}
+
+fn evaluate_policy_net(net: &PolicyNetwork, entries: &[ChessPolicyData]) -> PolicyReport {
+    let samples = entries
+        .par_iter()
+        .filter_map(|entry| {
+            evaluate_policy_entry(net, entry).map(|(stats, move_type)| (game_phase(&entry.board), stats, move_type))
+        })
+        .collect::<Vec<_>>();
+
+    let mut report = PolicyReport {
+        total: PolicyStats::default(),
+        phases: [PolicyStats::default(); 3],
+        move_types: [PolicyStats::default(); 4],
+    };
+    for (phase, stats, move_type) in samples {
+        report.total.merge(&stats);
+        report.phases[phase].merge(&stats);
+        report.move_types[move_type].merge(&stats);
+    }
+
+    report
+}
+
+fn print_policy_report(path: &Path, report: &PolicyReport) {
+    println!("Policy net {}", path.display());
+    println!("  {:<12} {:>10} {:>14} {:>8} {:>8}", "Phase", "Positions", "Cross-entropy", "Top-1", "Top-3");
+    let rows = std::iter::once(("all", &report.total)).chain(PHASES.iter().copied().zip(report.phases.iter()));
+    for (name, stats) in rows {
+        println!(
+            "  {:<12} {:>10} {:>14.5} {:>7.2}% {:>7.2}%",
+            name,
+            stats.positions,
+            stats.cross_entropy / stats.positions.max(1) as f64,
+            stats.accuracy(stats.top1),
+            stats.accuracy(stats.top3)
+        );
+    }
+
+    println!("\n  {:<12} {:>10} {:>14} {:>8} {:>8}", "Best move", "Positions", "Cross-entropy", "Top-1", "Top-3");
+    for (name, stats) in MOVE_TYPES.iter().zip(report.move_types.iter()) {
+        println!(
+            "  {:<12} {:>10} {:>14.5} {:>7.2}% {:>7.2}%",
+            name,
+            stats.positions,
+            stats.cross_entropy / stats.positions.max(1) as f64,
+            stats.accuracy(stats.top1),
+            stats.accuracy(stats.top3)
+        );
+    }
+    println!();
+}
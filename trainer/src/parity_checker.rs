@@ -0,0 +1,289 @@
+use std::io;
+use std::path::PathBuf;
+
+use datagen::{is_game_file, read_games, ChessMoveInfo, ChessPolicyData, PieceBoard};
+use goober::FeedForwardNetwork;
+use javelin::{Board, Features, Move, PolicyNetwork, ValueNetwork};
+use rand::rngs::StdRng;
+use rand::{FromEntropy, Rng, SeedableRng};
+
+use crate::args::Args;
+use crate::checkpoint::{load, reinterpret};
+use crate::cpu_value_trainer::{self, extract_inputs, load_entries, ValueNet};
+use crate::net_evaluator::{is_policy_file, to_board};
+use crate::policy_data_loader::{PolicyDataLoader, PolicyRecordReader};
+use crate::policy_trainer;
+
+pub const PARITY_HELP: &str = "Usage: trainer parity <data files...> [options]
+
+Checks that the trainer reads positions the same way the engine does. Random records are taken
+from the data, and value inputs, policy inputs, policy subnet indices and network outputs of the
+trainer are compared with the engine. Positions from game files are compared against the board
+the engine had during the game, other records against the board rebuilt from the record.
+
+Options:
+  --samples <n>               Records checked for each net type (default 1000)
+  --value <net>               Value net used to compare outputs (default random weights)
+  --policy <net>              Policy net used to compare outputs (default random weights)
+  --seed <n>                  Seed used to pick records, 0 uses a random seed (default 0)";
+
+pub struct ParitySettings {
+    pub data_paths: Vec<PathBuf>,
+    pub samples: usize,
+    pub value_net: Option<PathBuf>,
+    pub policy_net: Option<PathBuf>,
+    pub seed: u64,
+}
+
+impl ParitySettings {
+    pub fn from_args(args: &Args) -> Result<Self, String> {
+        if args.positional.is_empty() {
+            return Err("Missing <data files> argument".to_string());
+        }
+
+        let value_net = args.get::<String>("value", String::new())?;
+        let policy_net = args.get::<String>("policy", String::new())?;
+
+        Ok(Self {
+            data_paths: args.positional.iter().map(PathBuf::from).collect(),
+            samples: args.get::<usize>("samples", 1000)?.max(1),
+            value_net: Some(value_net).filter(|path| !path.is_empty()).map(PathBuf::from),
+            policy_net: Some(policy_net).filter(|path| !path.is_empty()).map(PathBuf::from),
+            seed: args.get("seed", 0)?,
+        })
+    }
+}
+
+//Outputs are computed by different code in the engine and in goober, so they may differ in
+//summation order
+const OUTPUT_TOLERANCE: f32 = 1e-4;
+const REPORTED_MISMATCHES: usize = 5;
+
+//Returns false when any mismatch was found
+pub fn check(settings: &ParitySettings) -> io::Result<bool> {
+    let mut rng = match settings.seed {
+        0 => StdRng::from_entropy(),
+        seed => StdRng::seed_from_u64(seed),
+    };
+
+    let (value_samples, policy_samples) = sample_records(&settings.data_paths, settings.samples, &mut rng)?;
+    if value_samples.is_empty() && policy_samples.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "No records found"));
+    }
+
+    let value_net = match &settings.value_net {
+        Some(path) => load::<ValueNet>(path)?,
+        None => cpu_value_trainer::rand_init(),
+    };
+    let policy_net = match &settings.policy_net {
+        Some(path) => load::<PolicyNetwork>(path)?,
+        None => policy_trainer::rand_init(),
+    };
+
+    let engine_value_net = reinterpret::<ValueNet, ValueNetwork>(&value_net);
+
+    let mut report = MismatchReport::default();
+    for (board, record) in &value_samples {
+        check_value(board, record, (&value_net, &engine_value_net), &mut report);
+    }
+    for (board, record) in &policy_samples {
+        check_policy(board, record, &policy_net, &mut report);
+    }
+
+    println!("Value positions checked: {}", value_samples.len());
+    println!(" - Input mismatches: {}", report.value_inputs);
+    println!(" - Output mismatches: {} (max difference {})", report.value_outputs, report.value_difference);
+    println!("Policy positions checked: {} ({} moves)", policy_samples.len(), report.policy_moves);
+    println!(" - Input mismatches: {}", report.policy_inputs);
+    println!(" - Subnet index mismatches: {}", report.policy_subnets);
+    println!(" - Output mismatches: {} (max difference {})", report.policy_outputs, report.policy_difference);
+
+    if !report.examples.is_empty() {
+        println!("\nFirst mismatches:");
+        for example in &report.examples {
+            println!(" - {example}");
+        }
+    }
+
+    Ok(report.total() == 0)
+}
+
+#[derive(Default)]
+struct MismatchReport {
+    value_inputs: usize,
+    value_outputs: usize,
+    value_difference: f32,
+    policy_moves: usize,
+    policy_inputs: usize,
+    policy_subnets: usize,
+    policy_outputs: usize,
+    policy_difference: f32,
+    examples: Vec<String>,
+}
+
+impl MismatchReport {
+    fn total(&self) -> usize {
+        self.value_inputs + self.value_outputs + self.policy_inputs + self.policy_subnets + self.policy_outputs
+    }
+
+    fn example(&mut self, message: String) {
+        if self.examples.len() < REPORTED_MISMATCHES {
+            self.examples.push(message);
+        }
+    }
+}
+
+fn check_value(
+    board: &Board,
+    record: &PieceBoard,
+    (net, engine_net): (&ValueNet, &ValueNetwork),
+    report: &mut MismatchReport,
+) {
+    let trainer_inputs = extract_inputs(record);
+    let engine_inputs = Features::value_inputs(board);
+    if sorted(&trainer_inputs) != sorted(&engine_inputs) {
+        report.value_inputs += 1;
+        report.example(format!("value inputs differ in {}", board.get_fen()));
+    }
+
+    let trainer_output = net.out(&trainer_inputs)[0];
+    let engine_output = engine_net.evaluate(board);
+    let difference = (trainer_output - engine_output).abs();
+    report.value_difference = report.value_difference.max(difference);
+    if difference > OUTPUT_TOLERANCE {
+        report.value_outputs += 1;
+        report.example(format!("value output {trainer_output} instead of {engine_output} in {}", board.get_fen()));
+    }
+}
+
+fn check_policy(board: &Board, record: &ChessPolicyData, net: &PolicyNetwork, report: &mut MismatchReport) {
+    let Some((trainer_inputs, trainer_moves)) = PolicyDataLoader::prepare_policy_dataset(&[*record]).pop() else {
+        return;
+    };
+
+    let engine_inputs = Features::policy_inputs(board);
+    if sorted(&trainer_inputs) != sorted(&engine_inputs) {
+        report.policy_inputs += 1;
+        report.example(format!("policy inputs differ in {}", board.get_fen()));
+    }
+
+    let threats = Features::policy_threats(board);
+    for (info, &(from, to, _, see, threat)) in record.moves.iter().zip(&trainer_moves) {
+        let mv = Move::from_raw(info.mv);
+        report.policy_moves += 1;
+
+        let subnets = Features::policy_subnets(board, &mv, threats);
+        if (subnets.from, subnets.to, subnets.see, subnets.threat) != (from, to, see, threat) {
+            report.policy_subnets += 1;
+            report.example(format!(
+                "subnets of {} are ({from}, {to}, see {see}, threat {threat}) instead of ({}, {}, see {}, threat {}) in {}",
+                mv.to_string(),
+                subnets.from,
+                subnets.to,
+                subnets.see,
+                subnets.threat,
+                board.get_fen()
+            ));
+        }
+
+        //Trainer computes outputs from its prepared entry, the same way the loss is computed
+        let trainer_output =
+            net.subnets[from][threat].out(&trainer_inputs).dot(&net.subnets[64 + to][see].out(&trainer_inputs));
+        let engine_output = net.evaluate(board, &mv, &engine_inputs, threats);
+        let difference = (trainer_output - engine_output).abs();
+        report.policy_difference = report.policy_difference.max(difference);
+        if difference > OUTPUT_TOLERANCE {
+            report.policy_outputs += 1;
+            report.example(format!(
+                "policy output of {} is {trainer_output} instead of {engine_output} in {}",
+                mv.to_string(),
+                board.get_fen()
+            ));
+        }
+    }
+}
+
+fn sorted(inputs: &[usize]) -> Vec<usize> {
+    let mut result = inputs.to_vec();
+    result.sort_unstable();
+    result
+}
+
+type ValueSample = (Board, PieceBoard);
+type PolicySample = (Board, ChessPolicyData);
+
+//Reservoir sampling, so every record has the same chance to be picked without keeping all of them
+struct Reservoir<T> {
+    items: Vec<T>,
+    capacity: usize,
+    seen: usize,
+}
+
+impl<T> Reservoir<T> {
+    fn new(capacity: usize) -> Self {
+        Self { items: Vec::with_capacity(capacity), capacity, seen: 0 }
+    }
+
+    fn offer(&mut self, rng: &mut StdRng, item: impl FnOnce() -> T) {
+        self.seen += 1;
+        if self.items.len() < self.capacity {
+            self.items.push(item());
+            return;
+        }
+
+        let index = rng.gen_range(0, self.seen);
+        if index < self.capacity {
+            self.items[index] = item();
+        }
+    }
+}
+
+fn sample_records(
+    paths: &[PathBuf],
+    samples: usize,
+    rng: &mut StdRng,
+) -> io::Result<(Vec<ValueSample>, Vec<PolicySample>)> {
+    let mut value_samples = Reservoir::new(samples);
+    let mut policy_samples = Reservoir::new(samples);
+
+    for path in paths {
+        if is_game_file(path) {
+            //Records are built the same way game expansion builds them, but the board the engine
+            //played on is kept next to them
+            for game in read_games(path)? {
+                let mut board = game.start_board();
+                for (entry, policy) in &game.moves {
+                    let mut piece_board = PieceBoard::from_board(&board);
+                    piece_board.score = f32::from(entry.score) / f32::from(u16::MAX);
+                    piece_board.result = game.header.result;
+                    piece_board.num = entry.num;
+                    value_samples.offer(rng, || (board, piece_board));
+
+                    if !policy.is_empty() && policy.len() <= 104 && entry.num > 0 {
+                        policy_samples.offer(rng, || {
+                            let mut record =
+                                ChessPolicyData { board: piece_board, moves: [ChessMoveInfo::default(); 104] };
+                            record.moves[..policy.len()].copy_from_slice(policy);
+                            (board, record)
+                        });
+                    }
+
+                    board.make_move(Move::from_raw(entry.mv));
+                }
+            }
+        } else if is_policy_file(path) {
+            let mut reader = PolicyRecordReader::new(vec![path.clone()]);
+            while let Some(record) = reader.next()? {
+                if record.board.num > 0 {
+                    policy_samples.offer(rng, || (to_board(&record.board), record));
+                }
+            }
+        } else {
+            for record in load_entries(std::slice::from_ref(path))? {
+                value_samples.offer(rng, || (to_board(&record), record));
+            }
+        }
+    }
+
+    Ok((value_samples.items, policy_samples.items))
+}
@@ -1,52 +1,37 @@
 use colored::Colorize;
-use datagen::ChessPolicyData;
+use datagen::{is_game_file, ChessPolicyData, GameMove, GameRecord};
 use goober::SparseVector;
-use javelin::{Attacks, Bitboard, Board, Move, Side, Square, SEE};
+use javelin::{Bitboard, Board, Features, Move, Side, Square};
+use rand::Rng;
 use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+
+pub type PolicyEntry = (SparseVector, Vec<(usize, usize, f32, usize, usize)>);
 
 #[allow(unused)]
 pub struct PolicyDataLoader;
 #[allow(unused)]
 impl PolicyDataLoader {
-    pub fn prepare_policy_dataset(
-        data: &Vec<ChessPolicyData>,
-    ) -> Vec<(SparseVector, Vec<(usize, usize, f32, usize, usize)>)> {
+    pub fn prepare_policy_dataset(data: &[ChessPolicyData]) -> Vec<PolicyEntry> {
         data.par_iter()
             .filter(|data_entry| data_entry.board.num != 0) // Filter out invalid boards first
             .map(|data_entry| {
-                let converted_bitboards = if data_entry.board.side_to_move == 0 {
-                    convert_to_12_bitboards(data_entry.board.piece_boards)
-                } else {
-                    flip_board(&convert_to_12_bitboards(data_entry.board.piece_boards))
-                };
-
-                let board = Board::from_datapack(&converted_bitboards, data_entry.board.side_to_move);
-                let threat_map = calculate_threats(converted_bitboards);
+                let board = Board::from_datapack(&data_entry.board.to_bitboards(), data_entry.board.side_to_move);
+                let threats = Features::policy_threats(&board);
 
                 let mut total_visits = 0.0;
                 let mut index_results: Vec<(usize, usize, f32, usize, usize)> = Vec::new();
                 for child_index in 0..data_entry.board.num as usize {
                     let child = data_entry.moves[child_index];
-                    let mv = Move::from_raw(child.mv);
-                    let (from_index, to_index) = if data_entry.board.side_to_move == 0 {
-                        (mv.get_from_square().get_value(), mv.get_to_square().get_value())
-                    } else {
-                        (mv.get_from_square().get_value() ^ 56, mv.get_to_square().get_value() ^ 56)
-                    };
+                    let subnets = Features::policy_subnets(&board, &Move::from_raw(child.mv), threats);
 
-                    let see = usize::from(SEE::static_exchange_evaluation(
-                        &board,
-                        Move::from_squares(Square::from_raw(from_index), Square::from_raw(to_index), 0),
-                        -108,
-                    ));
-
-                    index_results.push((
-                        from_index,
-                        to_index,
-                        child.visits as f32,
-                        see,
-                        usize::from((threat_map & (1 << from_index)).is_not_empty()),
-                    ));
+                    index_results.push((subnets.from, subnets.to, child.visits as f32, subnets.see, subnets.threat));
                     total_visits += child.visits as f32;
                 }
 
@@ -54,78 +39,192 @@ impl PolicyDataLoader {
                     *visits /= total_visits;
                 }
 
-                (extract_inputs(converted_bitboards), index_results)
+                (Features::policy_inputs(&board), index_results)
             })
             .collect::<Vec<_>>() // Collect results into a Vec
     }
 }
 
-fn calculate_threats(board: [Bitboard; 12]) -> Bitboard {
-    let mut threats = Bitboard::EMPTY;
+//Streams policy records from disk instead of loading the whole dataset. Records pass through a
+//bounded shuffle buffer, and a background thread prepares the next chunk of inputs while the
+//current one is used for training. First `skip` records of every pass are left out, so they can
+//be used as a validation set
+pub struct PolicyDataStream {
+    receiver: Receiver<(Vec<PolicyEntry>, usize)>,
+    _handle: JoinHandle<()>,
+}
 
-    let king_square = board[5].ls1b_square();
-    let mut occupancy = Bitboard::EMPTY;
-    board.map(|bb| {
-        occupancy |= bb;
-    });
-    occupancy ^= king_square.get_bit();
+impl PolicyDataStream {
+    pub fn new(paths: Vec<PathBuf>, skip: u64, offset: u64, chunk_size: usize, shuffle_buffer: usize) -> Self {
+        //Only one chunk waits in the channel, so at most two prepared chunks are in memory
+        let (sender, receiver) = mpsc::sync_channel(1);
 
-    for rook_square in (board[9] | board[10]).into_iter() {
-        threats |= Attacks::get_rook_attacks_for_square(rook_square, occupancy)
-    }
+        let handle = thread::spawn(move || {
+            let mut rng = rand::thread_rng();
+            let mut reader = PolicyRecordReader::new(paths.clone());
+            reader.skip(skip + offset).expect("Failed to read policy data");
 
-    for bishop_square in (board[8] | board[10]).into_iter() {
-        threats |= Attacks::get_bishop_attacks_for_square(bishop_square, occupancy)
-    }
+            let mut buffer = Vec::with_capacity(shuffle_buffer.max(1));
+            loop {
+                let mut chunk = Vec::with_capacity(chunk_size);
+                while chunk.len() < chunk_size {
+                    let record = match reader.next().expect("Failed to read policy data") {
+                        Some(record) => record,
+                        None => {
+                            reader = PolicyRecordReader::new(paths.clone());
+                            reader.skip(skip).expect("Failed to read policy data");
+                            continue;
+                        }
+                    };
 
-    for king_square in board[11].into_iter() {
-        threats |= Attacks::get_king_attacks_for_square(king_square)
-    }
+                    if buffer.len() < buffer.capacity() {
+                        buffer.push(record);
+                        continue;
+                    }
 
-    for knight_square in board[7].into_iter() {
-        threats |= Attacks::get_knight_attacks_for_square(knight_square)
+                    let index = rng.gen_range(0, buffer.len());
+                    chunk.push(std::mem::replace(&mut buffer[index], record));
+                }
+
+                let record_count = chunk.len();
+                let prepared = PolicyDataLoader::prepare_policy_dataset(&chunk);
+                if sender.send((prepared, record_count)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Self { receiver, _handle: handle }
     }
 
-    for pawn_square in board[6].into_iter() {
-        threats |= Attacks::get_pawn_attacks_for_square(pawn_square, Side::BLACK)
+    //Returns prepared entries together with the number of records they were made from
+    pub fn next_chunk(&self) -> (Vec<PolicyEntry>, usize) {
+        self.receiver.recv().expect("Policy data thread stopped")
     }
+}
 
-    threats
+//Reads policy records from policy files and game files one after another, games are expanded
+//into policy records the same way `Files` does it
+pub struct PolicyRecordReader {
+    paths: Vec<PathBuf>,
+    file_index: usize,
+    source: Option<PolicySource>,
 }
 
-fn convert_to_12_bitboards(board: [Bitboard; 4]) -> [Bitboard; 12] {
-    let mut result = [Bitboard::EMPTY; 12];
-    for square_index in 0..64 {
-        let square = Square::from_raw(square_index);
-        let piece_index: usize = (if board[0].get_bit(square) { 1 } else { 0 }
-            | if board[1].get_bit(square) { 2 } else { 0 }
-            | if board[2].get_bit(square) { 4 } else { 0 })
-            + if board[3].get_bit(square) { 6 } else { 0 };
-        if piece_index == 0 {
-            continue;
+enum PolicySource {
+    Policy(BufReader<File>),
+    Games(BufReader<File>, VecDeque<ChessPolicyData>),
+}
+
+impl PolicyRecordReader {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        Self { paths, file_index: 0, source: None }
+    }
+
+    //Returns None once every file is read
+    pub fn next(&mut self) -> io::Result<Option<ChessPolicyData>> {
+        loop {
+            let source = match &mut self.source {
+                Some(source) => source,
+                None => {
+                    if self.file_index >= self.paths.len() {
+                        return Ok(None);
+                    }
+
+                    let path = &self.paths[self.file_index];
+                    let reader = BufReader::new(File::open(path)?);
+                    self.file_index += 1;
+                    self.source.insert(match is_game_file(path) {
+                        true => PolicySource::Games(reader, VecDeque::new()),
+                        false => PolicySource::Policy(reader),
+                    })
+                }
+            };
+
+            match source {
+                PolicySource::Policy(reader) => {
+                    let mut buffer = [0u8; size_of::<ChessPolicyData>()];
+                    if reader.read_exact(&mut buffer).is_ok() {
+                        return Ok(Some(bytemuck::pod_read_unaligned(&buffer)));
+                    }
+                }
+                PolicySource::Games(reader, queue) => {
+                    if let Some(record) = queue.pop_front() {
+                        return Ok(Some(record));
+                    }
+
+                    match GameRecord::read_from(reader) {
+                        Ok(Some(game)) => {
+                            queue.extend(game.expand().1);
+                            continue;
+                        }
+                        Ok(None) => (),
+                        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => (),
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+
+            self.source = None;
         }
-        result[piece_index - 1].set_bit(square);
     }
-    result
-}
 
-fn extract_inputs(board: [Bitboard; 12]) -> SparseVector {
-    let mut result = SparseVector::with_capacity(32);
-    for piece_index in 0..12 {
-        for square in board[piece_index] {
-            result.push(piece_index * 64 + square.get_value())
+    //Skips records without decoding them where possible, policy files are skipped by seeking
+    pub fn skip(&mut self, mut count: u64) -> io::Result<()> {
+        while count > 0 {
+            if let Some(PolicySource::Policy(reader)) = &mut self.source {
+                let record_size = size_of::<ChessPolicyData>() as u64;
+                let position = reader.stream_position()?;
+                let remaining = (reader.get_ref().metadata()?.len() - position) / record_size;
+                let skipped = remaining.min(count);
+                reader.seek(SeekFrom::Start(position + skipped * record_size))?;
+                count -= skipped;
+
+                if count == 0 {
+                    return Ok(());
+                }
+                self.source = None;
+            }
+
+            if self.next()?.is_none() {
+                return Ok(());
+            }
+            count -= 1;
         }
+
+        Ok(())
     }
-    result
 }
 
-fn flip_board(board: &[Bitboard; 12]) -> [Bitboard; 12] {
-    let mut result = [Bitboard::EMPTY; 12];
-    for piece_index in 0..6 {
-        result[piece_index] = board[piece_index + 6].flip();
-        result[piece_index + 6] = board[piece_index].flip();
+//Number of policy records in the files, games are counted without replaying them
+pub fn count_policy_records(paths: &[PathBuf]) -> io::Result<u64> {
+    let mut result = 0;
+    for path in paths {
+        if !is_game_file(path) {
+            result += path.metadata()?.len() / size_of::<ChessPolicyData>() as u64;
+            continue;
+        }
+
+        let mut reader = BufReader::new(File::open(path)?);
+        loop {
+            match GameRecord::read_from(&mut reader) {
+                Ok(Some(game)) => {
+                    result += game
+                        .moves
+                        .iter()
+                        .filter(|(entry, policy)| {
+                            !policy.is_empty() && policy.len() <= 104 && entry.flags & GameMove::POLICY_FILTERED == 0
+                        })
+                        .count() as u64
+                }
+                Ok(None) => break,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            }
+        }
     }
-    result
+
+    Ok(result)
 }
 
 #[allow(unused)]
@@ -1,100 +1,250 @@
-use std::{io::Write, time::Instant};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-use datagen::Files;
-use goober::{FeedForwardNetwork, OutputLayer, SparseVector};
+use datagen::{shard_paths, Files};
+use goober::{FeedForwardNetwork, OutputLayer};
 use javelin::{PolicyNetwork, SubNet};
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 use rand::Rng;
 
-use crate::policy_data_loader::PolicyDataLoader;
+use crate::args::Args;
+use crate::checkpoint::{boxed_and_zeroed, export, TrainingState};
+use crate::policy_data_loader::{
+    count_policy_records, PolicyDataLoader, PolicyDataStream, PolicyEntry, PolicyRecordReader,
+};
+
+pub const POLICY_HELP: &str = "Usage: trainer policy [data files...] [options]
+
+Trains the policy network on policy and game files. Without data files the policy file and every
+policy or game shard in the data directory are used. Data is streamed from disk, so it doesn't
+have to fit into memory.
+
+Options:
+  --name <id>                 Network name used for exported nets and checkpoints (default policy_008b)
+  --threads <n>               Thread count (default 7)
+  --superbatches <n>          Superbatches to train (default 60)
+  --batch-size <n>            Positions per batch (default 16384)
+  --batches <n>               Batches per superbatch (default 1536)
+  --lr <f>                    Start learning rate (default 0.001)
+  --lr-drop <n>               Superbatches between learning rate drops, 0 disables drops (default 25)
+  --lr-gamma <f>              Learning rate multiplier applied on every drop (default 0.1)
+  --validation <f>            Fraction of positions held out for validation loss (default 0.01)
+  --validation-limit <n>      Maximum number of validation positions (default 100000)
+  --chunk-batches <n>         Batches prepared at once by the loader thread (default 32)
+  --shuffle-buffer <n>        Positions kept in the shuffle buffer (default 500000)
+  --output <dir>              Export and checkpoint directory (default ../../resources/training/checkpoints)
+  --resume <checkpoint>       Checkpoint directory to continue training from";
+
+pub struct PolicySettings {
+    pub data_paths: Vec<PathBuf>,
+    pub name: String,
+    pub threads: usize,
+    pub superbatches: usize,
+    pub batch_size: usize,
+    pub batches_per_superbatch: usize,
+    pub learning_rate: f32,
+    pub lr_drop: usize,
+    pub lr_gamma: f32,
+    pub validation_ratio: f32,
+    pub validation_limit: usize,
+    pub chunk_batches: usize,
+    pub shuffle_buffer: usize,
+    pub output_directory: PathBuf,
+    pub resume: Option<PathBuf>,
+}
+
+impl PolicySettings {
+    pub fn from_args(args: &Args) -> Result<Self, String> {
+        let resume = args.get::<String>("resume", String::new())?;
+        let validation_ratio = args.get::<f32>("validation", 0.01)?;
+        if !(0.0..1.0).contains(&validation_ratio) {
+            return Err("Validation fraction has to be between 0 and 1".to_string());
+        }
 
-const BATCH_SIZE: usize = 16_384;
-const BATCHES_PER_SUPERBATCH: usize = 1536;
-const EXPORT_PATH: &'static str = "../../resources/training/checkpoints/";
+        Ok(Self {
+            data_paths: args.positional.iter().map(PathBuf::from).collect(),
+            name: args.get("name", "policy_008b".to_string())?,
+            threads: args.get::<usize>("threads", 7)?.max(1),
+            superbatches: args.get("superbatches", 60)?,
+            batch_size: args.get::<usize>("batch-size", 16_384)?.max(1),
+            batches_per_superbatch: args.get::<usize>("batches", 1536)?.max(1),
+            learning_rate: args.get("lr", 0.001)?,
+            lr_drop: args.get("lr-drop", 25)?,
+            lr_gamma: args.get("lr-gamma", 0.1)?,
+            validation_ratio,
+            validation_limit: args.get("validation-limit", 100_000)?,
+            chunk_batches: args.get::<usize>("chunk-batches", 32)?.max(1),
+            shuffle_buffer: args.get("shuffle-buffer", 500_000)?,
+            output_directory: PathBuf::from(args.get("output", "../../resources/training/checkpoints".to_string())?),
+            resume: Some(resume).filter(|path| !path.is_empty()).map(PathBuf::from),
+        })
+    }
+}
 
 pub struct PolicyTrainer;
 impl PolicyTrainer {
-    pub fn train(name: &'static str, threads: usize, superbatches: usize, mut learning_rate: f32, lr_drop: usize) {
-        let mut train_data = Files::new();
-        let _ = train_data.load_policy();
-        let mut policy = rand_init();
-        let throughput = superbatches * BATCHES_PER_SUPERBATCH * BATCH_SIZE;
-
-        println!("Network Name: {name}");
-        println!("Export Path: {}", format!("{}{}.net", EXPORT_PATH, name).as_str());
-        println!("Thread Count: {threads}");
-        println!("Loaded Positions: {}", train_data.policy_data.len());
-        println!("Superbatches: {superbatches}");
-        println!("LR Drop: {lr_drop}");
-        println!("Start LR: {learning_rate}");
-        println!("Epochs {:.2}\n", throughput as f64 / train_data.policy_data.len() as f64);
+    pub fn train(settings: &PolicySettings) -> io::Result<()> {
+        let superbatches = settings.superbatches;
+        let batch_size = settings.batch_size;
+        let batches_per_superbatch = settings.batches_per_superbatch;
+
+        let mut data_paths = settings.data_paths.clone();
+        if data_paths.is_empty() {
+            data_paths.push(Path::new(Files::DATA_DIRECTORY).join(Files::POLICY_FILE));
+            data_paths.extend(shard_paths(Path::new(Files::DATA_DIRECTORY), "policy"));
+            data_paths.extend(shard_paths(Path::new(Files::DATA_DIRECTORY), "games"));
+            data_paths.retain(|path| path.exists());
+        }
 
-        let mut momentum = boxed_and_zeroed::<PolicyNetwork>();
-        let mut velocity = boxed_and_zeroed::<PolicyNetwork>();
+        //Validation positions are taken from the start of the data and skipped by the training
+        //stream, so they mostly come from games that are not used for training
+        let total_count = count_policy_records(&data_paths)?;
+        let validation_count =
+            ((total_count as f32 * settings.validation_ratio) as u64).min(settings.validation_limit as u64);
+        let train_count = total_count - validation_count;
+        if train_count == 0 {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "No policy data found"));
+        }
+
+        let mut validation_entries = Vec::with_capacity(validation_count as usize);
+        let mut reader = PolicyRecordReader::new(data_paths.clone());
+        while validation_entries.len() < validation_count as usize {
+            match reader.next()? {
+                Some(record) => validation_entries.push(record),
+                None => break,
+            }
+        }
+        let validation_data = PolicyDataLoader::prepare_policy_dataset(&validation_entries);
+        drop(validation_entries);
+
+        let mut state = match &settings.resume {
+            Some(checkpoint) => TrainingState::<PolicyNetwork>::load(checkpoint)?,
+            None => TrainingState::new(rand_init(), settings.learning_rate),
+        };
+        let throughput = superbatches.saturating_sub(state.superbatch_index) * batches_per_superbatch * batch_size;
+
+        println!("Network Name: {}", settings.name);
+        println!("Export Path: {}", settings.output_directory.join(format!("{}.net", settings.name)).display());
+        println!("Thread Count: {}", settings.threads);
+        println!("Training Positions: {train_count}");
+        println!("Validation Positions: {}", validation_data.len());
+        println!("Superbatches: {superbatches}");
+        println!("LR Drop: {}", settings.lr_drop);
+        println!("Start LR: {}", state.learning_rate);
+        if settings.resume.is_some() {
+            println!("Resumed From Superbatch: {}", state.superbatch_index);
+        }
+        println!("Epochs {:.2}\n", throughput as f64 / train_count as f64);
 
         let mut running_error = 0.0;
-        let mut superbatch_index = 0;
-        let mut batch_index = 0;
-        let mut data_chunk_start_index = 0;
-
-        'training: loop {
-            let data_chunk_end_index = (data_chunk_start_index + 512 * BATCH_SIZE).min(train_data.policy_data.len());
-            let mut policy_data = PolicyDataLoader::prepare_policy_dataset(
-                &&train_data.policy_data[data_chunk_start_index..data_chunk_end_index].to_vec(),
-            );
-            data_chunk_start_index = data_chunk_end_index % train_data.policy_data.len();
+        let mut batch_index = state.superbatch_index * batches_per_superbatch;
+        state.data_index %= train_count as usize;
+
+        //Resumed training continues from the position in the data stored in the checkpoint
+        let stream = PolicyDataStream::new(
+            data_paths,
+            validation_count,
+            state.data_index as u64,
+            settings.chunk_batches * batch_size,
+            settings.shuffle_buffer,
+        );
+
+        while state.superbatch_index < superbatches {
+            let (mut policy_data, record_count) = stream.next_chunk();
+            state.data_index = (state.data_index + record_count) % train_count as usize;
             policy_data.shuffle(&mut thread_rng());
             let timer = Instant::now();
 
-            for (index, batch) in policy_data.chunks(BATCH_SIZE).enumerate() {
+            for (index, batch) in policy_data.chunks(batch_size).enumerate() {
                 let mut grad = boxed_and_zeroed();
-                running_error += gradient_batch(threads, &policy, &mut grad, batch);
+                running_error += gradient_batch(settings.threads, &state.network, &mut grad, batch);
                 let adj = 1.0 / batch.len() as f32;
-                update(&mut policy, &grad, adj, learning_rate, &mut momentum, &mut velocity);
+                update(&mut state.network, &grad, adj, state.learning_rate, &mut state.momentum, &mut state.velocity);
 
                 batch_index += 1;
                 let l: usize = policy_data.len();
                 print!(
-                    "> Superbatch {}/{superbatches} Batch {}/{BATCHES_PER_SUPERBATCH} - {index} - {l} Speed {:.0}\r",
-                    superbatch_index + 1,
-                    batch_index % BATCHES_PER_SUPERBATCH,
-                    (index * BATCH_SIZE) as f32 / timer.elapsed().as_secs_f32()
+                    "> Superbatch {}/{superbatches} Batch {}/{batches_per_superbatch} - {index} - {l} Speed {:.0}\r",
+                    state.superbatch_index + 1,
+                    batch_index % batches_per_superbatch,
+                    (index * batch_size) as f32 / timer.elapsed().as_secs_f32()
                 );
                 let _ = std::io::stdout().flush();
 
-                if batch_index % BATCHES_PER_SUPERBATCH == 0 {
-                    superbatch_index += 1;
-                    println!(
-                        "> Superbatch {superbatch_index}/{superbatches} Running Loss {}",
-                        running_error / (BATCHES_PER_SUPERBATCH * BATCH_SIZE) as f32
+                if batch_index % batches_per_superbatch == 0 {
+                    state.superbatch_index += 1;
+                    print!(
+                        "> Superbatch {}/{superbatches} Running Loss {}",
+                        state.superbatch_index,
+                        running_error / (batches_per_superbatch * batch_size) as f32
                     );
+                    if !validation_data.is_empty() {
+                        print!(
+                            " Validation Loss {}",
+                            validation_loss(settings.threads, &state.network, &validation_data)
+                        );
+                    }
+                    println!();
                     running_error = 0.0;
 
-                    if superbatch_index % lr_drop == 0 {
-                        learning_rate *= 0.1;
-                        println!("Dropping LR to {learning_rate}");
+                    if settings.lr_drop > 0 && state.superbatch_index % settings.lr_drop == 0 {
+                        state.learning_rate *= settings.lr_gamma;
+                        println!("Dropping LR to {}", state.learning_rate);
                     }
 
-                    export(&policy, format!("{}{}-sb{superbatch_index}.net", EXPORT_PATH, name).as_str());
+                    let name = format!("{}-sb{}", settings.name, state.superbatch_index);
+                    export(state.network.as_ref(), &settings.output_directory.join(format!("{name}.net")));
+                    state.save(&settings.output_directory.join(name))?;
 
-                    if superbatch_index == superbatches {
-                        break 'training;
+                    if state.superbatch_index == superbatches {
+                        break;
                     }
                 }
             }
         }
 
-        loop {}
+        Ok(())
     }
 }
 
-fn gradient_batch(
-    threads: usize,
-    policy: &PolicyNetwork,
-    grad: &mut PolicyNetwork,
-    batch: &[(SparseVector, Vec<(usize, usize, f32, usize, usize)>)],
-) -> f32 {
+//Average cross entropy of the network policy against the visit distribution
+fn validation_loss(threads: usize, policy: &PolicyNetwork, data: &[PolicyEntry]) -> f32 {
+    let size = (data.len() / threads).max(1);
+
+    let error = std::thread::scope(|s| {
+        data.chunks(size)
+            .map(|chunk| s.spawn(move || chunk.iter().map(|entry| entry_loss(entry, policy)).sum::<f32>()))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|p| p.join().unwrap())
+            .sum::<f32>()
+    });
+
+    error / data.len() as f32
+}
+
+fn entry_loss((entry_input, entry_moves): &PolicyEntry, policy: &PolicyNetwork) -> f32 {
+    let policies = entry_moves
+        .iter()
+        .map(|&(from_index, to_index, expected_policy, see, threat)| {
+            let from_out = policy.subnets[from_index][threat].out(entry_input);
+            let to_out = policy.subnets[64 + to_index][see].out(entry_input);
+            (from_out.dot(&to_out), expected_policy)
+        })
+        .collect::<Vec<_>>();
+
+    let max = policies.iter().fold(f32::NEG_INFINITY, |max, &(policy_value, _)| max.max(policy_value));
+    let total = policies.iter().map(|&(policy_value, _)| (policy_value - max).exp()).sum::<f32>();
+
+    policies
+        .iter()
+        .map(|&(policy_value, expected_policy)| -expected_policy * ((policy_value - max).exp() / total).ln())
+        .sum()
+}
+
+fn gradient_batch(threads: usize, policy: &PolicyNetwork, grad: &mut PolicyNetwork, batch: &[PolicyEntry]) -> f32 {
     let size = (batch.len() / threads).max(1);
     let mut errors = vec![0.0; threads];
 
@@ -142,7 +292,7 @@ fn update(
 }
 
 fn update_single_grad(
-    (entry_input, entry_moves): &(SparseVector, Vec<(usize, usize, f32, usize, usize)>),
+    (entry_input, entry_moves): &PolicyEntry,
     policy: &PolicyNetwork,
     grad: &mut PolicyNetwork,
     error: &mut f32,
@@ -189,7 +339,7 @@ fn update_single_grad(
     }
 }
 
-fn rand_init() -> Box<PolicyNetwork> {
+pub fn rand_init() -> Box<PolicyNetwork> {
     let mut policy = boxed_and_zeroed::<PolicyNetwork>();
 
     let mut rng = rand::thread_rng();
@@ -209,26 +359,3 @@ fn add_without_explicit_lifetime(lhs: &mut PolicyNetwork, rhs: &PolicyNetwork) {
         }
     }
 }
-
-fn boxed_and_zeroed<T>() -> Box<T> {
-    unsafe {
-        let layout = std::alloc::Layout::new::<T>();
-        let ptr = std::alloc::alloc_zeroed(layout);
-        if ptr.is_null() {
-            std::alloc::handle_alloc_error(layout);
-        }
-        Box::from_raw(ptr.cast())
-    }
-}
-
-fn export(net: &Box<PolicyNetwork>, path: &str) {
-    let size = std::mem::size_of::<PolicyNetwork>();
-
-    let mut file = std::fs::File::create(path).unwrap();
-
-    unsafe {
-        let slice: *const u8 = std::slice::from_ref(net.as_ref()).as_ptr().cast();
-        let struct_bytes: &[u8] = std::slice::from_raw_parts(slice, size);
-        file.write_all(struct_bytes).expect("Failed to write data!");
-    }
-}
@@ -0,0 +1,319 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use datagen::PieceBoard;
+use javelin::{Pesto, PestoWeights, Side};
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+use rayon::prelude::*;
+
+use crate::args::Args;
+use crate::cpu_value_trainer::load_entries;
+use crate::net_evaluator::to_board;
+
+pub const TEXEL_HELP: &str = "Usage: trainer texel <data files...> [options]
+
+Tunes PeSTO piece values and tables to value data with Texel's method. Evaluation is tapered the
+same way the engine does it and mapped to expected score with the engine's eval scale of 400, so
+tuned tables can replace the value network with `setoption name PestoEval value true`. Tuning
+starts from the tables in `src/eval/pesto.rs` and writes new ones in the same format, ready to be
+pasted over them.
+
+Options:
+  --epochs <n>                Full passes over the data, one optimizer step each (default 500)
+  --lr <f>                    Adam learning rate in centipawns (default 1.0)
+  --wdl <f>                   Weight of the game result in the target (default 0.5)
+  --limit <n>                 Maximum number of positions used for tuning, 0 uses all (default 0)
+  --holdout <n>               Positions kept out of tuning to report validation loss (default 10000)
+  --report <n>                Epochs between loss reports (default 10)
+  --output <file>             File for the tuned tables (default pesto_tuned.rs)";
+
+pub struct TexelSettings {
+    pub data_paths: Vec<PathBuf>,
+    pub epochs: usize,
+    pub learning_rate: f32,
+    pub wdl: f32,
+    pub limit: usize,
+    pub holdout: usize,
+    pub report: usize,
+    pub output: PathBuf,
+}
+
+impl TexelSettings {
+    pub fn from_args(args: &Args) -> Result<Self, String> {
+        if args.positional.is_empty() {
+            return Err("Missing <data files> argument".to_string());
+        }
+
+        Ok(Self {
+            data_paths: args.positional.iter().map(PathBuf::from).collect(),
+            epochs: args.get("epochs", 500)?,
+            learning_rate: args.get("lr", 1.0)?,
+            wdl: args.get("wdl", 0.5)?,
+            limit: args.get("limit", 0)?,
+            holdout: args.get("holdout", 10_000)?,
+            report: args.get::<usize>("report", 10)?.max(1),
+            output: PathBuf::from(args.get::<String>("output", "pesto_tuned.rs".to_string())?),
+        })
+    }
+}
+
+const EVAL_SCALE: f32 = 400.0;
+const MAX_PHASE: f32 = Pesto::MAX_PHASE as f32;
+
+//Weights are kept in one array, midgame half first. Each half starts with piece values followed
+//by the tables
+const TABLES_OFFSET: usize = 6;
+const HALF_SIZE: usize = TABLES_OFFSET + 6 * 64;
+const WEIGHT_COUNT: usize = 2 * HALF_SIZE;
+
+type Weights = Vec<f32>;
+
+//Position reduced to what the tapered evaluation reads. Pieces are stored as piece index * 64
+//plus table index, with the top bit set for black pieces
+struct TexelEntry {
+    pieces: [u16; 32],
+    count: u8,
+    phase: u8,
+    target: f32,
+}
+
+const BLACK_PIECE: u16 = 0x8000;
+
+impl TexelEntry {
+    fn new(entry: &PieceBoard, wdl: f32) -> Self {
+        let board = to_board(entry);
+        let mut pieces = [0; 32];
+        let mut count = 0;
+        Pesto::map_pieces(&board, |piece_index, table_index, side| {
+            let black = if side == Side::BLACK { BLACK_PIECE } else { 0 };
+            pieces[count] = (piece_index * 64 + table_index) as u16 | black;
+            count += 1;
+        });
+
+        //Target from white perspective, the same way PeSTO scores the position
+        let white_score = if entry.side_to_move == 0 { entry.score } else { 1.0 - entry.score };
+        let white_result = f32::from(entry.result + 1) / 2.0;
+
+        Self {
+            pieces,
+            count: count as u8,
+            phase: Pesto::phase(&board) as u8,
+            target: wdl * white_result + (1.0 - wdl) * white_score,
+        }
+    }
+
+    //Calls the method with weight index and sign of every midgame and endgame term, together
+    //with the share of the phase the term is weighted by
+    fn map_terms<F: FnMut(usize, f32)>(&self, mut method: F) {
+        let midgame = f32::from(self.phase) / MAX_PHASE;
+        let endgame = 1.0 - midgame;
+
+        for &piece in &self.pieces[..usize::from(self.count)] {
+            let sign = if piece & BLACK_PIECE != 0 { -1.0 } else { 1.0 };
+            let feature = usize::from(piece & !BLACK_PIECE);
+            let piece_index = feature / 64;
+
+            method(piece_index, sign * midgame);
+            method(TABLES_OFFSET + feature, sign * midgame);
+            method(HALF_SIZE + piece_index, sign * endgame);
+            method(HALF_SIZE + TABLES_OFFSET + feature, sign * endgame);
+        }
+    }
+
+    fn evaluate(&self, weights: &Weights) -> f32 {
+        let mut score = 0.0;
+        self.map_terms(|index, coefficient| score += weights[index] * coefficient);
+        score
+    }
+}
+
+pub fn tune(settings: &TexelSettings) -> io::Result<()> {
+    let mut records = load_entries(&settings.data_paths)?;
+    records.shuffle(&mut thread_rng());
+    if settings.limit > 0 {
+        records.truncate(settings.limit + settings.holdout);
+    }
+
+    let validation_size = settings.holdout.min(records.len() / 10);
+    let entries = records.par_iter().map(|record| TexelEntry::new(record, settings.wdl)).collect::<Vec<_>>();
+    let (validation, train) = entries.split_at(validation_size);
+    if train.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "No value data found"));
+    }
+
+    println!("Tuning Positions: {}", train.len());
+    println!("Validation Positions: {}", validation.len());
+    println!("Epochs: {}", settings.epochs);
+    println!("LR: {}", settings.learning_rate);
+    println!("WDL: {}\n", settings.wdl);
+
+    let mut weights = to_weights(&Pesto::weights());
+    let mut optimizer = Adam::new(settings.learning_rate);
+    println!("Initial Loss {} Validation Loss {}", loss(train, &weights), loss(validation, &weights));
+
+    for epoch in 1..=settings.epochs {
+        let (gradient, error) = gradient(train, &weights);
+        optimizer.step(&mut weights, &gradient);
+
+        if epoch % settings.report == 0 || epoch == settings.epochs {
+            print!("> Epoch {epoch}/{} Loss {}", settings.epochs, error / train.len() as f32);
+            if !validation.is_empty() {
+                print!(" Validation Loss {}", loss(validation, &weights));
+            }
+            println!();
+        }
+    }
+
+    let tuned = from_weights(&weights);
+    println!("\nMidgame piece values: {:?}", tuned.midgame_piece_values);
+    println!("Endgame piece values: {:?}", tuned.endgame_piece_values);
+
+    fs::write(&settings.output, format_weights(&tuned))?;
+    println!("Tuned tables written to {}", settings.output.display());
+    Ok(())
+}
+
+fn sigmoid(value: f32) -> f32 {
+    1.0 / (1.0 + (-value).exp())
+}
+
+fn loss(entries: &[TexelEntry], weights: &Weights) -> f32 {
+    if entries.is_empty() {
+        return 0.0;
+    }
+
+    let error = entries
+        .par_iter()
+        .map(|entry| (sigmoid(entry.evaluate(weights) / EVAL_SCALE) - entry.target).powi(2))
+        .sum::<f32>();
+    error / entries.len() as f32
+}
+
+//Gradient of the summed sigmoid MSE, together with the summed error
+fn gradient(entries: &[TexelEntry], weights: &Weights) -> (Weights, f32) {
+    entries
+        .par_chunks(4096)
+        .map(|chunk| {
+            let mut gradient = vec![0.0; WEIGHT_COUNT];
+            let mut error = 0.0;
+            for entry in chunk {
+                let prediction = sigmoid(entry.evaluate(weights) / EVAL_SCALE);
+                let difference = prediction - entry.target;
+                error += difference.powi(2);
+
+                let output_gradient = 2.0 * difference * prediction * (1.0 - prediction) / EVAL_SCALE;
+                entry.map_terms(|index, coefficient| gradient[index] += output_gradient * coefficient);
+            }
+            (gradient, error)
+        })
+        .reduce(
+            || (vec![0.0; WEIGHT_COUNT], 0.0),
+            |(mut gradient, error), (other, other_error)| {
+                gradient.iter_mut().zip(&other).for_each(|(value, other)| *value += other);
+                (gradient, error + other_error)
+            },
+        )
+}
+
+struct Adam {
+    learning_rate: f32,
+    momentum: Weights,
+    velocity: Weights,
+    step: i32,
+}
+
+impl Adam {
+    const BETA1: f32 = 0.9;
+    const BETA2: f32 = 0.999;
+    const EPSILON: f32 = 1e-8;
+
+    fn new(learning_rate: f32) -> Self {
+        Self { learning_rate, momentum: vec![0.0; WEIGHT_COUNT], velocity: vec![0.0; WEIGHT_COUNT], step: 0 }
+    }
+
+    //Scale of the gradient doesn't matter to Adam, so the summed gradient is used as it is
+    fn step(&mut self, weights: &mut Weights, gradient: &Weights) {
+        self.step += 1;
+        let momentum_correction = 1.0 - Self::BETA1.powi(self.step);
+        let velocity_correction = 1.0 - Self::BETA2.powi(self.step);
+
+        for index in 0..WEIGHT_COUNT {
+            self.momentum[index] = Self::BETA1 * self.momentum[index] + (1.0 - Self::BETA1) * gradient[index];
+            self.velocity[index] = Self::BETA2 * self.velocity[index] + (1.0 - Self::BETA2) * gradient[index].powi(2);
+
+            let momentum = self.momentum[index] / momentum_correction;
+            let velocity = self.velocity[index] / velocity_correction;
+            weights[index] -= self.learning_rate * momentum / (velocity.sqrt() + Self::EPSILON);
+        }
+    }
+}
+
+fn to_weights(pesto: &PestoWeights) -> Weights {
+    let half = |values: &[i32; 6], tables: &[[i32; 64]; 6]| {
+        values.iter().chain(tables.iter().flatten()).map(|&value| value as f32).collect::<Vec<f32>>()
+    };
+
+    let mut weights = half(&pesto.midgame_piece_values, &pesto.midgame_tables);
+    weights.extend(half(&pesto.endgame_piece_values, &pesto.endgame_tables));
+    weights
+}
+
+fn from_weights(weights: &Weights) -> PestoWeights {
+    let mut result = PestoWeights {
+        midgame_piece_values: [0; 6],
+        endgame_piece_values: [0; 6],
+        midgame_tables: [[0; 64]; 6],
+        endgame_tables: [[0; 64]; 6],
+    };
+
+    for (half, (values, tables)) in [
+        (&mut result.midgame_piece_values, &mut result.midgame_tables),
+        (&mut result.endgame_piece_values, &mut result.endgame_tables),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let weights = &weights[half * HALF_SIZE..(half + 1) * HALF_SIZE];
+        for (value, weight) in values.iter_mut().zip(weights) {
+            *value = weight.round() as i32;
+        }
+
+        for (value, weight) in tables.iter_mut().flatten().zip(&weights[TABLES_OFFSET..]) {
+            *value = weight.round() as i32;
+        }
+    }
+
+    //Pawns never stand on the first and last rank, so these entries are not tuned and stay at 0
+    for tables in [&mut result.midgame_tables, &mut result.endgame_tables] {
+        tables[0][..8].fill(0);
+        tables[0][56..].fill(0);
+    }
+
+    result
+}
+
+//Constants named the same way as in `src/eval/pesto.rs`
+fn format_weights(weights: &PestoWeights) -> String {
+    const PIECES: [&str; 6] = ["PAWN", "KNIGHT", "BISHOP", "ROOK", "QUEEN", "KING"];
+    let join = |values: &[i32]| values.iter().map(i32::to_string).collect::<Vec<String>>().join(", ");
+
+    let mut result = format!(
+        "const MIDGAME_PIECE_VALUE: [i32; 6] = [{}];\nconst ENDGAME_PIECE_VALUE: [i32; 6] = [{}];\n",
+        join(&weights.midgame_piece_values),
+        join(&weights.endgame_piece_values)
+    );
+
+    for (index, piece) in PIECES.iter().enumerate() {
+        for (phase, tables) in [("MIDGAME", &weights.midgame_tables), ("ENDGAME", &weights.endgame_tables)] {
+            result.push_str(&format!("\nconst {phase}_{piece}_TABLE: [i32; 64] = [\n"));
+            for rank in tables[index].chunks(8) {
+                result.push_str(&format!("    {},\n", join(rank)));
+            }
+            result.push_str("];\n");
+        }
+    }
+
+    result
+}
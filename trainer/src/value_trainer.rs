@@ -1,8 +1,88 @@
-use bullet::{inputs, lr, optimiser, outputs, wdl, LocalSettings, Loss, TrainerBuilder, TrainingSchedule};
+#[cfg(feature = "bullet")]
+use bullet::{inputs, lr, optimiser, outputs, wdl, LocalSettings, Loss, TestDataset, TrainerBuilder, TrainingSchedule};
+use std::path::Path;
 
+use crate::args::Args;
+
+pub const VALUE_HELP: &str = "Usage: trainer value [data files...] [options]
+
+Trains the value network with bullet. Without data files ../../resources/data/bullet_data.data is used.
+
+`trainer value-cpu` takes the same options but trains on the CPU. It reads value and game files
+instead of bullet data, uses the data directory when no files are given, and resumes from its own
+checkpoint directories.
+
+Options:
+  --name <id>                 Network id used for checkpoint names (default value_11)
+  --threads <n>               Thread count (default 7)
+  --superbatches <n>          Last superbatch to train (default 80)
+  --batch-size <n>            Positions per batch (default 16384)
+  --batches <n>               Batches per superbatch (default 6104)
+  --lr <f>                    Start learning rate (default 0.001)
+  --lr-gamma <f>              Learning rate multiplier applied every lr step (default 0.1)
+  --lr-step <n>               Superbatches between learning rate drops (default 30)
+  --wdl <f>                   Weight of the game result in the target (default 0.7)
+  --save-rate <n>             Superbatches between checkpoints (default 10)
+  --output <dir>              Checkpoint directory (default ../../resources/training/checkpoints)
+  --validation <file>         Bullet data file used to report validation loss every superbatch
+  --resume <checkpoint>       Checkpoint directory to continue from, optimizer state included
+  --start <n>                 Superbatch to start from (default 1, or the one after the resumed checkpoint)";
+
+pub struct ValueSettings {
+    pub name: String,
+    pub threads: usize,
+    pub superbatches: usize,
+    pub batch_size: usize,
+    pub batches_per_superbatch: usize,
+    pub learning_rate: f32,
+    pub lr_gamma: f32,
+    pub lr_step: usize,
+    pub wdl: f32,
+    pub save_rate: usize,
+    pub data_paths: Vec<String>,
+    pub output_directory: String,
+    pub validation: Option<String>,
+    pub resume: Option<String>,
+    pub start_superbatch: usize,
+}
+
+impl ValueSettings {
+    pub fn from_args(args: &Args) -> Result<Self, String> {
+        let resume = args.get::<String>("resume", String::new())?;
+        let validation = args.get::<String>("validation", String::new())?;
+
+        //Bullet names checkpoints `{net_id}-{superbatch}`, so resumed training continues after it
+        let resumed_superbatch = Path::new(&resume)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.rsplit('-').next())
+            .and_then(|superbatch| superbatch.parse::<usize>().ok());
+
+        Ok(Self {
+            name: args.get("name", "value_11".to_string())?,
+            threads: args.get("threads", 7)?,
+            superbatches: args.get("superbatches", 80)?,
+            batch_size: args.get("batch-size", 16_384)?,
+            batches_per_superbatch: args.get("batches", 6104)?,
+            learning_rate: args.get("lr", 0.001)?,
+            lr_gamma: args.get("lr-gamma", 0.1)?,
+            lr_step: args.get("lr-step", 30)?,
+            wdl: args.get("wdl", 0.7)?,
+            save_rate: args.get("save-rate", 10)?,
+            data_paths: args.positional.clone(),
+            output_directory: args.get("output", "../../resources/training/checkpoints".to_string())?,
+            validation: Some(validation).filter(|path| !path.is_empty()),
+            resume: Some(resume).filter(|path| !path.is_empty()),
+            start_superbatch: args.get("start", resumed_superbatch.map_or(1, |superbatch| superbatch + 1))?,
+        })
+    }
+}
+
+#[cfg(feature = "bullet")]
 pub struct ValueTrainer;
+#[cfg(feature = "bullet")]
 impl ValueTrainer {
-    pub fn run() {
+    pub fn run(settings: &ValueSettings) {
         let mut trainer = TrainerBuilder::default()
         .optimiser(optimiser::AdamW)
         .single_perspective()
@@ -15,22 +95,28 @@ impl ValueTrainer {
         .add_layer(1)
         .build();
 
+        //Bullet checkpoints store optimizer state next to the weights, so loading one restores
+        //Adam momentum and velocity as well
+        if let Some(checkpoint) = &settings.resume {
+            trainer.load_from_checkpoint(checkpoint);
+        }
+
         let schedule = TrainingSchedule {
-            net_id: "value_11".to_string(),
+            net_id: settings.name.clone(),
             eval_scale: 400.0,
             ft_regularisation: 0.0,
-            batch_size: 16_384,
-            batches_per_superbatch: 6104,
-            start_superbatch: 1,
-            end_superbatch: 80,
-            wdl_scheduler: wdl::ConstantWDL { value: 0.7 },
+            batch_size: settings.batch_size,
+            batches_per_superbatch: settings.batches_per_superbatch,
+            start_superbatch: settings.start_superbatch,
+            end_superbatch: settings.superbatches,
+            wdl_scheduler: wdl::ConstantWDL { value: settings.wdl },
             lr_scheduler: lr::StepLR {
-                start: 0.001,
-                gamma: 0.1,
-                step: 30,
+                start: settings.learning_rate,
+                gamma: settings.lr_gamma,
+                step: settings.lr_step,
             },
             loss_function: Loss::SigmoidMSE,
-            save_rate: 10,
+            save_rate: settings.save_rate,
             optimiser_settings: optimiser::AdamWParams {
                 decay: 0.01,
                 beta1: 0.9,
@@ -41,10 +127,13 @@ impl ValueTrainer {
         };
 
         let settings = LocalSettings {
-            threads: 7,
-            data_file_paths: vec!["../../resources/data/bullet_data.data"],
-            test_set: None,
-            output_directory: "../../resources/training/checkpoints",
+            threads: settings.threads,
+            data_file_paths: match settings.data_paths.is_empty() {
+                true => vec!["../../resources/data/bullet_data.data"],
+                false => settings.data_paths.iter().map(String::as_str).collect(),
+            },
+            test_set: settings.validation.as_deref().map(|path| TestDataset { path, freq: 1 }),
+            output_directory: &settings.output_directory,
         };
 
         trainer.run(&schedule, &settings);